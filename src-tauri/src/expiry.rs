@@ -0,0 +1,90 @@
+use crate::otp::{keyword_regex, window_before};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+// Stated lifetimes outside this range are something else ("valid for 30 days")
+const MAX_VALIDITY_SECONDS: u64 = 24 * 60 * 60;
+// How far a phrase like "expires in" may sit from the duration it describes
+const CONTEXT_BEFORE: usize = 40;
+const CONTEXT_AFTER: usize = 25;
+
+/// Words that say a duration is how long the code stays valid, by locale
+const VALIDITY_KEYWORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "expires?",
+            "expiring",
+            "expiration",
+            "valid",
+            "good for",
+            // Only about the code when it says what to do in time: "we'll
+            // reply within 2 hours" is not a lifetime
+            r"(?:use|enter|code)(?:\W+\w+){0,2}\W+within",
+            "active for",
+        ],
+    ),
+    (
+        "de",
+        &["gültig", "läuft", "abläuft", "verfällt", "innerhalb"],
+    ),
+    ("es", &["válid[oa]", "caduca", "expira", "vence", "durante"]),
+    ("fr", &["valable", "valide", "expire", "pendant"]),
+    ("pt", &["válid[oa]", "expira", "vence"]),
+    ("ja", &["有効", "以内に(?:入力|ご?使用|ご?利用)", "期限"]),
+    (
+        "zh",
+        &[
+            "有效",
+            "内(?:输入|使用|填写)",
+            "內(?:輸入|使用|填寫)",
+            "过期",
+            "過期",
+            "失效",
+        ],
+    ),
+];
+
+lazy_static! {
+    // Latin units need a word boundary so "10 minutes" isn't read as
+    // "10 min"; CJK units run straight into the next word
+    static ref DURATION_RE: Regex = Regex::new(
+        r"(?i)(?-u:\b)(?P<amount>[0-9]{1,4})\s*(?:(?P<latin>seconds?|secs?|minutes?|mins?|hours?|hrs?|sekunden|minuten|stunden?|segundos?|minutos?|horas?|secondes?|heures?)\b|(?P<cjk>秒|分钟|分鐘|分間|分|小时|小時|時間))"
+    )
+    .expect("Duration regex should be valid");
+    static ref VALIDITY_RE: Regex = keyword_regex(VALIDITY_KEYWORDS);
+}
+
+/// How long the message says its code stays valid, in seconds: "expires in
+/// 10 minutes", "valid for 5 min", "gültig für 15 Minuten", "10分钟内有效".
+/// Durations with no validity wording nearby are ignored.
+pub fn stated_validity(text: &str) -> Option<u64> {
+    DURATION_RE.captures_iter(text).find_map(|caps| {
+        let m = caps.get(0)?;
+        let before = window_before(text, m.start(), CONTEXT_BEFORE);
+        let after: String = text[m.end()..].chars().take(CONTEXT_AFTER).collect();
+        if !VALIDITY_RE.is_match(before) && !VALIDITY_RE.is_match(&after) {
+            return None;
+        }
+
+        let amount: u64 = caps["amount"].parse().ok()?;
+        let unit = caps
+            .name("latin")
+            .or_else(|| caps.name("cjk"))?
+            .as_str()
+            .to_lowercase();
+        let seconds = amount * unit_seconds(&unit);
+        (1..=MAX_VALIDITY_SECONDS)
+            .contains(&seconds)
+            .then_some(seconds)
+    })
+}
+
+fn unit_seconds(unit: &str) -> u64 {
+    match unit {
+        "小时" | "小時" | "時間" => 60 * 60,
+        u if u.starts_with('h') || u.starts_with("stund") => 60 * 60,
+        u if u.starts_with('m') || u.starts_with('分') => 60,
+        _ => 1,
+    }
+}
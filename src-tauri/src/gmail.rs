@@ -1,22 +1,25 @@
 use crate::keychain::KeychainManager;
+use crate::mail_source::{EmailMessage, MailSource, RATE_LIMIT_ERROR};
+use crate::mime::{self, BodyText};
+use crate::oauth_server::{PendingAuth, Pkce};
+use crate::sender_auth;
+use async_trait::async_trait;
 use chrono::Utc;
 use reqwest::{Client, StatusCode};
-use serde::Deserialize;
-use sha2::{Digest, Sha256};
-
-pub const RATE_LIMIT_ERROR: &str = "RATE_LIMIT_ERROR";
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 
 const GMAIL_SCOPES: &[&str] = &["https://www.googleapis.com/auth/gmail.readonly"];
-const OAUTH_REDIRECT_URI: &str = "http://localhost:8234/callback";
-
-#[derive(Debug, Clone)]
-pub struct EmailMessage {
-    pub id: String,
-    pub from: String,
-    pub subject: String,
-    pub snippet: String,
-    pub body: String,
-}
+const GMAIL_API_URL: &str = "https://gmail.googleapis.com/gmail/v1/users/me";
+const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const SYNC_STATE_FILE: &str = "gmail_sync.json";
+const ACCOUNTS_FILE: &str = "gmail_accounts.json";
+// Returned by history.list when the start ID is too old and a full sync is needed
+const HISTORY_EXPIRED_ERROR: &str = "HISTORY_EXPIRED";
 
 #[derive(Deserialize)]
 struct TokenResponse {
@@ -35,22 +38,75 @@ struct Message {
     id: String,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Profile {
+    #[serde(default)]
+    email_address: String,
+    history_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HistoryListResponse {
+    #[serde(default)]
+    history: Vec<HistoryRecord>,
+    history_id: Option<String>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HistoryRecord {
+    #[serde(default)]
+    messages_added: Vec<HistoryMessageAdded>,
+}
+
+#[derive(Deserialize)]
+struct HistoryMessageAdded {
+    message: HistoryMessage,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HistoryMessage {
+    id: String,
+    #[serde(default)]
+    label_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct SyncState {
+    // Account address -> last synced historyId
+    #[serde(default)]
+    history_ids: HashMap<String, String>,
+}
+
 #[derive(Deserialize)]
 struct MessageDetail {
     #[serde(default)]
     payload: Option<Payload>,
+    // Milliseconds since the epoch, as a string
+    #[serde(default, rename = "internalDate")]
+    internal_date: Option<String>,
     #[serde(default)]
     snippet: String,
 }
 
+// A MIME part as the Gmail API returns it; the top-level payload is one too
 #[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
 struct Payload {
+    #[serde(default)]
+    mime_type: String,
+    #[serde(default)]
+    filename: String,
     #[serde(default)]
     headers: Vec<Header>,
     #[serde(default)]
     body: Option<BodyPart>,
     #[serde(default)]
-    parts: Option<Vec<Part>>,
+    parts: Option<Vec<Payload>>,
 }
 
 #[derive(Deserialize)]
@@ -65,19 +121,23 @@ struct BodyPart {
     data: String,
 }
 
-#[derive(Deserialize, Default)]
-struct Part {
-    #[serde(default)]
-    body: Option<BodyPart>,
-    #[serde(default)]
-    parts: Option<Vec<Part>>,
-}
-
 pub struct GmailClient {
-    authenticated: bool,
+    authenticated: AtomicBool,
+    // Address of the signed-in account; None until sign-in completes
+    account: Option<String>,
     client_id: String,
+    // Optional: Google "Desktop app" clients can sign in with PKCE alone
     client_secret: String,
+    api_base_url: String,
+    token_url: String,
     http_client: Client,
+    pending_auth: Mutex<Option<PendingAuth>>,
+    // Cached (token, expiry) so every API call doesn't hit the keychain
+    access_token: Mutex<Option<(String, i64)>>,
+    // Last Gmail historyId we synced up to; None forces a full list
+    history_id: Mutex<Option<String>>,
+    // historyId reached by the last listing, saved once its messages are fetched
+    pending_history_id: Mutex<Option<String>>,
 }
 
 impl GmailClient {
@@ -86,105 +146,112 @@ impl GmailClient {
         let client_secret =
             std::env::var("GOOGLE_CLIENT_SECRET").unwrap_or_else(|_| "".to_string());
 
-        if client_id.is_empty() || client_secret.is_empty() {
-            println!("WARNING: GOOGLE_CLIENT_ID or GOOGLE_CLIENT_SECRET not set. Authentication will fail.");
+        let api_base_url =
+            std::env::var("OTPBAR_GMAIL_API_URL").unwrap_or_else(|_| GMAIL_API_URL.to_string());
+        let token_url = std::env::var("OTPBAR_GOOGLE_TOKEN_URL")
+            .unwrap_or_else(|_| GOOGLE_TOKEN_URL.to_string());
+
+        if client_id.is_empty() {
+            println!("WARNING: GOOGLE_CLIENT_ID not set. Authentication will fail.");
         }
 
-        Ok(GmailClient {
-            authenticated: false,
-            client_id,
-            client_secret,
-            http_client: Client::new(),
-        })
+        Ok(Self::with_endpoints(
+            &client_id,
+            &client_secret,
+            &api_base_url,
+            &token_url,
+        ))
     }
 
-    pub fn is_authenticated(&self) -> bool {
-        self.authenticated
+    /// Build a client against explicit endpoints (used for local mocks)
+    pub fn with_endpoints(
+        client_id: &str,
+        client_secret: &str,
+        api_base_url: &str,
+        token_url: &str,
+    ) -> Self {
+        GmailClient {
+            authenticated: AtomicBool::new(false),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            api_base_url: api_base_url.trim_end_matches('/').to_string(),
+            token_url: token_url.to_string(),
+            http_client: Client::new(),
+            pending_auth: Mutex::new(None),
+            access_token: Mutex::new(None),
+            account: None,
+            history_id: Mutex::new(None),
+            pending_history_id: Mutex::new(None),
+        }
     }
 
-    pub fn get_auth_url(&self) -> String {
-        format!(
-            "https://accounts.google.com/o/oauth2/v2/auth?client_id={}&redirect_uri={}&response_type=code&scope={}&prompt=consent&access_type=offline",
-            urlencoding::encode(&self.client_id),
-            urlencoding::encode(OAUTH_REDIRECT_URI),
-            urlencoding::encode(&GMAIL_SCOPES.join(" "))
-        )
+    /// Client for an account that has already signed in
+    pub async fn for_account(account: &str) -> Result<Self, String> {
+        let mut client = Self::new().await?;
+        client.set_account(account);
+        Ok(client)
     }
 
-    pub async fn exchange_code(&mut self, code: &str) -> Result<(), String> {
-        let params = [
-            ("code", code),
-            ("client_id", &self.client_id),
-            ("client_secret", &self.client_secret),
-            ("redirect_uri", OAUTH_REDIRECT_URI),
-            ("grant_type", "authorization_code"),
-        ];
-
-        let resp: TokenResponse = self
-            .http_client
-            .post("https://oauth2.googleapis.com/token")
-            .form(&params)
-            .send()
-            .await
-            .map_err(|e| format!("Token request failed: {}", e))?
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse token response: {}", e))?;
-
-        KeychainManager::set_access_token(&resp.access_token)?;
-
-        let expiry = Utc::now().timestamp() + resp.expires_in.unwrap_or(3600) as i64;
-        KeychainManager::set_token_expiry(expiry)?;
-
-        if let Some(refresh_token) = resp.refresh_token {
-            KeychainManager::set_refresh_token(&refresh_token)?;
-        } else {
-            let existing = KeychainManager::get_refresh_token()?;
-            if existing.is_none() {
-                return Err("No refresh token received".to_string());
-            }
-        }
-
-        self.authenticated = true;
-        log::info!("OAuth exchange successful, user authenticated");
-        Ok(())
+    fn set_account(&mut self, account: &str) {
+        let history_id = load_sync_state().history_ids.get(account).cloned();
+        *self.history_id.lock().unwrap() = history_id;
+        self.account = Some(account.to_string());
     }
 
-    async fn refresh_access_token(&self) -> Result<String, String> {
-        let refresh_token =
-            KeychainManager::get_refresh_token()?.ok_or("No refresh token stored")?;
+    fn account_key(&self) -> Result<&str, String> {
+        self.account
+            .as_deref()
+            .ok_or_else(|| "Gmail account is not signed in".to_string())
+    }
 
-        let params = [
+    async fn request_token_refresh(&self, refresh_token: &str) -> Result<TokenResponse, String> {
+        let mut params = vec![
             ("refresh_token", refresh_token),
-            ("client_id", self.client_id.clone()),
-            ("client_secret", self.client_secret.clone()),
-            ("grant_type", "refresh_token".to_string()),
+            ("client_id", self.client_id.as_str()),
+            ("grant_type", "refresh_token"),
         ];
+        if !self.client_secret.is_empty() {
+            params.push(("client_secret", self.client_secret.as_str()));
+        }
 
-        let resp: TokenResponse = self
-            .http_client
-            .post("https://oauth2.googleapis.com/token")
+        self.http_client
+            .post(&self.token_url)
             .form(&params)
             .send()
             .await
             .map_err(|e| format!("Refresh request failed: {}", e))?
             .json()
             .await
-            .map_err(|e| format!("Failed to parse refresh response: {}", e))?;
+            .map_err(|e| format!("Failed to parse refresh response: {}", e))
+    }
+
+    async fn refresh_access_token(&self) -> Result<String, String> {
+        let account = self.account_key()?;
+        let refresh_token =
+            KeychainManager::get_gmail_refresh_token(account)?.ok_or("No refresh token stored")?;
 
-        let expiry = Utc::now().timestamp() + resp.expires_in.unwrap_or(3600) as i64;
-        KeychainManager::set_access_token(&resp.access_token)?;
-        KeychainManager::set_token_expiry(expiry)?;
+        let resp = self.request_token_refresh(&refresh_token).await?;
+        let expiry = store_tokens(account, &resp)?;
+        *self.access_token.lock().unwrap() = Some((resp.access_token.clone(), expiry));
 
         Ok(resp.access_token)
     }
 
     async fn get_valid_access_token(&self) -> Result<String, String> {
+        let account = self.account_key()?;
+        let now = Utc::now().timestamp();
+
+        if let Some((token, expiry)) = self.access_token.lock().unwrap().clone() {
+            if now < expiry - 60 {
+                return Ok(token);
+            }
+        }
+
         // Check if we have a valid access token
-        if let Ok(Some(token)) = KeychainManager::get_access_token() {
-            if let Ok(Some(expiry)) = KeychainManager::get_token_expiry() {
-                let now = Utc::now().timestamp();
+        if let Ok(Some(token)) = KeychainManager::get_gmail_access_token(account) {
+            if let Ok(Some(expiry)) = KeychainManager::get_gmail_token_expiry(account) {
                 if now < expiry - 60 {
+                    *self.access_token.lock().unwrap() = Some((token.clone(), expiry));
                     return Ok(token);
                 }
             }
@@ -199,7 +266,7 @@ impl GmailClient {
 
         // Make a lightweight API call to verify the token works
         self.http_client
-            .get("https://gmail.googleapis.com/gmail/v1/users/me/profile")
+            .get(format!("{}/profile", self.api_base_url))
             .header("Authorization", format!("Bearer {}", access_token))
             .send()
             .await
@@ -210,16 +277,183 @@ impl GmailClient {
         Ok(())
     }
 
-    pub async fn try_restore_auth(&mut self) -> bool {
-        if self.client_id.is_empty() || self.client_secret.is_empty() {
+    fn set_history_id(&self, history_id: Option<String>) {
+        *self.history_id.lock().unwrap() = history_id.clone();
+
+        let Some(account) = &self.account else {
+            return;
+        };
+        let mut state = load_sync_state();
+        match history_id {
+            Some(history_id) => {
+                state.history_ids.insert(account.clone(), history_id);
+            }
+            None => {
+                state.history_ids.remove(account);
+            }
+        }
+        save_sync_state(&state);
+    }
+
+    async fn get_profile(&self, access_token: &str) -> Result<Profile, String> {
+        let response = self
+            .http_client
+            .get(format!("{}/profile", self.api_base_url))
+            .header("Authorization", format!("Bearer {}", access_token))
+            .send()
+            .await
+            .map_err(|e| format!("Gmail API request failed: {}", e))?;
+
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            return Err(RATE_LIMIT_ERROR.to_string());
+        }
+
+        response
+            .error_for_status()
+            .map_err(|e| format!("Gmail profile request failed: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse profile: {}", e))
+    }
+
+    /// List recent unread messages and record the current historyId so later
+    /// polls only need `history.list`
+    async fn full_sync(&self, access_token: &str) -> Result<Vec<String>, String> {
+        // Read the historyId first so nothing that arrives during the list is missed
+        let history_id = self.get_profile(access_token).await?.history_id;
+
+        let list_url = format!(
+            "{}/messages?q=is%3Aunread%20newer_than:1d&maxResults=25",
+            self.api_base_url
+        );
+
+        let response = self
+            .http_client
+            .get(&list_url)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .send()
+            .await
+            .map_err(|e| format!("Gmail API request failed: {}", e))?;
+
+        // Check for rate limit error (HTTP 429)
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            return Err(RATE_LIMIT_ERROR.to_string());
+        }
+
+        let list_resp: MessageListResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse message list: {}", e))?;
+
+        *self.pending_history_id.lock().unwrap() = Some(history_id);
+
+        Ok(list_resp
+            .messages
+            .unwrap_or_default()
+            .into_iter()
+            .map(|m| m.id)
+            .collect())
+    }
+
+    /// IDs of unread inbox messages added since `start_history_id`
+    async fn sync_history(
+        &self,
+        access_token: &str,
+        start_history_id: &str,
+    ) -> Result<Vec<String>, String> {
+        let mut ids: Vec<String> = Vec::new();
+        let mut latest_history_id = None;
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut url = format!(
+                "{}/history?startHistoryId={}&historyTypes=messageAdded&labelId=INBOX",
+                self.api_base_url,
+                urlencoding::encode(start_history_id)
+            );
+            if let Some(token) = &page_token {
+                url.push_str(&format!("&pageToken={}", urlencoding::encode(token)));
+            }
+
+            let response = self
+                .http_client
+                .get(&url)
+                .header("Authorization", format!("Bearer {}", access_token))
+                .send()
+                .await
+                .map_err(|e| format!("Gmail API request failed: {}", e))?;
+
+            match response.status() {
+                StatusCode::TOO_MANY_REQUESTS => return Err(RATE_LIMIT_ERROR.to_string()),
+                StatusCode::NOT_FOUND => return Err(HISTORY_EXPIRED_ERROR.to_string()),
+                _ => {}
+            }
+
+            let page: HistoryListResponse = response
+                .error_for_status()
+                .map_err(|e| format!("Gmail history request failed: {}", e))?
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse history: {}", e))?;
+
+            for added in page.history.into_iter().flat_map(|h| h.messages_added) {
+                let message = added.message;
+                let unread =
+                    message.label_ids.is_empty() || message.label_ids.iter().any(|l| l == "UNREAD");
+                if unread && !ids.contains(&message.id) {
+                    ids.push(message.id);
+                }
+            }
+
+            if page.history_id.is_some() {
+                latest_history_id = page.history_id;
+            }
+
+            match page.next_page_token {
+                Some(token) => page_token = Some(token),
+                None => break,
+            }
+        }
+
+        *self.pending_history_id.lock().unwrap() = latest_history_id;
+
+        Ok(ids)
+    }
+
+    fn extract_body(&self, payload: &Payload) -> BodyText {
+        let mut text = BodyText::default();
+        collect_body_text(payload, &mut text);
+        text
+    }
+}
+
+#[async_trait]
+impl MailSource for GmailClient {
+    fn name(&self) -> &'static str {
+        "gmail"
+    }
+
+    fn account(&self) -> Option<String> {
+        self.account.clone()
+    }
+
+    fn is_authenticated(&self) -> bool {
+        self.authenticated.load(Ordering::Relaxed)
+    }
+
+    async fn try_restore_auth(&mut self) -> bool {
+        if self.client_id.is_empty() {
             return false;
         }
+        let Some(account) = self.account.clone() else {
+            return false;
+        };
 
-        match KeychainManager::get_refresh_token() {
+        match KeychainManager::get_gmail_refresh_token(&account) {
             Ok(Some(_)) => {
                 match self.validate_credentials().await {
                     Ok(_) => {
-                        self.authenticated = true;
+                        self.authenticated.store(true, Ordering::Relaxed);
                         log::info!("Successfully restored and validated Gmail authentication from keychain");
                         true
                     }
@@ -235,55 +469,97 @@ impl GmailClient {
         }
     }
 
-    pub async fn get_recent_unread(&self) -> Result<Vec<EmailMessage>, String> {
-        let access_token = self.get_valid_access_token().await?;
+    fn get_auth_url(&self, redirect_uri: &str, state: &str) -> Result<String, String> {
+        let pkce = Pkce::generate();
+        let url = format!(
+            "https://accounts.google.com/o/oauth2/v2/auth?client_id={}&redirect_uri={}&response_type=code&scope={}&state={}&code_challenge={}&code_challenge_method=S256&prompt=consent&access_type=offline",
+            urlencoding::encode(&self.client_id),
+            urlencoding::encode(redirect_uri),
+            urlencoding::encode(&GMAIL_SCOPES.join(" ")),
+            urlencoding::encode(state),
+            pkce.challenge
+        );
+        *self.pending_auth.lock().unwrap() = Some(PendingAuth {
+            verifier: pkce.verifier,
+            redirect_uri: redirect_uri.to_string(),
+        });
+        Ok(url)
+    }
 
-        let list_url = "https://gmail.googleapis.com/gmail/v1/users/me/messages?q=is%3Aunread%20newer_than:1d&maxResults=25".to_string();
+    async fn exchange_code(&mut self, code: &str) -> Result<(), String> {
+        let pending = self
+            .pending_auth
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or("Sign-in was not started")?;
 
-        let response = self
+        let mut params = vec![
+            ("code", code),
+            ("client_id", self.client_id.as_str()),
+            ("redirect_uri", pending.redirect_uri.as_str()),
+            ("grant_type", "authorization_code"),
+            ("code_verifier", pending.verifier.as_str()),
+        ];
+        if !self.client_secret.is_empty() {
+            params.push(("client_secret", self.client_secret.as_str()));
+        }
+
+        let resp: TokenResponse = self
             .http_client
-            .get(&list_url)
-            .header("Authorization", format!("Bearer {}", access_token))
+            .post(&self.token_url)
+            .form(&params)
             .send()
             .await
-            .map_err(|e| format!("Gmail API request failed: {}", e))?;
+            .map_err(|e| format!("Token request failed: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse token response: {}", e))?;
 
-        // Check for rate limit error (HTTP 429)
-        if response.status() == StatusCode::TOO_MANY_REQUESTS {
-            return Err(RATE_LIMIT_ERROR.to_string());
+        // Tokens are stored per account, so find out which one signed in
+        let account = self.get_profile(&resp.access_token).await?.email_address;
+        if account.is_empty() {
+            return Err("Failed to determine Gmail account address".to_string());
         }
 
-        let list_resp: MessageListResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse message list: {}", e))?;
+        if resp.refresh_token.is_none() {
+            let existing = KeychainManager::get_gmail_refresh_token(&account).unwrap_or(None);
+            if existing.is_none() {
+                return Err("No refresh token received".to_string());
+            }
+        }
+        let expiry = store_tokens(&account, &resp)?;
+        add_gmail_account(&account);
+
+        self.set_account(&account);
+        *self.access_token.lock().unwrap() = Some((resp.access_token.clone(), expiry));
+        self.authenticated.store(true, Ordering::Relaxed);
+        log::info!("OAuth exchange successful, Gmail account added");
+        Ok(())
+    }
 
-        let messages = list_resp.messages.unwrap_or_default();
-        let mut results = Vec::new();
+    async fn list_new_messages(&self) -> Result<Vec<String>, String> {
+        let access_token = self.get_valid_access_token().await?;
 
-        for msg in messages {
-            match self.fetch_message_detail(&msg.id, &access_token).await {
-                Ok(detail) => results.push(detail),
-                Err(e) => {
-                    // SECURITY: Hash message ID to prevent correlation with Gmail logs
-                    let id_hash = hash_message_id(&msg.id);
-                    log::warn!("Failed to fetch message {}: {}", id_hash, e);
+        self.pending_history_id.lock().unwrap().take();
+        let start_history_id = self.history_id.lock().unwrap().clone();
+        if let Some(start_history_id) = start_history_id {
+            match self.sync_history(&access_token, &start_history_id).await {
+                Ok(ids) => return Ok(ids),
+                Err(e) if e == HISTORY_EXPIRED_ERROR => {
+                    log::info!("Gmail history ID expired, falling back to full sync");
                 }
+                Err(e) => return Err(e),
             }
         }
 
-        Ok(results)
+        self.full_sync(&access_token).await
     }
 
-    async fn fetch_message_detail(
-        &self,
-        msg_id: &str,
-        access_token: &str,
-    ) -> Result<EmailMessage, String> {
-        let url = format!(
-            "https://gmail.googleapis.com/gmail/v1/users/me/messages/{}?format=full",
-            msg_id
-        );
+    async fn fetch_message_detail(&self, msg_id: &str) -> Result<EmailMessage, String> {
+        let access_token = self.get_valid_access_token().await?;
+
+        let url = format!("{}/messages/{}?format=full", self.api_base_url, msg_id);
 
         let resp: MessageDetail = self
             .http_client
@@ -292,6 +568,8 @@ impl GmailClient {
             .send()
             .await
             .map_err(|e| format!("Message fetch failed: {}", e))?
+            .error_for_status()
+            .map_err(|e| format!("Message fetch rejected: {}", e))?
             .json()
             .await
             .map_err(|e| format!("Failed to parse message: {}", e))?;
@@ -309,67 +587,205 @@ impl GmailClient {
             }
         }
 
-        let body = self.extract_body_text(&payload);
+        let body = self.extract_body(&payload);
 
         Ok(EmailMessage {
             id: msg_id.to_string(),
-            from,
             subject,
-            snippet: resp.snippet,
-            body,
+            snippet: mime::decode_html_entities(&resp.snippet),
+            links: body.links(),
+            body: body.into_text(),
+            sent_at: resp.internal_date.and_then(|d| d.parse().ok()),
+            sender_auth: sender_auth::verify_sender(
+                payload
+                    .headers
+                    .iter()
+                    .map(|h| (h.name.as_str(), h.value.as_str())),
+                &from,
+            ),
+            from,
         })
     }
 
-    fn extract_body_text(&self, payload: &Payload) -> String {
-        if let Some(body) = &payload.body {
-            if !body.data.is_empty() {
-                if let Ok(decoded) = base64_url_decode(&body.data) {
-                    if let Ok(text) = String::from_utf8(decoded) {
-                        return text;
-                    }
-                }
-            }
+    fn commit_listing(&self) {
+        let history_id = self.pending_history_id.lock().unwrap().take();
+        if history_id.is_some() {
+            self.set_history_id(history_id);
         }
+    }
 
-        if let Some(parts) = &payload.parts {
-            for part in parts {
-                let text = self.extract_part_text(part);
-                if !text.is_empty() {
-                    return text;
-                }
-            }
+    async fn clear_auth(&self) -> Result<(), String> {
+        *self.access_token.lock().unwrap() = None;
+        if let Some(account) = self.account.clone() {
+            KeychainManager::delete_gmail_credentials(&account)?;
+            self.pending_history_id.lock().unwrap().take();
+            self.set_history_id(None);
+            remove_gmail_account(&account);
         }
+        self.authenticated.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
 
-        String::new()
+/// Save the tokens to the keychain, returning when the access token expires
+fn store_tokens(account: &str, resp: &TokenResponse) -> Result<i64, String> {
+    let expiry = Utc::now().timestamp() + resp.expires_in.unwrap_or(3600) as i64;
+    KeychainManager::set_gmail_access_token(account, &resp.access_token)?;
+    KeychainManager::set_gmail_token_expiry(account, expiry)?;
+    if let Some(refresh_token) = &resp.refresh_token {
+        KeychainManager::set_gmail_refresh_token(account, refresh_token)?;
     }
+    Ok(expiry)
+}
 
-    fn extract_part_text(&self, part: &Part) -> String {
-        if let Some(body) = &part.body {
-            if !body.data.is_empty() {
-                if let Ok(decoded) = base64_url_decode(&body.data) {
-                    if let Ok(text) = String::from_utf8(decoded) {
-                        return text;
-                    }
+/// Move the tokens of a single-account install under the account's address
+/// so it is picked up like any other signed-in account
+pub async fn migrate_legacy_credentials() {
+    let refresh_token = match KeychainManager::get_legacy_gmail_refresh_token() {
+        Ok(Some(token)) => token,
+        _ => return,
+    };
+    let client = match GmailClient::new().await {
+        Ok(client) if !client.client_id.is_empty() => client,
+        _ => return,
+    };
+
+    let migrated = async {
+        let resp = client.request_token_refresh(&refresh_token).await?;
+        let account = client.get_profile(&resp.access_token).await?.email_address;
+        if account.is_empty() {
+            return Err("Failed to determine Gmail account address".to_string());
+        }
+        KeychainManager::set_gmail_refresh_token(&account, &refresh_token)?;
+        store_tokens(&account, &resp)?;
+        add_gmail_account(&account);
+        Ok::<(), String>(())
+    }
+    .await;
+
+    match migrated {
+        Ok(()) => {
+            let _ = KeychainManager::delete_legacy_gmail_credentials();
+            log::info!("Migrated Gmail credentials to per-account storage");
+        }
+        Err(e) => log::warn!("Failed to migrate Gmail credentials: {}", e),
+    }
+}
+
+pub fn get_gmail_accounts_path() -> Result<PathBuf, String> {
+    let mut path = dirs::config_dir().ok_or("Failed to get config directory")?;
+    path.push("otpbar");
+    fs::create_dir_all(&path).map_err(|e| format!("Failed to create config directory: {}", e))?;
+    path.push(ACCOUNTS_FILE);
+    Ok(path)
+}
+
+/// Addresses of every signed-in Gmail account
+pub fn load_gmail_accounts() -> Vec<String> {
+    match get_gmail_accounts_path() {
+        Ok(path) if path.exists() => fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+fn save_gmail_accounts(accounts: &[String]) {
+    match get_gmail_accounts_path() {
+        Ok(path) => match serde_json::to_string_pretty(accounts) {
+            Ok(json) => {
+                if let Err(e) = fs::write(&path, json) {
+                    log::warn!("Failed to save Gmail accounts: {}", e);
                 }
             }
+            Err(e) => {
+                log::warn!("Failed to serialize Gmail accounts: {}", e);
+            }
+        },
+        Err(e) => {
+            log::warn!("Failed to get Gmail accounts path: {}", e);
         }
+    }
+}
+
+fn add_gmail_account(account: &str) {
+    let mut accounts = load_gmail_accounts();
+    if !accounts.iter().any(|a| a == account) {
+        accounts.push(account.to_string());
+        save_gmail_accounts(&accounts);
+    }
+}
+
+fn remove_gmail_account(account: &str) {
+    let mut accounts = load_gmail_accounts();
+    accounts.retain(|a| a != account);
+    save_gmail_accounts(&accounts);
+}
 
-        if let Some(parts) = &part.parts {
-            for part in parts {
-                let text = self.extract_part_text(part);
-                if !text.is_empty() {
-                    return text;
+fn get_sync_state_path() -> Result<PathBuf, String> {
+    let mut path = dirs::config_dir().ok_or("Failed to get config directory")?;
+    path.push("otpbar");
+    fs::create_dir_all(&path).map_err(|e| format!("Failed to create config directory: {}", e))?;
+    path.push(SYNC_STATE_FILE);
+    Ok(path)
+}
+
+fn load_sync_state() -> SyncState {
+    match get_sync_state_path() {
+        Ok(path) if path.exists() => fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default(),
+        _ => SyncState::default(),
+    }
+}
+
+fn save_sync_state(state: &SyncState) {
+    match get_sync_state_path() {
+        Ok(path) => match serde_json::to_string_pretty(state) {
+            Ok(json) => {
+                if let Err(e) = fs::write(&path, json) {
+                    log::warn!("Failed to save Gmail sync state: {}", e);
                 }
             }
+            Err(e) => {
+                log::warn!("Failed to serialize Gmail sync state: {}", e);
+            }
+        },
+        Err(e) => {
+            log::warn!("Failed to get Gmail sync state path: {}", e);
         }
+    }
+}
 
-        String::new()
+/// Walk the part tree collecting text parts. Gmail has already undone the
+/// transfer encoding, but the bytes are still in the part's charset.
+fn collect_body_text(part: &Payload, out: &mut BodyText) {
+    if !part.filename.is_empty() {
+        return;
     }
 
-    pub async fn clear_auth(&mut self) -> Result<(), String> {
-        KeychainManager::delete_all_credentials()?;
-        self.authenticated = false;
-        Ok(())
+    let mime_type = part.mime_type.to_ascii_lowercase();
+    if mime_type == "text/plain" || mime_type == "text/html" {
+        if let Some(body) = part.body.as_ref().filter(|b| !b.data.is_empty()) {
+            if let Ok(decoded) = base64_url_decode(&body.data) {
+                let content_type = part
+                    .headers
+                    .iter()
+                    .find(|h| h.name.eq_ignore_ascii_case("content-type"))
+                    .map(|h| h.value.as_str());
+                let charset = mime::content_type_charset(content_type);
+                out.add(
+                    &mime_type,
+                    mime::decode_charset(&decoded, charset.as_deref()),
+                );
+            }
+        }
+    }
+
+    for sub in part.parts.iter().flatten() {
+        collect_body_text(sub, out);
     }
 }
 
@@ -385,10 +801,3 @@ fn base64_url_decode(input: &str) -> Result<Vec<u8>, String> {
 
     STANDARD.decode(&padded).map_err(|e| e.to_string())
 }
-
-// SECURITY: Hash message IDs for logging to prevent correlation with Gmail API logs
-fn hash_message_id(id: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(id.as_bytes());
-    format!("{:x}", hasher.finalize())
-}
@@ -1,9 +1,36 @@
-use crate::types::CodeEntry;
+use crate::keychain::KeychainManager;
+use crate::types::{CodeEntry, EntryKind, RetentionPolicy};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 const HISTORY_FILE: &str = "code_history.json";
-const MAX_HISTORY_SIZE: usize = 50;
+const FORMAT_VERSION: u32 = 1;
+const NONCE_LEN: usize = 12;
+// Ties the ciphertext to this file format, so it can't be passed off as
+// another version
+const ASSOCIATED_DATA: &[u8] = b"otpbar-history-v1";
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+// Upper bound on a configured history size
+pub const MAX_HISTORY_LIMIT: usize = 500;
+// Appends before the log is rewritten as a single record
+const MAX_LOG_RECORDS: usize = 16;
+
+pub type HistoryKey = [u8; 32];
+
+/// One record of `code_history.json`: a JSON list of entries sealed with
+/// AES-256-GCM
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedHistory {
+    version: u32,
+    nonce: String,
+    ciphertext: String,
+}
 
 pub fn get_history_path() -> Result<PathBuf, String> {
     let mut path = dirs::config_dir().ok_or("Failed to get config directory")?;
@@ -13,49 +40,441 @@ pub fn get_history_path() -> Result<PathBuf, String> {
     Ok(path)
 }
 
-pub fn load_history() -> Vec<CodeEntry> {
-    match get_history_path() {
-        Ok(path) if path.exists() => match fs::read_to_string(&path) {
-            Ok(content) => match serde_json::from_str::<Vec<CodeEntry>>(&content) {
-                Ok(entries) => {
-                    log::info!("Loaded {} codes from history", entries.len());
-                    entries
-                }
-                Err(e) => {
-                    log::warn!("Failed to parse history file: {}", e);
-                    Vec::new()
-                }
+pub fn generate_key() -> HistoryKey {
+    rand::random()
+}
+
+pub fn encrypt_history(codes: &[CodeEntry], key: &HistoryKey) -> Result<String, String> {
+    let json =
+        serde_json::to_vec(codes).map_err(|e| format!("Failed to serialize history: {}", e))?;
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let cipher = Aes256Gcm::new_from_slice(key)
+        .map_err(|e| format!("Failed to create history cipher: {}", e))?;
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &json,
+                aad: ASSOCIATED_DATA,
             },
+        )
+        .map_err(|_| "Failed to encrypt history".to_string())?;
+
+    serde_json::to_string(&EncryptedHistory {
+        version: FORMAT_VERSION,
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    })
+    .map_err(|e| format!("Failed to serialize history: {}", e))
+}
+
+pub fn decrypt_history(content: &str, key: &HistoryKey) -> Result<Vec<CodeEntry>, String> {
+    let sealed: EncryptedHistory = serde_json::from_str(content)
+        .map_err(|e| format!("Failed to parse history file: {}", e))?;
+    if sealed.version != FORMAT_VERSION {
+        return Err(format!(
+            "Unsupported history file version {}",
+            sealed.version
+        ));
+    }
+    let nonce = STANDARD
+        .decode(&sealed.nonce)
+        .map_err(|e| format!("Failed to decode history nonce: {}", e))?;
+    if nonce.len() != NONCE_LEN {
+        return Err("Invalid history nonce".to_string());
+    }
+    let ciphertext = STANDARD
+        .decode(&sealed.ciphertext)
+        .map_err(|e| format!("Failed to decode history: {}", e))?;
+
+    let cipher = Aes256Gcm::new_from_slice(key)
+        .map_err(|e| format!("Failed to create history cipher: {}", e))?;
+    let json = cipher
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad: ASSOCIATED_DATA,
+            },
+        )
+        .map_err(|_| "Failed to decrypt history: wrong key or corrupted file".to_string())?;
+    serde_json::from_slice(&json).map_err(|e| format!("Failed to parse history: {}", e))
+}
+
+/// Entries from a history file written before encryption, or `None` when
+/// the file isn't one
+pub fn parse_plaintext_history(content: &str) -> Option<Vec<CodeEntry>> {
+    serde_json::from_str(content).ok()
+}
+
+/// The entries `policy` still allows keeping at `now` (ms), newest first
+pub fn apply_retention(codes: &[CodeEntry], policy: &RetentionPolicy, now: i64) -> Vec<CodeEntry> {
+    let cutoff = policy
+        .max_age_days
+        .map(|days| now - i64::from(days) * DAY_MS);
+    codes
+        .iter()
+        .filter(|entry| cutoff.is_none_or(|cutoff| entry.timestamp >= cutoff))
+        .take(policy.max_count)
+        .cloned()
+        .collect()
+}
+
+/// What gets written to disk under `policy`: the retained entries, without
+/// their codes and links when only metadata is kept
+pub fn entries_to_persist(
+    codes: &[CodeEntry],
+    policy: &RetentionPolicy,
+    now: i64,
+) -> Vec<CodeEntry> {
+    let mut entries = apply_retention(codes, policy, now);
+    if policy.metadata_only {
+        for entry in &mut entries {
+            entry.code.clear();
+            entry.display_code = None;
+        }
+    }
+    entries
+}
+
+/// The history key from the keychain, generating and storing one on first
+/// run when `create` is set. `Ok(None)` means there is no key yet.
+fn history_key(create: bool) -> Result<Option<HistoryKey>, String> {
+    let key = match KeychainManager::get_history_key()? {
+        Some(encoded) => {
+            let bytes =
+                hex::decode(encoded).map_err(|e| format!("Failed to decode history key: {}", e))?;
+            HistoryKey::try_from(bytes.as_slice())
+                .map_err(|_| "History key in the keychain has the wrong length".to_string())?
+        }
+        None if create => {
+            let key = generate_key();
+            KeychainManager::set_history_key(&hex::encode(key))
+                .map_err(|e| format!("Failed to store history key: {}", e))?;
+            log::info!("Generated a new history encryption key");
+            key
+        }
+        None => return Ok(None),
+    };
+    Ok(Some(key))
+}
+
+/// The code history, newest first, backed by a log of encrypted records in
+/// `code_history.json`. Each line is one record holding entries newest
+/// first, and later lines are newer. A new code is appended as its own line;
+/// removals, and every `MAX_LOG_RECORDS` appends, rewrite the log as a single
+/// record through a temp file and rename.
+#[derive(Default)]
+pub struct HistoryStore {
+    // None keeps the history in memory only
+    path: Option<PathBuf>,
+    // Read from the keychain on first use when not given
+    key: Option<HistoryKey>,
+    entries: Vec<CodeEntry>,
+    records: usize,
+    // The log couldn't be read when opened, e.g. because the keychain was
+    // unavailable, so it mustn't be written over until it can be
+    locked: bool,
+}
+
+impl HistoryStore {
+    /// The history in the config directory, keyed from the keychain
+    pub fn load(policy: &RetentionPolicy) -> Self {
+        let path = match get_history_path() {
+            Ok(path) => path,
             Err(e) => {
-                log::warn!("Failed to read history file: {}", e);
-                Vec::new()
+                log::warn!("Failed to get history path: {}", e);
+                return Self::default();
             }
-        },
-        _ => Vec::new(),
-    }
-}
-
-pub fn save_history(codes: &[CodeEntry]) {
-    match get_history_path() {
-        Ok(path) => {
-            let to_save = codes
-                .iter()
-                .take(MAX_HISTORY_SIZE)
-                .cloned()
-                .collect::<Vec<_>>();
-            match serde_json::to_string_pretty(&to_save) {
-                Ok(json) => {
-                    if let Err(e) = fs::write(&path, json) {
-                        log::warn!("Failed to save history: {}", e);
-                    }
+        };
+        let store = Self::open_or_lock(path, None, policy);
+        if !store.locked {
+            log::info!("Loaded {} codes from history", store.entries.len());
+        }
+        store
+    }
+
+    /// `open`, or if the log can't be read right now, a locked store. New
+    /// codes are kept in memory and the log is left alone until a later
+    /// write finds it readable and merges it in.
+    pub fn open_or_lock(path: PathBuf, key: Option<HistoryKey>, policy: &RetentionPolicy) -> Self {
+        match Self::open(path.clone(), key, policy) {
+            Ok(store) => store,
+            Err(e) => {
+                log::error!("Failed to load history, leaving it untouched: {}", e);
+                Self {
+                    path: Some(path),
+                    key,
+                    locked: true,
+                    ..Self::default()
                 }
-                Err(e) => {
-                    log::warn!("Failed to serialize history: {}", e);
+            }
+        }
+    }
+
+    /// Open the log at `path`, encrypting a plaintext history left by older
+    /// versions in place. A log that can't be decrypted is moved aside and
+    /// the history starts empty; a keychain error leaves it untouched.
+    pub fn open(
+        path: PathBuf,
+        key: Option<HistoryKey>,
+        policy: &RetentionPolicy,
+    ) -> Result<Self, String> {
+        let mut store = Self {
+            path: Some(path.clone()),
+            key,
+            ..Self::default()
+        };
+        if !path.exists() {
+            return Ok(store);
+        }
+        let content =
+            fs::read_to_string(&path).map_err(|e| format!("Failed to read history file: {}", e))?;
+        // `compact` leaves an empty file, written without a key, once
+        // everything has aged out
+        if content.trim().is_empty() {
+            return Ok(store);
+        }
+        let now = chrono::Utc::now().timestamp_millis();
+
+        if let Some(entries) = parse_plaintext_history(&content) {
+            log::info!("Encrypting {} codes from plaintext history", entries.len());
+            store.entries = apply_retention(&entries, policy, now);
+            store.compact(policy)?;
+            return Ok(store);
+        }
+
+        let Some(key) = store.key(false)? else {
+            log::error!("History is encrypted but its key is missing from the keychain");
+            set_aside(&path);
+            return Ok(store);
+        };
+        match read_log(&content, &key) {
+            Ok(log) => {
+                store.entries = apply_retention(&log.entries, policy, now);
+                store.records = log.records;
+                if log.torn || store.entries.len() < log.entries.len() {
+                    store.compact(policy)?;
                 }
+                Ok(store)
+            }
+            Err(e) => {
+                log::error!("{}", e);
+                set_aside(&path);
+                Ok(store)
+            }
+        }
+    }
+
+    pub fn entries(&self) -> &[CodeEntry] {
+        &self.entries
+    }
+
+    /// The newest `count` entries, as shown in the menubar list, skipping
+    /// used ones unless `include_used` is set
+    pub fn recent(&self, count: usize, include_used: bool) -> Vec<CodeEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| include_used || entry.used_at.is_none())
+            .take(count)
+            .cloned()
+            .collect()
+    }
+
+    /// Mark the unused code from message `message_id` as used at `now`,
+    /// returning whether there was one
+    pub fn mark_used(&mut self, message_id: &str, now: i64, policy: &RetentionPolicy) -> bool {
+        let Some(entry) = self.entries.iter_mut().find(|entry| {
+            entry.kind == EntryKind::Code
+                && entry.message_id == message_id
+                && entry.used_at.is_none()
+        }) else {
+            return false;
+        };
+        entry.used_at = Some(now);
+        if let Err(e) = self.compact(policy) {
+            log::warn!("Failed to save history: {}", e);
+        }
+        true
+    }
+
+    /// Add a new code at the top. Entries past `max_count` stay in the log
+    /// until the next rewrite, which is at most `MAX_LOG_RECORDS` appends
+    /// away.
+    pub fn append(&mut self, entry: CodeEntry, policy: &RetentionPolicy) {
+        self.entries.insert(0, entry);
+        let now = chrono::Utc::now().timestamp_millis();
+        self.entries = apply_retention(&self.entries, policy, now);
+
+        let result = if self.locked || self.records >= MAX_LOG_RECORDS {
+            self.compact(policy)
+        } else {
+            self.append_record(policy)
+        };
+        if let Err(e) = result {
+            log::warn!("Failed to save history: {}", e);
+        }
+    }
+
+    /// Drop entries `keep` rejects, rewriting the log if any were removed
+    pub fn retain(&mut self, keep: impl Fn(&CodeEntry) -> bool, policy: &RetentionPolicy) {
+        let before = self.entries.len();
+        self.entries.retain(|entry| keep(entry));
+        if self.entries.len() < before {
+            if let Err(e) = self.compact(policy) {
+                log::warn!("Failed to save history: {}", e);
+            }
+        }
+    }
+
+    /// Drop entries `policy` no longer allows at `now`, returning how many
+    pub fn enforce_retention(&mut self, policy: &RetentionPolicy, now: i64) -> usize {
+        let before = self.entries.len();
+        self.entries = apply_retention(&self.entries, policy, now);
+        let removed = before - self.entries.len();
+        if removed > 0 {
+            if let Err(e) = self.compact(policy) {
+                log::warn!("Failed to save history: {}", e);
             }
         }
-        Err(e) => {
-            log::warn!("Failed to get history path: {}", e);
+        removed
+    }
+
+    /// Drop every entry, including any in a log that couldn't be read
+    pub fn clear(&mut self) -> Result<(), String> {
+        self.entries.clear();
+        self.locked = false;
+        self.compact(&RetentionPolicy::default())
+    }
+
+    /// Rewrite the log as a single record of the current entries
+    pub fn compact(&mut self, policy: &RetentionPolicy) -> Result<(), String> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+        self.unlock(policy)?;
+        let now = chrono::Utc::now().timestamp_millis();
+        let persisted = entries_to_persist(&self.entries, policy, now);
+        // An empty history needs no key
+        let content = if persisted.is_empty() {
+            String::new()
+        } else {
+            format!("{}\n", encrypt_history(&persisted, &self.write_key()?)?)
+        };
+
+        write_atomic(&path, content.as_bytes())?;
+        self.records = usize::from(!persisted.is_empty());
+        Ok(())
+    }
+
+    fn append_record(&mut self, policy: &RetentionPolicy) -> Result<(), String> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+        let now = chrono::Utc::now().timestamp_millis();
+        let newest = entries_to_persist(&self.entries[..1], policy, now);
+        let line = format!("{}\n", encrypt_history(&newest, &self.write_key()?)?);
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to open history file: {}", e))?;
+        file.write_all(line.as_bytes())
+            .map_err(|e| format!("Failed to append to history: {}", e))?;
+        self.records += 1;
+        Ok(())
+    }
+
+    // Merge in a log that couldn't be read before, now that it can be,
+    // or refuse to write
+    fn unlock(&mut self, policy: &RetentionPolicy) -> Result<(), String> {
+        let Some(path) = self.path.clone().filter(|_| self.locked) else {
+            return Ok(());
+        };
+        let stored = Self::open(path, self.key, policy)
+            .map_err(|e| format!("History is locked until it can be read: {}", e))?;
+        log::info!(
+            "History is readable again; merging {} codes",
+            stored.entries.len()
+        );
+
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut entries = std::mem::take(&mut self.entries);
+        entries.extend(stored.entries);
+        self.entries = apply_retention(&entries, policy, now);
+        self.key = stored.key;
+        self.locked = false;
+        Ok(())
+    }
+
+    fn key(&mut self, create: bool) -> Result<Option<HistoryKey>, String> {
+        if self.key.is_none() {
+            self.key = history_key(create)?;
+        }
+        Ok(self.key)
+    }
+
+    // Never fall back to writing codes in the clear
+    fn write_key(&mut self) -> Result<HistoryKey, String> {
+        self.key(true)?
+            .ok_or_else(|| "History encryption key unavailable".to_string())
+    }
+}
+
+struct HistoryLog {
+    entries: Vec<CodeEntry>,
+    records: usize,
+    // The last record was cut short, e.g. by a crash mid-append
+    torn: bool,
+}
+
+fn read_log(content: &str, key: &HistoryKey) -> Result<HistoryLog, String> {
+    let lines: Vec<&str> = content.lines().filter(|l| !l.trim().is_empty()).collect();
+    let mut records = Vec::with_capacity(lines.len());
+    let mut torn = false;
+    for (i, line) in lines.iter().enumerate() {
+        match decrypt_history(line, key) {
+            Ok(entries) => records.push(entries),
+            // Earlier records decrypted, so the key is right and only the
+            // last write is damaged
+            Err(e) if i > 0 && i + 1 == lines.len() => {
+                log::warn!("Skipping incomplete history record: {}", e);
+                torn = true;
+            }
+            Err(e) => return Err(e),
         }
     }
+
+    Ok(HistoryLog {
+        records: records.len(),
+        entries: records.into_iter().rev().flatten().collect(),
+        torn,
+    })
+}
+
+fn write_atomic(path: &Path, content: &[u8]) -> Result<(), String> {
+    let temp = path.with_extension("json.tmp");
+    let mut file =
+        fs::File::create(&temp).map_err(|e| format!("Failed to create history file: {}", e))?;
+    file.write_all(content)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| format!("Failed to write history file: {}", e))?;
+    fs::rename(&temp, path).map_err(|e| format!("Failed to replace history file: {}", e))
+}
+
+/// Move an unreadable history file out of the way rather than overwriting
+/// it, in case the key turns up again
+// Timestamped so an earlier unreadable file is never overwritten
+fn set_aside(path: &Path) {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".unreadable-{}",
+        chrono::Utc::now().timestamp_millis()
+    ));
+    let target = path.with_file_name(name);
+    match fs::rename(path, &target) {
+        Ok(()) => log::warn!("Moved unreadable history to {}", target.display()),
+        Err(e) => log::warn!("Failed to move unreadable history: {}", e),
+    }
 }
@@ -0,0 +1,119 @@
+use crate::types::{CodeEntry, EntryKind};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+// Largest page a single query returns
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// Filters over the code history. Unset filters match everything; text
+/// comparisons ignore case.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HistoryQuery {
+    /// Provider name, e.g. "Stripe"
+    pub provider: Option<String>,
+    /// Part of the sender's name or address
+    pub sender: Option<String>,
+    /// Mailbox the code was read from
+    pub account: Option<String>,
+    /// Only entries received at or after this time, in ms
+    pub since: Option<i64>,
+    /// Only entries received before this time, in ms
+    pub until: Option<i64>,
+    pub kind: Option<EntryKind>,
+    /// Free text matched against sender, provider and account
+    pub text: Option<String>,
+    pub sort: HistorySort,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistorySort {
+    #[default]
+    NewestFirst,
+    OldestFirst,
+    /// By provider name, newest first within each provider
+    Provider,
+}
+
+/// One page of query results, with the number of entries that matched
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryPage {
+    pub entries: Vec<CodeEntry>,
+    pub total: usize,
+}
+
+impl HistoryQuery {
+    /// A page of the full history, newest first
+    pub fn page(offset: usize, limit: usize) -> Self {
+        Self {
+            offset,
+            limit: Some(limit),
+            ..Default::default()
+        }
+    }
+
+    /// A page of entries whose sender, provider or account contains `text`
+    pub fn search(text: &str, offset: usize, limit: usize) -> Self {
+        Self {
+            text: Some(text.to_string()),
+            ..Self::page(offset, limit)
+        }
+    }
+
+    pub fn matches(&self, entry: &CodeEntry) -> bool {
+        let equals = |filter: &Option<String>, value: Option<&String>| {
+            filter
+                .as_ref()
+                .is_none_or(|f| value.is_some_and(|v| v.eq_ignore_ascii_case(f.trim())))
+        };
+        let contains = |haystack: &str, needle: &str| {
+            haystack
+                .to_lowercase()
+                .contains(&needle.trim().to_lowercase())
+        };
+
+        equals(&self.provider, Some(&entry.provider))
+            && equals(&self.account, entry.account.as_ref())
+            && self
+                .sender
+                .as_ref()
+                .is_none_or(|s| contains(&entry.sender, s))
+            && self.since.is_none_or(|t| entry.timestamp >= t)
+            && self.until.is_none_or(|t| entry.timestamp < t)
+            && self.kind.is_none_or(|k| entry.kind == k)
+            && self.text.as_ref().is_none_or(|t| {
+                contains(&entry.sender, t)
+                    || contains(&entry.provider, t)
+                    || entry.account.as_ref().is_some_and(|a| contains(a, t))
+            })
+    }
+}
+
+/// Run `query` over history entries
+pub fn query_history(entries: &[CodeEntry], query: &HistoryQuery) -> HistoryPage {
+    let mut matching: Vec<&CodeEntry> = entries.iter().filter(|e| query.matches(e)).collect();
+    match query.sort {
+        HistorySort::NewestFirst => matching.sort_by_key(|e| std::cmp::Reverse(e.timestamp)),
+        HistorySort::OldestFirst => matching.sort_by_key(|e| e.timestamp),
+        HistorySort::Provider => matching.sort_by(|a, b| {
+            a.provider
+                .to_lowercase()
+                .cmp(&b.provider.to_lowercase())
+                .then(b.timestamp.cmp(&a.timestamp))
+        }),
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    HistoryPage {
+        total: matching.len(),
+        entries: matching
+            .into_iter()
+            .skip(query.offset)
+            .take(limit)
+            .cloned()
+            .collect(),
+    }
+}
@@ -0,0 +1,711 @@
+use crate::keychain::KeychainManager;
+use crate::mail_source::{EmailMessage, MailSource};
+use crate::mime;
+use crate::sender_auth;
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Duration};
+
+const IMAP_CONFIG_FILE: &str = "imap.json";
+const CONNECT_TIMEOUT_SECS: u64 = 15;
+const COMMAND_TIMEOUT_SECS: u64 = 30;
+// RFC 2177: clients should re-issue IDLE at least every 29 minutes
+const IDLE_REFRESH_SECS: u64 = 25 * 60;
+const IDLE_RETRY_SECS: u64 = 30;
+const SEARCH_WINDOW_DAYS: i64 = 1;
+const MAX_SEARCH_RESULTS: usize = 25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImapAuthMethod {
+    /// Plain `LOGIN` with a password or app password
+    Login,
+    /// SASL `XOAUTH2` with a bearer access token
+    XOAuth2,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImapConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    #[serde(default = "default_use_tls")]
+    pub use_tls: bool,
+    #[serde(default = "default_auth_method")]
+    pub auth_method: ImapAuthMethod,
+    #[serde(default = "default_mailbox")]
+    pub mailbox: String,
+}
+
+fn default_use_tls() -> bool {
+    true
+}
+
+fn default_auth_method() -> ImapAuthMethod {
+    ImapAuthMethod::Login
+}
+
+fn default_mailbox() -> String {
+    "INBOX".to_string()
+}
+
+pub fn get_imap_config_path() -> Result<PathBuf, String> {
+    let mut path = dirs::config_dir().ok_or("Failed to get config directory")?;
+    path.push("otpbar");
+    fs::create_dir_all(&path).map_err(|e| format!("Failed to create config directory: {}", e))?;
+    path.push(IMAP_CONFIG_FILE);
+    Ok(path)
+}
+
+pub fn load_imap_config() -> Option<ImapConfig> {
+    let path = get_imap_config_path().ok()?;
+    if !path.exists() {
+        return None;
+    }
+    match fs::read_to_string(&path) {
+        Ok(content) => match serde_json::from_str::<ImapConfig>(&content) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                log::warn!("Failed to parse IMAP config: {}", e);
+                None
+            }
+        },
+        Err(e) => {
+            log::warn!("Failed to read IMAP config: {}", e);
+            None
+        }
+    }
+}
+
+pub fn save_imap_config(config: &ImapConfig) -> Result<(), String> {
+    let path = get_imap_config_path()?;
+    let json = serde_json::to_string_pretty(config)
+        .map_err(|e| format!("Failed to serialize IMAP config: {}", e))?;
+    fs::write(&path, json).map_err(|e| format!("Failed to save IMAP config: {}", e))
+}
+
+pub fn delete_imap_config() {
+    if let Ok(path) = get_imap_config_path() {
+        if path.exists() {
+            if let Err(e) = fs::remove_file(&path) {
+                log::warn!("Failed to delete IMAP config: {}", e);
+            }
+        }
+    }
+}
+
+trait ImapStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ImapStream for T {}
+
+/// One server response line, with any `{n}` literals pulled out in order
+#[derive(Debug, Default)]
+struct ResponseLine {
+    text: String,
+    literals: Vec<Vec<u8>>,
+}
+
+struct ImapConnection {
+    stream: BufReader<Box<dyn ImapStream>>,
+    next_tag: u32,
+    // Kept across calls so a cancelled read (e.g. IDLE timeout) doesn't lose bytes
+    line_buf: Vec<u8>,
+    // UIDVALIDITY of the examined mailbox, 0 if the server didn't say
+    uid_validity: u32,
+}
+
+impl ImapConnection {
+    async fn connect(config: &ImapConfig) -> Result<Self, String> {
+        let addr = format!("{}:{}", config.host, config.port);
+        let tcp = timeout(
+            Duration::from_secs(CONNECT_TIMEOUT_SECS),
+            TcpStream::connect(&addr),
+        )
+        .await
+        .map_err(|_| format!("Timed out connecting to {}", config.host))?
+        .map_err(|e| format!("Failed to connect to {}: {}", config.host, e))?;
+
+        let stream: Box<dyn ImapStream> = if config.use_tls {
+            let connector = native_tls::TlsConnector::new()
+                .map_err(|e| format!("Failed to create TLS connector: {}", e))?;
+            let connector = tokio_native_tls::TlsConnector::from(connector);
+            let tls = connector
+                .connect(&config.host, tcp)
+                .await
+                .map_err(|e| format!("TLS handshake failed: {}", e))?;
+            Box::new(tls)
+        } else {
+            Box::new(tcp)
+        };
+
+        let mut conn = ImapConnection {
+            stream: BufReader::new(stream),
+            next_tag: 1,
+            line_buf: Vec::new(),
+            uid_validity: 0,
+        };
+
+        let greeting = conn.read_line().await?;
+        if !greeting.text.starts_with("* OK") && !greeting.text.starts_with("* PREAUTH") {
+            return Err(format!("Unexpected IMAP greeting: {}", greeting.text));
+        }
+
+        Ok(conn)
+    }
+
+    async fn read_raw_line(&mut self) -> Result<String, String> {
+        loop {
+            let n = self
+                .stream
+                .read_until(b'\n', &mut self.line_buf)
+                .await
+                .map_err(|e| format!("IMAP read failed: {}", e))?;
+            if n == 0 {
+                return Err("IMAP connection closed".to_string());
+            }
+            if self.line_buf.ends_with(b"\n") {
+                let line = String::from_utf8_lossy(&self.line_buf)
+                    .trim_end_matches(['\r', '\n'])
+                    .to_string();
+                self.line_buf.clear();
+                return Ok(line);
+            }
+        }
+    }
+
+    async fn read_line(&mut self) -> Result<ResponseLine, String> {
+        let mut response = ResponseLine::default();
+        loop {
+            let line = self.read_raw_line().await?;
+            match literal_size(&line) {
+                Some(size) => {
+                    let mut literal = vec![0u8; size];
+                    self.stream
+                        .read_exact(&mut literal)
+                        .await
+                        .map_err(|e| format!("IMAP literal read failed: {}", e))?;
+                    response.text.push_str(&line);
+                    response.literals.push(literal);
+                }
+                None => {
+                    response.text.push_str(&line);
+                    return Ok(response);
+                }
+            }
+        }
+    }
+
+    async fn write_line(&mut self, line: &str) -> Result<(), String> {
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(format!("{}\r\n", line).as_bytes())
+            .await
+            .map_err(|e| format!("IMAP write failed: {}", e))?;
+        stream
+            .flush()
+            .await
+            .map_err(|e| format!("IMAP write failed: {}", e))
+    }
+
+    fn tag(&mut self) -> String {
+        let tag = format!("A{}", self.next_tag);
+        self.next_tag += 1;
+        tag
+    }
+
+    async fn read_until_tagged(&mut self, tag: &str) -> Result<Vec<ResponseLine>, String> {
+        let mut untagged = Vec::new();
+        loop {
+            let line = self.read_line().await?;
+            if let Some(status) = line.text.strip_prefix(&format!("{} ", tag)) {
+                if status.starts_with("OK") {
+                    return Ok(untagged);
+                }
+                return Err(format!("IMAP command failed: {}", status));
+            }
+            untagged.push(line);
+        }
+    }
+
+    async fn command(&mut self, command: &str) -> Result<Vec<ResponseLine>, String> {
+        let tag = self.tag();
+        self.write_line(&format!("{} {}", tag, command)).await?;
+        timeout(
+            Duration::from_secs(COMMAND_TIMEOUT_SECS),
+            self.read_until_tagged(&tag),
+        )
+        .await
+        .map_err(|_| "Timed out waiting for IMAP response".to_string())?
+    }
+
+    async fn authenticate(&mut self, config: &ImapConfig, secret: &str) -> Result<(), String> {
+        match config.auth_method {
+            ImapAuthMethod::Login => {
+                let command = format!(
+                    "LOGIN {} {}",
+                    quote_string(&config.username)?,
+                    quote_string(secret)?
+                );
+                self.command(&command).await?;
+            }
+            ImapAuthMethod::XOAuth2 => {
+                let sasl = format!("user={}\x01auth=Bearer {}\x01\x01", config.username, secret);
+                let tag = self.tag();
+                self.write_line(&format!(
+                    "{} AUTHENTICATE XOAUTH2 {}",
+                    tag,
+                    STANDARD.encode(sasl)
+                ))
+                .await?;
+
+                // On failure the server sends a base64 error challenge that must be
+                // answered with an empty line before it returns the tagged NO
+                let line = self.read_line().await?;
+                if line.text.starts_with('+') {
+                    self.write_line("").await?;
+                } else if let Some(status) = line.text.strip_prefix(&format!("{} ", tag)) {
+                    if status.starts_with("OK") {
+                        return Ok(());
+                    }
+                    return Err(format!("IMAP authentication failed: {}", status));
+                }
+                self.read_until_tagged(&tag)
+                    .await
+                    .map_err(|e| format!("IMAP authentication failed: {}", e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Open the mailbox read-only so fetching never marks messages as seen
+    async fn examine(&mut self, mailbox: &str) -> Result<(), String> {
+        let lines = self
+            .command(&format!("EXAMINE {}", quote_string(mailbox)?))
+            .await?;
+        self.uid_validity = lines
+            .iter()
+            .find_map(|l| l.text.split_once("[UIDVALIDITY "))
+            .and_then(|(_, rest)| rest.split(']').next()?.trim().parse().ok())
+            .unwrap_or(0);
+        Ok(())
+    }
+
+    async fn search_unseen_since(&mut self, since: chrono::NaiveDate) -> Result<Vec<u32>, String> {
+        let lines = self
+            .command(&format!(
+                "UID SEARCH UNSEEN SINCE {}",
+                since.format("%d-%b-%Y")
+            ))
+            .await?;
+
+        let mut uids: Vec<u32> = lines
+            .iter()
+            .filter_map(|l| l.text.strip_prefix("* SEARCH"))
+            .flat_map(|rest| rest.split_whitespace().filter_map(|n| n.parse().ok()))
+            .collect();
+        uids.sort_unstable_by(|a, b| b.cmp(a));
+        Ok(uids)
+    }
+
+    /// Unseen messages as `message_id`s, newest first
+    async fn search_unseen_ids(&mut self, since: chrono::NaiveDate) -> Result<Vec<String>, String> {
+        let uids = self.search_unseen_since(since).await?;
+        Ok(uids
+            .into_iter()
+            .take(MAX_SEARCH_RESULTS)
+            .map(|uid| message_id(self.uid_validity, uid))
+            .collect())
+    }
+
+    /// A message listed under `uid_validity`, which must still hold: after a
+    /// change the same UID can name a different message
+    async fn fetch_listed(&mut self, uid_validity: u32, uid: u32) -> Result<Vec<u8>, String> {
+        if uid_validity != self.uid_validity {
+            return Err("IMAP mailbox changed since the message was listed".to_string());
+        }
+        self.fetch_raw(uid).await
+    }
+
+    async fn fetch_raw(&mut self, uid: u32) -> Result<Vec<u8>, String> {
+        let lines = self
+            .command(&format!("UID FETCH {} (UID BODY.PEEK[])", uid))
+            .await?;
+
+        lines
+            .into_iter()
+            .find(|l| l.text.contains(" FETCH ") && !l.literals.is_empty())
+            .and_then(|mut l| l.literals.pop())
+            .ok_or_else(|| "Message not found".to_string())
+    }
+
+    /// Wait in IDLE until the server reports new mail or `wait` elapses.
+    /// Returns true when new mail arrived.
+    async fn idle(&mut self, wait: Duration) -> Result<bool, String> {
+        let tag = self.tag();
+        self.write_line(&format!("{} IDLE", tag)).await?;
+
+        let line = self.read_line().await?;
+        if !line.text.starts_with('+') {
+            return Err(format!("Server refused IDLE: {}", line.text));
+        }
+
+        let deadline = tokio::time::Instant::now() + wait;
+        let mut new_mail = false;
+        loop {
+            match tokio::time::timeout_at(deadline, self.read_raw_line()).await {
+                Ok(Ok(line)) => {
+                    if line.starts_with("* ") && line.ends_with(" EXISTS") {
+                        new_mail = true;
+                        break;
+                    }
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(_) => break,
+            }
+        }
+
+        self.write_line("DONE").await?;
+        timeout(
+            Duration::from_secs(COMMAND_TIMEOUT_SECS),
+            self.read_until_tagged(&tag),
+        )
+        .await
+        .map_err(|_| "Timed out leaving IDLE".to_string())??;
+
+        Ok(new_mail)
+    }
+
+    async fn logout(&mut self) {
+        let _ = self.command("LOGOUT").await;
+    }
+}
+
+fn literal_size(line: &str) -> Option<usize> {
+    let inner = line.strip_suffix('}')?;
+    let start = inner.rfind('{')?;
+    inner[start + 1..].trim_end_matches('+').parse().ok()
+}
+
+fn quote_string(value: &str) -> Result<String, String> {
+    if value.contains(['\r', '\n']) {
+        return Err("IMAP strings cannot contain line breaks".to_string());
+    }
+    Ok(format!(
+        "\"{}\"",
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    ))
+}
+
+pub struct ImapClient {
+    config: ImapConfig,
+    secret: String,
+    authenticated: AtomicBool,
+    session: Mutex<Option<ImapConnection>>,
+    new_mail: Arc<Notify>,
+    idle_task: std::sync::Mutex<Option<JoinHandle<()>>>,
+}
+
+impl ImapClient {
+    pub fn new(config: ImapConfig, secret: String) -> Self {
+        ImapClient {
+            config,
+            secret,
+            authenticated: AtomicBool::new(false),
+            session: Mutex::new(None),
+            new_mail: Arc::new(Notify::new()),
+            idle_task: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Build a client from the saved config and the secret stored in the keychain
+    pub fn from_keychain(config: ImapConfig) -> Result<Self, String> {
+        let secret = KeychainManager::get_imap_secret(&config.username)?
+            .ok_or("No IMAP credentials stored")?;
+        Ok(Self::new(config, secret))
+    }
+
+    /// Persist the account: secret to the keychain, connection settings to disk
+    pub fn save(&self) -> Result<(), String> {
+        KeychainManager::set_imap_secret(&self.config.username, &self.secret)?;
+        save_imap_config(&self.config)
+    }
+
+    /// Verify the credentials against the server and start listening for new mail
+    pub async fn connect(&mut self) -> Result<(), String> {
+        let conn = open_session(&self.config, &self.secret).await?;
+        *self.session.lock().await = Some(conn);
+        self.authenticated.store(true, Ordering::Relaxed);
+        self.start_idle();
+        log::info!("IMAP session established");
+        Ok(())
+    }
+
+    fn start_idle(&mut self) {
+        self.stop_idle();
+        let config = self.config.clone();
+        let secret = self.secret.clone();
+        let notify = Arc::clone(&self.new_mail);
+        *self.idle_task.get_mut().unwrap() = Some(tokio::spawn(idle_loop(config, secret, notify)));
+    }
+
+    fn stop_idle(&self) {
+        if let Some(task) = self.idle_task.lock().unwrap().take() {
+            task.abort();
+        }
+    }
+
+    async fn with_session<T, F>(&self, op: F) -> Result<T, String>
+    where
+        F: for<'a> Fn(
+            &'a mut ImapConnection,
+        ) -> std::pin::Pin<
+            Box<dyn std::future::Future<Output = Result<T, String>> + Send + 'a>,
+        >,
+    {
+        let mut session = self.session.lock().await;
+        if session.is_none() {
+            *session = Some(open_session(&self.config, &self.secret).await?);
+        }
+
+        let conn = session.as_mut().expect("IMAP session was just opened");
+        match op(conn).await {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                // Drop the broken session and retry once on a fresh connection
+                log::warn!("IMAP session error, reconnecting: {}", e);
+                *session = None;
+                let mut conn = open_session(&self.config, &self.secret).await?;
+                let result = op(&mut conn).await;
+                *session = Some(conn);
+                result
+            }
+        }
+    }
+}
+
+impl Drop for ImapClient {
+    fn drop(&mut self) {
+        self.stop_idle();
+    }
+}
+
+async fn open_session(config: &ImapConfig, secret: &str) -> Result<ImapConnection, String> {
+    let mut conn = ImapConnection::connect(config).await?;
+    conn.authenticate(config, secret).await?;
+    conn.examine(&config.mailbox).await?;
+    Ok(conn)
+}
+
+async fn idle_loop(config: ImapConfig, secret: String, notify: Arc<Notify>) {
+    loop {
+        let mut conn = match open_session(&config, &secret).await {
+            Ok(conn) => conn,
+            Err(e) => {
+                log::warn!("IMAP IDLE connection failed: {}", e);
+                tokio::time::sleep(Duration::from_secs(IDLE_RETRY_SECS)).await;
+                continue;
+            }
+        };
+
+        loop {
+            match conn.idle(Duration::from_secs(IDLE_REFRESH_SECS)).await {
+                Ok(true) => notify.notify_one(),
+                Ok(false) => {}
+                Err(e) => {
+                    log::warn!("IMAP IDLE interrupted: {}", e);
+                    break;
+                }
+            }
+        }
+
+        conn.logout().await;
+        tokio::time::sleep(Duration::from_secs(IDLE_RETRY_SECS)).await;
+    }
+}
+
+#[async_trait]
+impl MailSource for ImapClient {
+    fn name(&self) -> &'static str {
+        "imap"
+    }
+
+    fn account(&self) -> Option<String> {
+        Some(self.config.username.clone())
+    }
+
+    fn is_authenticated(&self) -> bool {
+        self.authenticated.load(Ordering::Relaxed)
+    }
+
+    async fn try_restore_auth(&mut self) -> bool {
+        match self.connect().await {
+            Ok(_) => true,
+            Err(e) => {
+                log::warn!("IMAP credential validation failed: {}", e);
+                false
+            }
+        }
+    }
+
+    async fn list_new_messages(&self) -> Result<Vec<String>, String> {
+        let since = (chrono::Utc::now() - chrono::Duration::days(SEARCH_WINDOW_DAYS)).date_naive();
+        self.with_session(move |conn| Box::pin(conn.search_unseen_ids(since)))
+            .await
+    }
+
+    async fn fetch_message_detail(&self, msg_id: &str) -> Result<EmailMessage, String> {
+        let (uid_validity, uid) = msg_id
+            .split_once(':')
+            .and_then(|(validity, uid)| Some((validity.parse().ok()?, uid.parse().ok()?)))
+            .ok_or_else(|| "Invalid IMAP message ID".to_string())?;
+        let raw = self
+            .with_session(move |conn| Box::pin(conn.fetch_listed(uid_validity, uid)))
+            .await?;
+
+        Ok(parse_raw_message(msg_id, &raw))
+    }
+
+    fn new_mail_notifier(&self) -> Option<Arc<Notify>> {
+        Some(Arc::clone(&self.new_mail))
+    }
+
+    async fn clear_auth(&self) -> Result<(), String> {
+        self.stop_idle();
+        if let Some(mut conn) = self.session.lock().await.take() {
+            conn.logout().await;
+        }
+        KeychainManager::delete_imap_secret(&self.config.username)?;
+        delete_imap_config();
+        self.authenticated.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// IMAP message IDs pair the UID with the mailbox's UIDVALIDITY, since UIDs
+/// are small numbers reused across mailboxes and servers
+fn message_id(uid_validity: u32, uid: u32) -> String {
+    format!("{}:{}", uid_validity, uid)
+}
+
+/// Split an RFC 5322 message into the fields the OTP extractor needs
+pub fn parse_raw_message(id: &str, raw: &[u8]) -> EmailMessage {
+    let (head, body) = mime::split_message(raw);
+    let headers = mime::parse_headers(&String::from_utf8_lossy(head));
+
+    let mut from = String::new();
+    let mut subject = String::new();
+    let mut sent_at = None;
+    for (name, value) in &headers {
+        match name.as_str() {
+            "from" if from.is_empty() => from = decode_encoded_words(value),
+            "subject" if subject.is_empty() => subject = decode_encoded_words(value),
+            "date" if sent_at.is_none() => {
+                sent_at = chrono::DateTime::parse_from_rfc2822(value)
+                    .ok()
+                    .map(|t| t.timestamp_millis());
+            }
+            _ => {}
+        }
+    }
+
+    let content = mime::message_body(&headers, body);
+    let sender_auth = sender_auth::verify_sender(
+        headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str())),
+        &from,
+    );
+    EmailMessage {
+        id: id.to_string(),
+        from,
+        subject,
+        snippet: String::new(),
+        links: content.links(),
+        body: content.into_text(),
+        sent_at,
+        sender_auth,
+    }
+}
+
+/// Decode RFC 2047 encoded words (`=?UTF-8?B?...?=`) in header values
+fn decode_encoded_words(value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    let mut after_encoded_word = false;
+
+    while let Some(start) = rest.find("=?") {
+        match decode_encoded_word(&rest[start..]) {
+            Some((decoded, len)) => {
+                // Whitespace between adjacent encoded words is not significant
+                let prefix = &rest[..start];
+                if !(after_encoded_word && prefix.trim().is_empty()) {
+                    out.push_str(prefix);
+                }
+                out.push_str(&decoded);
+                rest = &rest[start + len..];
+                after_encoded_word = true;
+            }
+            None => {
+                out.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                after_encoded_word = false;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn decode_encoded_word(word: &str) -> Option<(String, usize)> {
+    let inner = word.strip_prefix("=?")?;
+    let mut parts = inner.splitn(3, '?');
+    let charset = parts.next()?;
+    let encoding = parts.next()?;
+    let remainder = parts.next()?;
+    let end = remainder.find("?=")?;
+    let payload = &remainder[..end];
+    let consumed = 2 + inner.len() - remainder.len() + end + 2;
+
+    let bytes = match encoding.to_ascii_uppercase().as_str() {
+        "B" => STANDARD.decode(payload).ok()?,
+        "Q" => decode_q(payload),
+        _ => return None,
+    };
+    Some((mime::decode_charset(&bytes, Some(charset)), consumed))
+}
+
+fn decode_q(payload: &str) -> Vec<u8> {
+    let bytes = payload.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'_' => out.push(b' '),
+            // Work on bytes: the payload may hold raw non-ASCII text
+            b'=' if i + 2 < bytes.len() => match (hex_value(bytes[i + 1]), hex_value(bytes[i + 2]))
+            {
+                (Some(high), Some(low)) => {
+                    out.push(high << 4 | low);
+                    i += 2;
+                }
+                _ => out.push(b'='),
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    out
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|d| d as u8)
+}
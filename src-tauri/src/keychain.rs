@@ -1,47 +1,204 @@
 use keyring::Entry;
 
+const GMAIL_CREDENTIAL_KINDS: &[&str] = &["refresh-token", "access-token", "token-expiry"];
+const OUTLOOK_CREDENTIAL_ITEMS: &[&str] = &[
+    "outlook-refresh-token",
+    "outlook-access-token",
+    "outlook-token-expiry",
+];
+/// Keychain item holding the key that encrypts the code history
+pub const HISTORY_KEY_ITEM: &str = "history-key";
+
 pub struct KeychainManager;
 
 impl KeychainManager {
-    pub fn get_refresh_token() -> Result<Option<String>, String> {
-        let entry = Entry::new("otpbar", "gmail-refresh-token").map_err(|e| e.to_string())?;
+    pub fn get_gmail_refresh_token(account: &str) -> Result<Option<String>, String> {
+        let entry = Entry::new("otpbar", &gmail_key("refresh-token", account))
+            .map_err(|e| e.to_string())?;
         entry.get_password().map(Some).map_err(|e| e.to_string())
     }
 
-    pub fn set_refresh_token(token: &str) -> Result<(), String> {
+    pub fn set_gmail_refresh_token(account: &str, token: &str) -> Result<(), String> {
+        let entry = Entry::new("otpbar", &gmail_key("refresh-token", account))
+            .map_err(|e| e.to_string())?;
+        entry.set_password(token).map_err(|e| e.to_string())
+    }
+
+    pub fn get_gmail_access_token(account: &str) -> Result<Option<String>, String> {
+        let entry =
+            Entry::new("otpbar", &gmail_key("access-token", account)).map_err(|e| e.to_string())?;
+        entry.get_password().map(Some).map_err(|e| e.to_string())
+    }
+
+    pub fn set_gmail_access_token(account: &str, token: &str) -> Result<(), String> {
+        let entry =
+            Entry::new("otpbar", &gmail_key("access-token", account)).map_err(|e| e.to_string())?;
+        entry.set_password(token).map_err(|e| e.to_string())
+    }
+
+    pub fn get_gmail_token_expiry(account: &str) -> Result<Option<i64>, String> {
+        let entry =
+            Entry::new("otpbar", &gmail_key("token-expiry", account)).map_err(|e| e.to_string())?;
+        entry
+            .get_password()
+            .map(|s| s.parse().ok())
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn set_gmail_token_expiry(account: &str, expiry_ts: i64) -> Result<(), String> {
+        let entry =
+            Entry::new("otpbar", &gmail_key("token-expiry", account)).map_err(|e| e.to_string())?;
+        entry
+            .set_password(&expiry_ts.to_string())
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn delete_gmail_credentials(account: &str) -> Result<(), String> {
+        for kind in GMAIL_CREDENTIAL_KINDS {
+            let _ =
+                Entry::new("otpbar", &gmail_key(kind, account)).and_then(|e| e.delete_credential());
+        }
+        Ok(())
+    }
+
+    /// Names of the keychain items holding an account's Gmail credentials
+    pub fn gmail_keychain_items(account: &str) -> Vec<String> {
+        GMAIL_CREDENTIAL_KINDS
+            .iter()
+            .map(|kind| gmail_key(kind, account))
+            .collect()
+    }
+
+    /// Refresh token saved by versions that only supported one Gmail account
+    pub fn get_legacy_gmail_refresh_token() -> Result<Option<String>, String> {
         let entry = Entry::new("otpbar", "gmail-refresh-token").map_err(|e| e.to_string())?;
+        entry.get_password().map(Some).map_err(|e| e.to_string())
+    }
+
+    pub fn get_outlook_refresh_token() -> Result<Option<String>, String> {
+        let entry = Entry::new("otpbar", "outlook-refresh-token").map_err(|e| e.to_string())?;
+        entry.get_password().map(Some).map_err(|e| e.to_string())
+    }
+
+    pub fn set_outlook_refresh_token(token: &str) -> Result<(), String> {
+        let entry = Entry::new("otpbar", "outlook-refresh-token").map_err(|e| e.to_string())?;
         entry.set_password(token).map_err(|e| e.to_string())
     }
 
-    pub fn get_access_token() -> Result<Option<String>, String> {
-        let entry = Entry::new("otpbar", "gmail-access-token").map_err(|e| e.to_string())?;
+    pub fn get_outlook_access_token() -> Result<Option<String>, String> {
+        let entry = Entry::new("otpbar", "outlook-access-token").map_err(|e| e.to_string())?;
         entry.get_password().map(Some).map_err(|e| e.to_string())
     }
 
-    pub fn set_access_token(token: &str) -> Result<(), String> {
-        let entry = Entry::new("otpbar", "gmail-access-token").map_err(|e| e.to_string())?;
+    pub fn set_outlook_access_token(token: &str) -> Result<(), String> {
+        let entry = Entry::new("otpbar", "outlook-access-token").map_err(|e| e.to_string())?;
         entry.set_password(token).map_err(|e| e.to_string())
     }
 
-    pub fn get_token_expiry() -> Result<Option<i64>, String> {
-        let entry = Entry::new("otpbar", "gmail-token-expiry").map_err(|e| e.to_string())?;
+    pub fn get_outlook_token_expiry() -> Result<Option<i64>, String> {
+        let entry = Entry::new("otpbar", "outlook-token-expiry").map_err(|e| e.to_string())?;
         entry
             .get_password()
             .map(|s| s.parse().ok())
             .map_err(|e| e.to_string())
     }
 
-    pub fn set_token_expiry(expiry_ts: i64) -> Result<(), String> {
-        let entry = Entry::new("otpbar", "gmail-token-expiry").map_err(|e| e.to_string())?;
+    pub fn set_outlook_token_expiry(expiry_ts: i64) -> Result<(), String> {
+        let entry = Entry::new("otpbar", "outlook-token-expiry").map_err(|e| e.to_string())?;
         entry
             .set_password(&expiry_ts.to_string())
             .map_err(|e| e.to_string())
     }
 
-    pub fn delete_all_credentials() -> Result<(), String> {
+    pub fn delete_outlook_credentials() -> Result<(), String> {
+        for item in OUTLOOK_CREDENTIAL_ITEMS {
+            let _ = Entry::new("otpbar", item).and_then(|e| e.delete_credential());
+        }
+        Ok(())
+    }
+
+    /// Names of the keychain items holding the Outlook credentials
+    pub fn outlook_keychain_items() -> Vec<String> {
+        OUTLOOK_CREDENTIAL_ITEMS
+            .iter()
+            .map(|item| item.to_string())
+            .collect()
+    }
+
+    pub fn get_imap_secret(username: &str) -> Result<Option<String>, String> {
+        let entry = Entry::new("otpbar", &imap_secret_key(username)).map_err(|e| e.to_string())?;
+        entry.get_password().map(Some).map_err(|e| e.to_string())
+    }
+
+    pub fn set_imap_secret(username: &str, secret: &str) -> Result<(), String> {
+        let entry = Entry::new("otpbar", &imap_secret_key(username)).map_err(|e| e.to_string())?;
+        entry.set_password(secret).map_err(|e| e.to_string())
+    }
+
+    pub fn delete_imap_secret(username: &str) -> Result<(), String> {
+        let _ =
+            Entry::new("otpbar", &imap_secret_key(username)).and_then(|e| e.delete_credential());
+        Ok(())
+    }
+
+    /// Name of the keychain item holding an IMAP account's password or token
+    pub fn imap_keychain_item(username: &str) -> String {
+        imap_secret_key(username)
+    }
+
+    pub fn get_totp_secret(id: &str) -> Result<Option<String>, String> {
+        let entry = Entry::new("otpbar", &totp_secret_key(id)).map_err(|e| e.to_string())?;
+        entry.get_password().map(Some).map_err(|e| e.to_string())
+    }
+
+    pub fn set_totp_secret(id: &str, secret: &str) -> Result<(), String> {
+        let entry = Entry::new("otpbar", &totp_secret_key(id)).map_err(|e| e.to_string())?;
+        entry.set_password(secret).map_err(|e| e.to_string())
+    }
+
+    pub fn delete_totp_secret(id: &str) -> Result<(), String> {
+        let _ = Entry::new("otpbar", &totp_secret_key(id)).and_then(|e| e.delete_credential());
+        Ok(())
+    }
+
+    /// Name of the keychain item holding an authenticator account's secret
+    pub fn totp_keychain_item(id: &str) -> String {
+        totp_secret_key(id)
+    }
+
+    /// `None` only when no key has been stored yet; any other keychain
+    /// failure is an error, so callers don't mistake a locked keychain for a
+    /// missing key
+    pub fn get_history_key() -> Result<Option<String>, String> {
+        let entry = Entry::new("otpbar", HISTORY_KEY_ITEM).map_err(|e| e.to_string())?;
+        match entry.get_password() {
+            Ok(key) => Ok(Some(key)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    pub fn set_history_key(key: &str) -> Result<(), String> {
+        let entry = Entry::new("otpbar", HISTORY_KEY_ITEM).map_err(|e| e.to_string())?;
+        entry.set_password(key).map_err(|e| e.to_string())
+    }
+
+    pub fn delete_legacy_gmail_credentials() -> Result<(), String> {
         let _ = Entry::new("otpbar", "gmail-refresh-token").and_then(|e| e.delete_credential());
         let _ = Entry::new("otpbar", "gmail-access-token").and_then(|e| e.delete_credential());
         let _ = Entry::new("otpbar", "gmail-token-expiry").and_then(|e| e.delete_credential());
         Ok(())
     }
 }
+
+fn imap_secret_key(username: &str) -> String {
+    format!("imap-secret-{}", username)
+}
+
+fn gmail_key(kind: &str, account: &str) -> String {
+    format!("gmail-{}-{}", kind, account)
+}
+
+fn totp_secret_key(id: &str) -> String {
+    format!("totp-secret-{}", id)
+}
@@ -1,9 +1,22 @@
 // Library exports for testing
+pub mod expiry;
 pub mod gmail;
 pub mod history;
+pub mod history_query;
+pub mod imap;
 pub mod keychain;
+pub mod magic_link;
+pub mod mail_source;
+pub mod mime;
 pub mod oauth_server;
 pub mod otp;
+pub mod outlook;
+pub mod providers;
+pub mod rules;
+pub mod seen_messages;
+pub mod sender_auth;
+pub mod totp;
+pub mod totp_import;
 pub mod types;
 
 // Re-export commonly used types
@@ -0,0 +1,194 @@
+use crate::mime::Link;
+use crate::otp::keyword_regex;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::Url;
+
+const TEXT_SCORE: f32 = 3.0;
+const PATH_SCORE: f32 = 2.0;
+const TOKEN_SCORE: f32 = 1.0;
+// Links scoring below this, before the subject bonus, are ordinary links.
+// "Sign in" text alone isn't enough: plenty of mail links to a login page,
+// so the URL has to look like a sign-in link too.
+const MIN_SCORE: f32 = TEXT_SCORE + TOKEN_SCORE;
+// Score that maps to full confidence: "Sign in" link text, a sign-in path and
+// a one-time token, in a message whose subject says so
+const MAX_SCORE: f32 = 7.0;
+const SUBJECT_BONUS: f32 = 1.0;
+// Shortest path segment or query value that looks like a one-time token
+const MIN_TOKEN_LEN: usize = 20;
+
+/// Words in link text, or around a bare URL, that ask the reader to sign in
+/// or confirm something, by locale
+const LINK_KEYWORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "sign[- ]?in",
+            "log[- ]?in",
+            "magic link",
+            "verify",
+            "confirm",
+            "activate",
+            r"authenticat\w*",
+            "access your account",
+        ],
+    ),
+    (
+        "de",
+        &["anmelden", "einloggen", "bestätigen", "verifizieren"],
+    ),
+    (
+        "es",
+        &["iniciar sesión", "acceder", "verificar", "confirmar"],
+    ),
+    (
+        "fr",
+        &["se connecter", "connexion", "vérifier", "confirmer"],
+    ),
+    ("pt", &["entrar", "fazer login", "verificar", "confirmar"]),
+    ("ja", &["ログイン", "サインイン", "確認"]),
+    ("zh", &["登录", "登入", "验证", "驗證", "确认"]),
+];
+
+/// Links that appear in sign-in emails but never are the sign-in link
+const EXCLUDED_LINK_KEYWORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "unsubscribe",
+            "opt[- ]?out",
+            "preferences",
+            "privacy",
+            "terms",
+            "help",
+            "support",
+            "view (?:it )?in (?:your )?browser",
+            "not you",
+            r"didn'?t request",
+            "report",
+        ],
+    ),
+    ("de", &["abmelden", "abbestellen", "datenschutz"]),
+    ("es", &["darse de baja", "privacidad"]),
+    ("fr", &["désabonner", "désinscrire", "confidentialité"]),
+    ("pt", &["descadastrar", "privacidade"]),
+    ("ja", &["配信停止", "プライバシー"]),
+    ("zh", &["退订", "隐私"]),
+];
+
+lazy_static! {
+    static ref LINK_KEYWORD_RE: Regex = keyword_regex(LINK_KEYWORDS);
+    static ref EXCLUDED_RE: Regex = keyword_regex(EXCLUDED_LINK_KEYWORDS);
+    static ref PATH_KEYWORD_RE: Regex = Regex::new(
+        r"(?i)log-?in|sign-?_?in|magic|verif|confirm|auth|token|passwordless|session|otp"
+    )
+    .expect("Path keyword regex should be valid");
+    static ref PATH_EXCLUDED_RE: Regex =
+        Regex::new(r"(?i)unsubscribe|opt-?out|preferences|privacy|\.(?:png|jpe?g|gif|svg)$")
+            .expect("Path exclusion regex should be valid");
+    static ref URL_RE: Regex =
+        Regex::new(r#"https?://[^\s<>"'()\[\]]+"#).expect("URL regex should be valid");
+}
+
+/// A sign-in or verification link picked out of a message
+#[derive(Debug, Clone, PartialEq)]
+pub struct MagicLink {
+    pub url: String,
+    /// Between 0.0 and 1.0
+    pub confidence: f32,
+}
+
+/// Find the link a message asks the reader to click to sign in or verify.
+/// `links` are the anchors of the HTML body; bare URLs in `body` are
+/// considered too, described by the text around them. Only https links are
+/// returned, and unsubscribe, preference and similar footer links never are.
+pub fn extract_magic_link(subject: &str, body: &str, links: &[Link]) -> Option<MagicLink> {
+    let bonus = if LINK_KEYWORD_RE.is_match(subject) {
+        SUBJECT_BONUS
+    } else {
+        0.0
+    };
+
+    let mut best: Option<(f32, &str)> = None;
+    let candidates = links
+        .iter()
+        .map(|link| (link.url.as_str(), link.text.clone()))
+        .chain(bare_urls(body));
+    for (url, text) in candidates {
+        let Some(score) = score_link(url, &text).filter(|s| *s >= MIN_SCORE) else {
+            continue;
+        };
+        let score = score + bonus;
+        // Ties go to the earlier link
+        if best.is_none_or(|(best_score, _)| score > best_score) {
+            best = Some((score, url));
+        }
+    }
+
+    best.map(|(score, url)| MagicLink {
+        url: url.to_string(),
+        confidence: (score / MAX_SCORE).clamp(0.0, 1.0),
+    })
+}
+
+/// URLs written out in plain text. Their context is the rest of their line
+/// and the line before, where "Click below to sign in:" usually sits.
+fn bare_urls(body: &str) -> Vec<(&str, String)> {
+    let mut urls = Vec::new();
+    let mut previous = "";
+    for line in body.lines() {
+        for m in URL_RE.find_iter(line) {
+            // Trailing punctuation belongs to the sentence
+            let url = m.as_str().trim_end_matches(['.', ',', ';', ':', '!', '?']);
+            let context = format!("{}\n{}{}", previous, &line[..m.start()], &line[m.end()..]);
+            urls.push((url, context));
+        }
+        if !line.trim().is_empty() {
+            previous = line;
+        }
+    }
+    urls
+}
+
+fn score_link(url: &str, text: &str) -> Option<f32> {
+    let parsed = Url::parse(url).ok()?;
+    if parsed.scheme() != "https" || parsed.host_str().is_none() {
+        return None;
+    }
+
+    let path = parsed.path();
+    let query = parsed.query().unwrap_or("");
+    if EXCLUDED_RE.is_match(text) || PATH_EXCLUDED_RE.is_match(path) {
+        return None;
+    }
+
+    let mut score = 0.0;
+    if LINK_KEYWORD_RE.is_match(text) {
+        score += TEXT_SCORE;
+    }
+    if PATH_KEYWORD_RE.is_match(path) || PATH_KEYWORD_RE.is_match(query) {
+        score += PATH_SCORE;
+    }
+    if has_token(path, query) {
+        score += TOKEN_SCORE;
+    }
+    Some(score)
+}
+
+/// Sign-in links carry a long random token in the path or query
+fn has_token(path: &str, query: &str) -> bool {
+    path.split('/')
+        .chain(
+            query
+                .split('&')
+                .filter_map(|pair| pair.split_once('=').map(|(_, v)| v)),
+        )
+        .any(|piece| {
+            piece.len() >= MIN_TOKEN_LEN
+                && piece
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '%'))
+                && piece.chars().any(|c| c.is_ascii_digit())
+        })
+}
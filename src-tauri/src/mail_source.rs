@@ -0,0 +1,162 @@
+use crate::mime::Link;
+use crate::seen_messages::SeenMessages;
+use crate::types::SenderAuth;
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use futures_util::stream::{self, StreamExt};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+pub const RATE_LIMIT_ERROR: &str = "RATE_LIMIT_ERROR";
+const MAX_CONCURRENT_FETCHES: usize = 5;
+
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub id: String,
+    pub from: String,
+    pub subject: String,
+    pub snippet: String,
+    pub body: String,
+    // Link targets from the HTML body, which `body` only has the text of
+    pub links: Vec<Link>,
+    // When the message was sent, in ms since the epoch, if the source says
+    pub sent_at: Option<i64>,
+    pub sender_auth: SenderAuth,
+}
+
+/// An inbox the poll loop can read OTP emails from.
+///
+/// Implementations return `RATE_LIMIT_ERROR` from `list_new_messages` when the
+/// upstream API asks us to slow down, so the poll loop can back off.
+#[async_trait]
+pub trait MailSource: Send + Sync {
+    /// Short identifier used in logs (e.g. "gmail")
+    fn name(&self) -> &'static str;
+
+    /// Address of the signed-in account, used to tag codes when several
+    /// accounts are polled at once
+    fn account(&self) -> Option<String> {
+        None
+    }
+
+    fn is_authenticated(&self) -> bool;
+
+    /// Restore a previous session from the keychain, if any
+    async fn try_restore_auth(&mut self) -> bool;
+
+    /// URL the user is sent to for browser-based sign-in. `state` must be
+    /// passed through so the callback can be matched to this sign-in, and
+    /// `redirect_uri` points at the local callback server.
+    fn get_auth_url(&self, _redirect_uri: &str, _state: &str) -> Result<String, String> {
+        Err(format!("{} does not support browser sign-in", self.name()))
+    }
+
+    /// Complete browser-based sign-in with the code from the OAuth callback
+    async fn exchange_code(&mut self, _code: &str) -> Result<(), String> {
+        Err(format!("{} does not support browser sign-in", self.name()))
+    }
+
+    /// IDs of unread messages that may contain a code
+    async fn list_new_messages(&self) -> Result<Vec<String>, String>;
+
+    async fn fetch_message_detail(&self, msg_id: &str) -> Result<EmailMessage, String>;
+
+    /// Called once every message from the last `list_new_messages` has been
+    /// fetched. Sources that sync from a cursor move it forward here, so a
+    /// message that failed to load is listed again on the next poll.
+    fn commit_listing(&self) {}
+
+    /// Signalled when the server pushes new mail, so the poll loop can wake
+    /// up early instead of waiting out the full interval
+    fn new_mail_notifier(&self) -> Option<Arc<Notify>> {
+        None
+    }
+
+    /// Sign out and forget the stored credentials. Takes `&self` so a source
+    /// can be signed out while a poll still holds it.
+    async fn clear_auth(&self) -> Result<(), String>;
+}
+
+/// Poll every source in parallel, fetching the new messages that aren't
+/// already in `seen` a few at a time. Messages that fail to load are skipped
+/// and retried on the next poll. Results are in the same order as `sources`.
+pub async fn fetch_new_messages(
+    sources: &[&dyn MailSource],
+    seen: &mut SeenMessages,
+) -> Vec<Result<Vec<EmailMessage>, String>> {
+    let results = {
+        let seen = &*seen;
+        join_all(
+            sources
+                .iter()
+                .map(|source| fetch_unseen_messages(*source, seen)),
+        )
+        .await
+    };
+
+    for (source, result) in sources.iter().zip(&results) {
+        if let Ok(messages) = result {
+            let scope = seen_scope(*source);
+            for message in messages {
+                seen.insert(&scope, &message.id);
+            }
+        }
+    }
+    results
+}
+
+/// What a source's message IDs are unique within, for `SeenMessages`: the
+/// source kind and the signed-in account, so two Gmail accounts, or an IMAP
+/// login pointed at another server, never share IDs
+pub fn seen_scope(source: &dyn MailSource) -> String {
+    format!("{}:{}", source.name(), source.account().unwrap_or_default())
+}
+
+async fn fetch_unseen_messages(
+    source: &dyn MailSource,
+    seen: &SeenMessages,
+) -> Result<Vec<EmailMessage>, String> {
+    let scope = seen_scope(source);
+    let ids: Vec<String> = source
+        .list_new_messages()
+        .await?
+        .into_iter()
+        .filter(|id| !seen.contains(&scope, id))
+        .collect();
+
+    let fetched: Vec<(String, Result<EmailMessage, String>)> = stream::iter(ids)
+        .map(|id| async move {
+            let detail = source.fetch_message_detail(&id).await;
+            (id, detail)
+        })
+        .buffered(MAX_CONCURRENT_FETCHES)
+        .collect()
+        .await;
+
+    let mut results = Vec::new();
+    let mut all_fetched = true;
+    for (id, detail) in fetched {
+        match detail {
+            Ok(detail) => results.push(detail),
+            Err(e) => {
+                // SECURITY: Hash message ID to prevent correlation with provider logs
+                let id_hash = hash_message_id(&id);
+                log::warn!("Failed to fetch message {}: {}", id_hash, e);
+                all_fetched = false;
+            }
+        }
+    }
+
+    if all_fetched {
+        source.commit_listing();
+    }
+    Ok(results)
+}
+
+// SECURITY: Hash message IDs for logging to prevent correlation with provider API logs
+pub fn hash_message_id(id: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(id.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
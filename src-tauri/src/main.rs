@@ -7,15 +7,30 @@
 // - Message IDs: Hash or truncate (no Gmail correlation)
 // - Access tokens: Never log, use "[REDACTED]"
 // - Email bodies: Never log full content
+mod expiry;
 mod gmail;
 mod history;
+mod history_query;
+mod imap;
 mod keychain;
+mod magic_link;
+mod mail_source;
+mod mime;
 mod oauth_server;
 mod otp;
+mod outlook;
 mod preferences;
 mod privacy;
+mod providers;
+mod rules;
+mod seen_messages;
+mod sender_auth;
+mod totp;
+mod totp_import;
 mod types;
 
+use mail_source::{EmailMessage, MailSource};
+use std::sync::Arc;
 use tauri::{
     menu::{Menu, MenuItem},
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
@@ -24,13 +39,17 @@ use tauri::{
 use tauri_plugin_clipboard_manager::ClipboardExt;
 use tauri_plugin_notification::NotificationExt;
 use tauri_plugin_opener::OpenerExt;
-use types::{AppState, ClipboardConfig, CodeEntry, PrivacyPreferences};
+use types::{
+    AccountInfo, AppState, ClipboardConfig, CodeEntry, EntryKind, PrivacyPreferences,
+    RetentionPolicy, SenderAuth, UsedCodeDisplay,
+};
 
 const DEFAULT_POLL_INTERVAL_MS: u64 = 8000;
 const NOTIFICATION_COOLDOWN_MS: u64 = 3000;
 const DEFAULT_CLIPBOARD_TIMEOUT_SECONDS: u64 = 30;
 const BASE_BACKOFF_MS: u64 = 60_000; // 1 minute base backoff
 const MAX_BACKOFF_MS: u64 = 300_000; // 5 minutes max backoff
+const RETENTION_SWEEP_INTERVAL_SECS: u64 = 15 * 60;
 
 fn get_poll_interval() -> u64 {
     std::env::var("OTPBAR_POLL_INTERVAL_MS")
@@ -81,14 +100,18 @@ fn main() {
     let loaded_prefs = preferences::load_preferences();
     log::info!("Auto-copy enabled: {}", loaded_prefs.auto_copy_enabled);
 
+    // Loaded before anything can poll, so no code is recorded into an empty
+    // store that this would then replace
+    let loaded_history = history::HistoryStore::load(&loaded_prefs.retention);
+
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_opener::init())
         .manage(AppState {
-            gmail_client: tokio::sync::Mutex::new(None),
-            recent_codes: tokio::sync::Mutex::new(Vec::new()),
+            mail_sources: tokio::sync::Mutex::new(Vec::new()),
+            history: tokio::sync::Mutex::new(loaded_history),
             last_notification: tokio::sync::Mutex::new(0),
             is_polling: tokio::sync::Mutex::new(false),
             clipboard_config: tokio::sync::Mutex::new(ClipboardConfig {
@@ -97,6 +120,8 @@ fn main() {
             privacy_preferences: tokio::sync::Mutex::new(loaded_prefs),
             backoff_until: tokio::sync::Mutex::new(None),
             backoff_logged: tokio::sync::Mutex::new(false),
+            seen_messages: tokio::sync::Mutex::new(seen_messages::SeenMessages::load()),
+            totp_cache: tokio::sync::Mutex::new(totp::TotpCache::default()),
         })
         .setup(|app| {
             setup_menubar(app)?;
@@ -106,19 +131,40 @@ fn main() {
             get_codes,
             get_auth_status,
             start_auth,
+            configure_imap,
+            list_accounts,
+            remove_account,
+            add_totp_account,
+            import_totp_accounts,
+            list_totp_accounts,
+            remove_totp_account,
+            advance_hotp_counter,
+            add_extraction_rule,
+            list_extraction_rules,
+            test_extraction_rule,
+            remove_extraction_rule,
             copy_code,
             copy_code_with_expiry,
+            open_link,
             logout,
             quit_app,
             hide_window,
+            explain_extraction,
             extract_provider,
+            list_providers,
             get_clipboard_config,
             set_clipboard_timeout,
             get_privacy_data,
             clear_history,
+            get_history,
+            search_history,
+            query_history,
             get_preferences,
             set_auto_copy_enabled,
+            set_auto_copy_unverified,
             set_provider_auto_copy,
+            set_retention_policy,
+            set_used_code_display,
         ])
         .on_window_event(|window, event| {
             if let WindowEvent::Focused(is_focused) = event {
@@ -138,12 +184,16 @@ fn setup_menubar(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>>
 
     let handle = app.handle().clone();
 
-    // Load code history from disk
-    let saved_codes = history::load_history();
+    // Keep dropping history entries as they age past the retention policy
     let handle_clone = handle.clone();
     tauri::async_runtime::spawn(async move {
-        let state: State<AppState> = handle_clone.state();
-        *state.recent_codes.lock().await = saved_codes;
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(
+                RETENTION_SWEEP_INTERVAL_SECS,
+            ))
+            .await;
+            enforce_retention(&handle_clone).await;
+        }
     });
 
     // Create quit menu item
@@ -200,24 +250,75 @@ fn setup_menubar(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>>
         })
         .build(app)?;
 
-    // Start Gmail initialization in background
+    // Start mail source initialization in background
     let handle_for_spawn = handle.clone();
     tauri::async_runtime::spawn(async move {
-        if let Ok(mut client) = GmailClient::new().await {
-            if client.try_restore_auth().await {
-                let state: State<AppState> = handle_for_spawn.state();
-                *state.gmail_client.lock().await = Some(client);
-                start_polling(&handle_for_spawn).await;
-            } else {
-                let state: State<AppState> = handle_for_spawn.state();
-                *state.gmail_client.lock().await = Some(client);
+        gmail::migrate_legacy_credentials().await;
+
+        let mut restored = Vec::new();
+        for mut source in saved_mail_sources().await {
+            if source.try_restore_auth().await {
+                restored.push(Arc::from(source));
             }
         }
+
+        if !restored.is_empty() {
+            let state: State<AppState> = handle_for_spawn.state();
+            *state.mail_sources.lock().await = restored;
+            start_polling(&handle_for_spawn).await;
+        }
     });
 
     Ok(())
 }
 
+/// Every account the user has signed in to: the saved IMAP account, a
+/// previously signed-in Outlook account and each Gmail account
+async fn saved_mail_sources() -> Vec<Box<dyn MailSource>> {
+    let mut sources: Vec<Box<dyn MailSource>> = Vec::new();
+
+    if let Some(config) = imap::load_imap_config() {
+        match imap::ImapClient::from_keychain(config) {
+            Ok(client) => sources.push(Box::new(client)),
+            Err(e) => log::warn!("Failed to load IMAP account: {}", e),
+        }
+    }
+
+    if outlook::OutlookClient::has_stored_credentials() {
+        sources.push(Box::new(outlook::OutlookClient::new()));
+    }
+
+    for account in gmail::load_gmail_accounts() {
+        match GmailClient::for_account(&account).await {
+            Ok(client) => sources.push(Box::new(client)),
+            Err(e) => log::warn!("Failed to load Gmail account: {}", e),
+        }
+    }
+
+    sources
+}
+
+/// Add a signed-in source, replacing the one it supersedes. Gmail keeps
+/// credentials per account; other providers only have a single slot.
+async fn install_mail_source(state: &AppState, source: Box<dyn MailSource>) {
+    let mut sources = state.mail_sources.lock().await;
+    sources.retain(|existing| {
+        existing.name() != source.name()
+            || (source.name() == "gmail" && existing.account() != source.account())
+    });
+    sources.push(Arc::from(source));
+}
+
+/// Create an unauthenticated source for a browser sign-in provider
+async fn new_mail_source(provider: &str) -> Result<Box<dyn MailSource>, String> {
+    match provider {
+        "gmail" => Ok(Box::new(GmailClient::new().await?)),
+        "outlook" => Ok(Box::new(outlook::OutlookClient::new())),
+        "imap" => Err("IMAP accounts are set up with configure_imap".to_string()),
+        other => Err(format!("Unknown mail provider: {}", other)),
+    }
+}
+
 async fn start_polling(handle: &tauri::AppHandle) {
     let state: State<AppState> = handle.state();
 
@@ -227,17 +328,36 @@ async fn start_polling(handle: &tauri::AppHandle) {
     }
     *state.is_polling.lock().await = true;
     let poll_interval = get_poll_interval();
-    log::info!("Started Gmail polling (interval: {}ms)", poll_interval);
+    log::info!("Started mail polling (interval: {}ms)", poll_interval);
 
     let handle_clone = handle.clone();
     tauri::async_runtime::spawn(async move {
         let mut retry_count = 0u32;
 
         loop {
-            tokio::time::sleep(tokio::time::Duration::from_millis(poll_interval)).await;
-
             let state: State<AppState> = handle_clone.state();
 
+            let notifiers: Vec<_> = state
+                .mail_sources
+                .lock()
+                .await
+                .iter()
+                .filter_map(|source| source.new_mail_notifier())
+                .collect();
+            if notifiers.is_empty() {
+                tokio::time::sleep(tokio::time::Duration::from_millis(poll_interval)).await;
+            } else {
+                let notified = futures_util::future::select_all(
+                    notifiers.iter().map(|notify| Box::pin(notify.notified())),
+                );
+                tokio::select! {
+                    _ = tokio::time::sleep(tokio::time::Duration::from_millis(poll_interval)) => {}
+                    _ = notified => {
+                        log::info!("Server reported new mail, polling now");
+                    }
+                }
+            }
+
             // Check if we're in backoff period
             let now = chrono::Utc::now().timestamp_millis();
             let backoff_until = *state.backoff_until.lock().await;
@@ -252,119 +372,63 @@ async fn start_polling(handle: &tauri::AppHandle) {
                 log::info!("Rate limit backoff expired, resuming normal polling");
             }
 
-            let mut client_guard = state.gmail_client.lock().await;
+            // Work from a snapshot so signing in or out isn't blocked on the network
+            let sources: Vec<Arc<dyn MailSource>> = state.mail_sources.lock().await.clone();
+            if sources.is_empty() {
+                continue;
+            }
 
-            if let Some(client) = client_guard.as_mut() {
-                match client.get_recent_unread().await {
+            let results = {
+                let sources: Vec<&dyn MailSource> = sources.iter().map(|s| s.as_ref()).collect();
+                let mut seen = state.seen_messages.lock().await;
+                let results = mail_source::fetch_new_messages(&sources, &mut seen).await;
+                seen.save();
+                results
+            };
+
+            let mut rate_limited = None;
+            for (source, result) in sources.iter().zip(results) {
+                match result {
                     Ok(messages) => {
-                        // Reset retry count on success
-                        retry_count = 0;
-
                         for msg in messages {
-                            let text = format!("{} {} {}", msg.subject, msg.snippet, msg.body);
-                            if let Some(otp_code) = otp::extract_otp(&text) {
-                                let mut codes = state.recent_codes.lock().await;
-
-                                let is_duplicate = codes
-                                    .iter()
-                                    .any(|c| c.code == otp_code && c.message_id == msg.id);
-
-                                if !is_duplicate {
-                                    let provider = otp::extract_provider(&msg.from);
-                                    // SECURITY: Never log actual OTP codes - redact with asterisks
-                                    log::info!("OTP detected: ****** from provider {}", provider);
-                                    let entry = CodeEntry {
-                                        code: otp_code.clone(),
-                                        sender: extract_sender_name(&msg.from),
-                                        provider: provider.clone(),
-                                        timestamp: chrono::Utc::now().timestamp_millis(),
-                                        message_id: msg.id,
-                                    };
-
-                                    // Check if auto-copy is enabled for this provider
-                                    let should_auto_copy = {
-                                        let prefs = state.privacy_preferences.lock().await;
-                                        if !prefs.auto_copy_enabled {
-                                            false
-                                        } else {
-                                            prefs
-                                                .provider_auto_copy
-                                                .get(&provider)
-                                                .or_else(|| prefs.provider_auto_copy.get("default"))
-                                                .copied()
-                                                .unwrap_or(true)
-                                        }
-                                    };
-
-                                    if should_auto_copy {
-                                        let timeout = {
-                                            let config = state.clipboard_config.lock().await;
-                                            config.timeout_seconds
-                                        };
-                                        copy_to_clipboard_with_expiry(
-                                            otp_code.clone(),
-                                            handle_clone.clone(),
-                                            timeout,
-                                        )
-                                        .await;
-                                    }
-
-                                    if notifications_enabled() {
-                                        let mut last_notif = state.last_notification.lock().await;
-                                        let now = chrono::Utc::now().timestamp_millis() as u64;
-                                        if now - *last_notif >= NOTIFICATION_COOLDOWN_MS {
-                                            // SECURITY: Don't include OTP code in notification body
-                                            // (visible in notification center and system logs)
-                                            let _ = handle_clone
-                                                .notification()
-                                                .builder()
-                                                .title("OTP Copied")
-                                                .body(format!("Code from {}", entry.sender))
-                                                .show();
-                                            *last_notif = now;
-                                        }
-                                    }
-
-                                    codes.insert(0, entry);
-                                    if codes.len() > 10 {
-                                        codes.truncate(10);
-                                    }
-
-                                    history::save_history(&codes);
-
-                                    if let Some(window) = handle_clone.get_webview_window("main") {
-                                        let _ = window.emit("codes-updated", codes.clone());
-                                    }
-                                }
-                            }
+                            record_message(&handle_clone, msg, source.account()).await;
                         }
                     }
-                    Err(e) if e == gmail::RATE_LIMIT_ERROR => {
-                        // Rate limit error - implement exponential backoff with jitter
-                        let backoff_ms = calculate_backoff(retry_count);
-                        retry_count = retry_count.saturating_add(1);
-
-                        // Add jitter: +/- 25% of backoff time
-                        let jitter_ms = (backoff_ms as f64 * 0.25 * rand::random::<f64>()) as i64
-                            - (backoff_ms as i64 / 4);
-                        let backoff_until = now + backoff_ms as i64 + jitter_ms;
-
-                        *state.backoff_until.lock().await = Some(backoff_until);
-
-                        // Only log once per backoff period
-                        let mut logged = state.backoff_logged.lock().await;
-                        if !*logged {
-                            let backoff_seconds = (backoff_until - now) / 1000;
-                            log::warn!(
-                                "Gmail API rate limit exceeded. Backing off for ~{} seconds. Retry count: {}",
-                                backoff_seconds,
-                                retry_count
-                            );
-                            *logged = true;
-                        }
+                    Err(e) if e == mail_source::RATE_LIMIT_ERROR => {
+                        rate_limited = Some(source.name());
                     }
                     Err(e) => {
-                        log::error!("Gmail polling failed: {}", e);
+                        log::error!("{} polling failed: {}", source.name(), e);
+                    }
+                }
+            }
+
+            match rate_limited {
+                // Reset retry count on success
+                None => retry_count = 0,
+                Some(source_name) => {
+                    // Rate limit error - implement exponential backoff with jitter
+                    let backoff_ms = calculate_backoff(retry_count);
+                    retry_count = retry_count.saturating_add(1);
+
+                    // Add jitter: +/- 25% of backoff time
+                    let jitter_ms = (backoff_ms as f64 * 0.25 * rand::random::<f64>()) as i64
+                        - (backoff_ms as i64 / 4);
+                    let backoff_until = now + backoff_ms as i64 + jitter_ms;
+
+                    *state.backoff_until.lock().await = Some(backoff_until);
+
+                    // Only log once per backoff period
+                    let mut logged = state.backoff_logged.lock().await;
+                    if !*logged {
+                        let backoff_seconds = (backoff_until - now) / 1000;
+                        log::warn!(
+                            "{} API rate limit exceeded. Backing off for ~{} seconds. Retry count: {}",
+                            source_name,
+                            backoff_seconds,
+                            retry_count
+                        );
+                        *logged = true;
                     }
                 }
             }
@@ -372,6 +436,213 @@ async fn start_polling(handle: &tauri::AppHandle) {
     });
 }
 
+/// Extract a code, or failing that a sign-in link, from a freshly fetched
+/// message and surface it
+async fn record_message(handle: &tauri::AppHandle, msg: EmailMessage, account: Option<String>) {
+    let state: State<AppState> = handle.state();
+
+    let body = if msg.body.is_empty() {
+        &msg.snippet
+    } else {
+        &msg.body
+    };
+    // The user's own rules for this sender win over the built-in extractor
+    let otp_match = rules::apply_rules(&rules::load_rules(), &msg.from, &msg.subject, body)
+        .or_else(|| otp::extract_otp_from_message(&msg.subject, body));
+    let (otp_code, display_code, kind, confidence) = if let Some(otp_match) = otp_match {
+        let display_code = (otp_match.display != otp_match.code).then_some(otp_match.display);
+        (
+            otp_match.code,
+            display_code,
+            EntryKind::Code,
+            otp_match.confidence,
+        )
+    } else if let Some(link) = magic_link::extract_magic_link(&msg.subject, body, &msg.links) {
+        (link.url, None, EntryKind::Link, link.confidence)
+    } else {
+        return;
+    };
+
+    let mut history = state.history.lock().await;
+
+    let is_duplicate = history
+        .entries()
+        .iter()
+        .any(|c| c.code == otp_code && c.message_id == msg.id);
+    if is_duplicate {
+        return;
+    }
+
+    let provider = otp::extract_provider(&msg.from);
+    // A display name naming a known provider from an unrelated domain is
+    // shown as unverified, whatever the sender checks said
+    let sender_auth = if providers::catalog().is_lookalike(&msg.from) {
+        log::info!("Sender claims a known provider from another domain");
+        SenderAuth::Unverified
+    } else {
+        msg.sender_auth
+    };
+    let label = match kind {
+        EntryKind::Code => "OTP",
+        EntryKind::Link => "Sign-in link",
+    };
+    // SECURITY: Never log actual OTP codes or links - links carry a token too
+    log::info!(
+        "{} detected: ****** from provider {} (confidence {:.2})",
+        label,
+        provider,
+        confidence
+    );
+
+    // The stated lifetime runs from when the message was sent, which can be
+    // well before it reaches us
+    let now = chrono::Utc::now().timestamp_millis();
+    let expires_at = expiry::stated_validity(&format!("{}\n{}", msg.subject, body))
+        .map(|seconds| msg.sent_at.unwrap_or(now).min(now) + seconds as i64 * 1000);
+    let is_stale = expires_at.is_some_and(|t| t <= now);
+    if is_stale {
+        log::info!("{} arrived after it expired; not auto-copying", label);
+    }
+
+    let entry = CodeEntry {
+        code: otp_code.clone(),
+        sender: extract_sender_name(&msg.from),
+        provider: provider.clone(),
+        timestamp: now,
+        message_id: msg.id,
+        account,
+        remaining_seconds: None,
+        display_code,
+        kind,
+        expires_at,
+        expired: false,
+        sender_auth,
+        used_at: None,
+    };
+
+    // Links are opened rather than pasted, so they are never auto-copied
+    let should_auto_copy = kind == EntryKind::Code && !is_stale && {
+        let prefs = state.privacy_preferences.lock().await;
+        if !prefs.auto_copy_enabled {
+            false
+        } else if sender_auth == SenderAuth::Unverified && !prefs.auto_copy_unverified {
+            log::info!("Sender failed authentication checks; not auto-copying");
+            false
+        } else {
+            prefs
+                .provider_auto_copy
+                .get(&provider)
+                .or_else(|| prefs.provider_auto_copy.get("default"))
+                .copied()
+                .unwrap_or(true)
+        }
+    };
+
+    if should_auto_copy {
+        let timeout = {
+            let config = state.clipboard_config.lock().await;
+            config.timeout_seconds
+        };
+        copy_to_clipboard_with_expiry(otp_code.clone(), handle.clone(), timeout).await;
+    }
+
+    if notifications_enabled() {
+        let mut last_notif = state.last_notification.lock().await;
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+        if now - *last_notif >= NOTIFICATION_COOLDOWN_MS {
+            // SECURITY: Don't include OTP code in notification body
+            // (visible in notification center and system logs)
+            let (title, what) = match kind {
+                EntryKind::Code => ("OTP Copied", "Code"),
+                EntryKind::Link => ("Sign-in Link", "Link"),
+            };
+            let _ = handle
+                .notification()
+                .builder()
+                .title(title)
+                .body(format!("{} from {}", what, entry.sender))
+                .show();
+            *last_notif = now;
+        }
+    }
+
+    let prefs = state.privacy_preferences.lock().await.clone();
+    history.append(entry, &prefs.retention);
+
+    if let Some(window) = handle.get_webview_window("main") {
+        let _ = window.emit(
+            "codes-updated",
+            visible_codes(&state, &history, &prefs).await,
+        );
+    }
+}
+
+/// Drop codes the retention policy no longer allows, on disk and in the list
+async fn enforce_retention(handle: &tauri::AppHandle) {
+    let state: State<AppState> = handle.state();
+    let prefs = state.privacy_preferences.lock().await.clone();
+    let mut history = state.history.lock().await;
+    let now = chrono::Utc::now().timestamp_millis();
+    let removed = history.enforce_retention(&prefs.retention, now);
+
+    if removed > 0 {
+        log::info!("Removed {} codes past the retention policy", removed);
+        if let Some(window) = handle.get_webview_window("main") {
+            let _ = window.emit(
+                "codes-updated",
+                visible_codes(&state, &history, &prefs).await,
+            );
+        }
+    }
+}
+
+/// Mark the history entry the user copied a code from as used, and tell the
+/// UI. Authenticator codes have no history entry, so nothing is marked.
+async fn mark_code_used(message_id: Option<String>, handle: &tauri::AppHandle) {
+    let Some(message_id) = message_id else {
+        return;
+    };
+    let state: State<AppState> = handle.state();
+    let policy = state.privacy_preferences.lock().await.retention.clone();
+    let now = chrono::Utc::now().timestamp_millis();
+    let marked = state
+        .history
+        .lock()
+        .await
+        .mark_used(&message_id, now, &policy);
+
+    if marked {
+        log::info!("Marked a code as used");
+        if let Some(window) = handle.get_webview_window("main") {
+            let _ = window.emit("code-used", vec![message_id]);
+        }
+    }
+}
+
+/// The list shown in the menubar: current authenticator codes, then the
+/// newest email codes with those past their stated expiry marked, leaving
+/// out used ones when the user hides them
+async fn visible_codes(
+    state: &AppState,
+    history: &history::HistoryStore,
+    prefs: &PrivacyPreferences,
+) -> Vec<CodeEntry> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let include_used = prefs.used_codes == UsedCodeDisplay::Dim;
+
+    let mut codes = state.totp_cache.lock().await.current_codes(now);
+    codes.extend(
+        history
+            .recent(prefs.retention.recent_count, include_used)
+            .into_iter()
+            .map(|entry| CodeEntry {
+                expired: entry.expires_at.is_some_and(|t| t <= now),
+                ..entry
+            }),
+    );
+    codes
+}
+
 /// Calculate exponential backoff with a maximum cap
 fn calculate_backoff(retry_count: u32) -> u64 {
     let backoff = BASE_BACKOFF_MS * 2u64.pow(retry_count.min(6));
@@ -411,31 +682,45 @@ async fn copy_to_clipboard_with_expiry(
 // Tauri commands - must return Result for async commands with State
 #[tauri::command]
 async fn get_codes(state: State<'_, AppState>) -> Result<Vec<CodeEntry>, ()> {
-    Ok(state.recent_codes.lock().await.clone())
+    let prefs = state.privacy_preferences.lock().await.clone();
+    Ok(visible_codes(&state, &*state.history.lock().await, &prefs).await)
 }
 
 #[tauri::command]
 async fn get_auth_status(state: State<'_, AppState>) -> Result<bool, ()> {
     Ok(state
-        .gmail_client
+        .mail_sources
         .lock()
         .await
-        .as_ref()
-        .map(|c| c.is_authenticated())
-        .unwrap_or(false))
+        .iter()
+        .any(|source| source.is_authenticated()))
 }
 
 #[tauri::command]
 async fn start_auth(
+    provider: Option<String>,
     state: State<'_, AppState>,
     window: tauri::Window,
 ) -> Result<types::AuthResult, String> {
-    let mut client_guard = state.gmail_client.lock().await;
-    let client = client_guard.as_mut().ok_or("No Gmail client")?;
+    let provider = match provider {
+        Some(provider) => provider,
+        None => state
+            .mail_sources
+            .lock()
+            .await
+            .first()
+            .map(|source| source.name().to_string())
+            .unwrap_or_else(|| "gmail".to_string()),
+    };
 
-    let auth_url = client.get_auth_url();
+    // Run the browser flow on a fresh source so the shared one isn't locked
+    // while we wait for the user
+    let mut source = new_mail_source(&provider).await?;
 
-    let mut oauth_server = oauth_server::OAuthServer::start(8234).await?;
+    // Bind any free loopback port; both providers accept any port on a loopback redirect
+    let oauth_state = oauth_server::generate_state();
+    let mut oauth_server = oauth_server::OAuthServer::start(0, &oauth_state).await?;
+    let auth_url = source.get_auth_url(&oauth_server.redirect_uri(), &oauth_state)?;
 
     window
         .app_handle()
@@ -445,10 +730,11 @@ async fn start_auth(
 
     let code = oauth_server.wait_for_code().await?;
 
-    client.exchange_code(&code).await?;
+    source.exchange_code(&code).await?;
+
+    install_mail_source(&state, source).await;
 
     let handle = window.app_handle().clone();
-    drop(client_guard);
     start_polling(&handle).await;
 
     Ok(types::AuthResult {
@@ -458,16 +744,173 @@ async fn start_auth(
 }
 
 #[tauri::command]
-async fn copy_code(code: String, app: tauri::AppHandle) -> Result<bool, String> {
+async fn configure_imap(
+    config: imap::ImapConfig,
+    secret: String,
+    state: State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<types::AuthResult, String> {
+    let mut client = imap::ImapClient::new(config, secret);
+    client.connect().await?;
+    client.save()?;
+
+    install_mail_source(&state, Box::new(client)).await;
+    start_polling(&app).await;
+
+    Ok(types::AuthResult {
+        success: true,
+        error: None,
+    })
+}
+
+#[tauri::command]
+async fn list_accounts(state: State<'_, AppState>) -> Result<Vec<AccountInfo>, ()> {
+    Ok(state
+        .mail_sources
+        .lock()
+        .await
+        .iter()
+        .map(|source| AccountInfo {
+            provider: source.name().to_string(),
+            account: source.account(),
+            authenticated: source.is_authenticated(),
+        })
+        .collect())
+}
+
+/// Sign out of a single account and drop the codes read from it
+#[tauri::command]
+async fn remove_account(
+    provider: String,
+    account: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<bool, String> {
+    let mut sources = state.mail_sources.lock().await;
+    let index = sources
+        .iter()
+        .position(|source| source.name() == provider && source.account() == account)
+        .ok_or("Account not found")?;
+
+    let source = sources.remove(index);
+    drop(sources);
+    source.clear_auth().await?;
+
+    if account.is_some() {
+        let policy = state.privacy_preferences.lock().await.retention.clone();
+        state
+            .history
+            .lock()
+            .await
+            .retain(|c| c.account != account, &policy);
+    }
+    Ok(true)
+}
+
+/// Import an authenticator account from an `otpauth://` URI
+#[tauri::command]
+async fn add_totp_account(
+    uri: String,
+    state: State<'_, AppState>,
+) -> Result<totp::TotpAccount, String> {
+    let account = totp::add_totp_account(totp::parse_otpauth_uri(&uri)?)?;
+    state.totp_cache.lock().await.invalidate();
+    Ok(account)
+}
+
+/// Import authenticator accounts from a Google Authenticator migration URI
+/// or from the text or file contents of an Aegis/andOTP export
+#[tauri::command]
+async fn import_totp_accounts(
+    data: Vec<u8>,
+    password: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<totp_import::ImportSummary, String> {
+    // Encrypted exports take a deliberately slow key derivation; keep it off
+    // the thread that drives the UI
+    let imported = tauri::async_runtime::spawn_blocking(move || {
+        totp_import::parse_export(&data, password.as_deref())
+    })
+    .await
+    .map_err(|e| format!("Failed to read export: {}", e))??;
+    if imported.accounts.is_empty() {
+        return Err(match imported.skipped.first() {
+            Some(reason) => format!("No supported accounts found in export ({})", reason),
+            None => "No supported accounts found in export".to_string(),
+        });
+    }
+
+    let accounts = totp::add_totp_accounts(imported.accounts)?;
+    state.totp_cache.lock().await.invalidate();
+    Ok(totp_import::ImportSummary {
+        accounts,
+        skipped: imported.skipped,
+    })
+}
+
+#[tauri::command]
+fn list_totp_accounts() -> Vec<totp::TotpAccount> {
+    totp::load_totp_accounts()
+}
+
+#[tauri::command]
+async fn remove_totp_account(id: String, state: State<'_, AppState>) -> Result<(), String> {
+    let result = totp::remove_totp_account(&id);
+    state.totp_cache.lock().await.invalidate();
+    result
+}
+
+#[tauri::command]
+async fn advance_hotp_counter(id: String, state: State<'_, AppState>) -> Result<(), String> {
+    totp::advance_hotp_counter(&id)?;
+    state.totp_cache.lock().await.invalidate();
+    Ok(())
+}
+
+/// Add a sender-specific extraction rule
+#[tauri::command]
+fn add_extraction_rule(
+    sender: String,
+    pattern: rules::RulePattern,
+) -> Result<rules::ExtractionRule, String> {
+    rules::add_rule(&sender, pattern)
+}
+
+#[tauri::command]
+fn list_extraction_rules() -> Vec<rules::ExtractionRule> {
+    rules::load_rules()
+}
+
+/// Try a rule pattern on a pasted sample without saving it
+#[tauri::command]
+fn test_extraction_rule(
+    pattern: rules::RulePattern,
+    sample: String,
+) -> Result<Option<String>, String> {
+    rules::test_rule(&pattern, &sample)
+}
+
+#[tauri::command]
+fn remove_extraction_rule(id: String) -> Result<(), String> {
+    rules::remove_rule(&id)
+}
+
+#[tauri::command]
+async fn copy_code(
+    code: String,
+    message_id: Option<String>,
+    app: tauri::AppHandle,
+) -> Result<bool, String> {
     app.clipboard()
-        .write_text(code)
+        .write_text(code.clone())
         .map_err(|e| e.to_string())?;
+    mark_code_used(message_id, &app).await;
     Ok(true)
 }
 
 #[tauri::command]
 async fn copy_code_with_expiry(
     code: String,
+    message_id: Option<String>,
     app: tauri::AppHandle,
     state: State<'_, AppState>,
 ) -> Result<bool, String> {
@@ -479,6 +922,7 @@ async fn copy_code_with_expiry(
     app.clipboard()
         .write_text(code.clone())
         .map_err(|e| format!("Failed to write to clipboard: {}", e))?;
+    mark_code_used(message_id, &app).await;
 
     let app_clone = app.clone();
     tauri::async_runtime::spawn(async move {
@@ -493,6 +937,29 @@ async fn copy_code_with_expiry(
     Ok(true)
 }
 
+/// Open a detected sign-in link in the browser. Only links found in fetched
+/// mail can be opened, so the webview can't launch arbitrary URLs.
+#[tauri::command]
+async fn open_link(
+    message_id: String,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let url = state
+        .history
+        .lock()
+        .await
+        .entries()
+        .iter()
+        .find(|c| c.kind == EntryKind::Link && c.message_id == message_id)
+        .map(|c| c.code.clone())
+        .ok_or_else(|| "Link not found".to_string())?;
+
+    app.opener()
+        .open_url(&url, None::<String>)
+        .map_err(|e| format!("Failed to open link: {}", e))
+}
+
 #[tauri::command]
 async fn get_clipboard_config(state: State<'_, AppState>) -> Result<ClipboardConfig, String> {
     Ok(state.clipboard_config.lock().await.clone())
@@ -511,12 +978,17 @@ async fn set_clipboard_timeout(
 
 #[tauri::command]
 async fn logout(state: State<'_, AppState>, _app: tauri::AppHandle) -> Result<bool, String> {
-    let mut client_guard = state.gmail_client.lock().await;
-    if let Some(client) = client_guard.as_mut() {
-        client.clear_auth().await.map_err(|e| e.to_string())?;
+    let sources = state.mail_sources.lock().await.clone();
+    for source in &sources {
+        source.clear_auth().await.map_err(|e| e.to_string())?;
     }
-    state.recent_codes.lock().await.clear();
-    history::save_history(&[]);
+    state
+        .mail_sources
+        .lock()
+        .await
+        .retain(|kept| !sources.iter().any(|source| Arc::ptr_eq(kept, source)));
+    state.history.lock().await.clear()?;
+    state.seen_messages.lock().await.clear();
     Ok(true)
 }
 
@@ -530,21 +1002,73 @@ async fn hide_window(window: tauri::Window) -> Result<(), String> {
     window.hide().map_err(|e| e.to_string())
 }
 
+/// Every candidate the extractor considered in `text`, with scores and
+/// rejection reasons, for debugging missed or wrong codes
+#[tauri::command]
+fn explain_extraction(text: String) -> Vec<otp::CandidateExplanation> {
+    otp::explain_extraction(&text)
+}
+
 #[tauri::command]
 fn extract_provider(sender: String) -> String {
     otp::extract_provider(&sender)
 }
 
+/// The provider catalog, for icons and colours next to each code
 #[tauri::command]
-fn get_privacy_data() -> Result<privacy::PrivacyData, String> {
-    privacy::get_privacy_data()
+fn list_providers() -> Vec<providers::Provider> {
+    providers::catalog().providers().to_vec()
+}
+
+#[tauri::command]
+async fn get_privacy_data(state: State<'_, AppState>) -> Result<privacy::PrivacyData, String> {
+    let policy = state.privacy_preferences.lock().await.retention.clone();
+    privacy::get_privacy_data(&policy, state.history.lock().await.entries())
 }
 
 #[tauri::command]
 async fn clear_history(state: State<'_, AppState>) -> Result<(), String> {
-    privacy::clear_history()?;
-    state.recent_codes.lock().await.clear();
-    Ok(())
+    privacy::clear_history(&mut *state.history.lock().await)
+}
+
+/// A page of the full history, newest first
+#[tauri::command]
+async fn get_history(
+    offset: usize,
+    limit: usize,
+    state: State<'_, AppState>,
+) -> Result<history_query::HistoryPage, ()> {
+    Ok(history_query::query_history(
+        state.history.lock().await.entries(),
+        &history_query::HistoryQuery::page(offset, limit),
+    ))
+}
+
+/// A page of history entries whose sender, provider or account contains
+/// `query`
+#[tauri::command]
+async fn search_history(
+    query: String,
+    offset: usize,
+    limit: usize,
+    state: State<'_, AppState>,
+) -> Result<history_query::HistoryPage, ()> {
+    Ok(history_query::query_history(
+        state.history.lock().await.entries(),
+        &history_query::HistoryQuery::search(&query, offset, limit),
+    ))
+}
+
+/// Filter, sort and page the history, e.g. Stripe codes from this morning
+#[tauri::command]
+async fn query_history(
+    query: history_query::HistoryQuery,
+    state: State<'_, AppState>,
+) -> Result<history_query::HistoryPage, ()> {
+    Ok(history_query::query_history(
+        state.history.lock().await.entries(),
+        &query,
+    ))
 }
 
 #[tauri::command]
@@ -561,6 +1085,82 @@ async fn set_auto_copy_enabled(enabled: bool, state: State<'_, AppState>) -> Res
     Ok(())
 }
 
+#[tauri::command]
+async fn set_auto_copy_unverified(enabled: bool, state: State<'_, AppState>) -> Result<(), String> {
+    let mut prefs = state.privacy_preferences.lock().await;
+    prefs.auto_copy_unverified = enabled;
+    preferences::save_preferences(&prefs);
+    log::info!("Auto-copy from unverified senders: {}", enabled);
+    Ok(())
+}
+
+/// Change how long and how much history is kept, applying it right away
+#[tauri::command]
+async fn set_retention_policy(
+    policy: RetentionPolicy,
+    state: State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    if policy.max_count == 0 || policy.max_count > history::MAX_HISTORY_LIMIT {
+        return Err(format!(
+            "History size must be between 1 and {}",
+            history::MAX_HISTORY_LIMIT
+        ));
+    }
+    if policy.max_age_days == Some(0) {
+        return Err("Retention period must be at least one day".to_string());
+    }
+    if policy.recent_count == 0 {
+        return Err("The menubar list must show at least one code".to_string());
+    }
+
+    let prefs = {
+        let mut prefs = state.privacy_preferences.lock().await;
+        prefs.retention = policy.clone();
+        preferences::save_preferences(&prefs);
+        prefs.clone()
+    };
+    log::info!("Retention policy updated");
+
+    // Rewrite the log even when nothing expired, so switching to metadata
+    // only removes codes already on disk
+    let mut history = state.history.lock().await;
+    history.enforce_retention(&policy, chrono::Utc::now().timestamp_millis());
+    history.compact(&policy)?;
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.emit(
+            "codes-updated",
+            visible_codes(&state, &history, &prefs).await,
+        );
+    }
+    Ok(())
+}
+
+/// Dim or hide codes once they have been copied
+#[tauri::command]
+async fn set_used_code_display(
+    display: UsedCodeDisplay,
+    state: State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    let prefs = {
+        let mut prefs = state.privacy_preferences.lock().await;
+        prefs.used_codes = display;
+        preferences::save_preferences(&prefs);
+        prefs.clone()
+    };
+    log::info!("Used code display: {:?}", display);
+
+    if let Some(window) = app.get_webview_window("main") {
+        let history = state.history.lock().await;
+        let _ = window.emit(
+            "codes-updated",
+            visible_codes(&state, &history, &prefs).await,
+        );
+    }
+    Ok(())
+}
+
 #[tauri::command]
 async fn set_provider_auto_copy(
     provider: String,
@@ -0,0 +1,408 @@
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+// Deeper nesting than this is treated as malformed
+const MAX_MULTIPART_DEPTH: usize = 8;
+
+lazy_static! {
+    static ref COMMENT_RE: Regex = Regex::new(r"(?s)<!--.*?-->").expect("Comment regex should be valid");
+    static ref HIDDEN_BLOCK_RE: Regex = Regex::new(
+        r"(?is)<(?:script|style|head|template)\b.*?</(?:script|style|head|template)\s*>"
+    )
+    .expect("Hidden block regex should be valid");
+    static ref BLOCK_TAG_RE: Regex = Regex::new(
+        r"(?i)<\s*/?\s*(?:br|p|div|tr|li|ul|ol|table|h[1-6]|blockquote|section|article|header|footer)\b[^>]*>"
+    )
+    .expect("Block tag regex should be valid");
+    static ref CELL_TAG_RE: Regex =
+        Regex::new(r"(?i)<\s*/\s*(?:td|th)\s*>").expect("Cell tag regex should be valid");
+    static ref TAG_RE: Regex = Regex::new(r"(?s)<[^>]*>").expect("Tag regex should be valid");
+    static ref ANCHOR_RE: Regex =
+        Regex::new(r"(?is)<a\b([^>]*)>(.*?)</a\s*>").expect("Anchor regex should be valid");
+    static ref HREF_RE: Regex =
+        Regex::new(r#"(?is)\bhref\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#)
+            .expect("Href regex should be valid");
+    static ref ENTITY_RE: Regex =
+        Regex::new(r"&(#[0-9]{1,7}|#[xX][0-9a-fA-F]{1,6}|[a-zA-Z]{2,8});")
+            .expect("Entity regex should be valid");
+}
+
+/// A link from an HTML body. `html_to_text` keeps only the link text, so the
+/// targets are collected separately.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub url: String,
+    pub text: String,
+}
+
+/// The readable text of a message, collected part by part. The first
+/// `text/plain` part wins; HTML is only used when there is no plain text.
+#[derive(Default)]
+pub struct BodyText {
+    plain: Option<String>,
+    html: Option<String>,
+}
+
+impl BodyText {
+    /// Record a decoded text part of the given MIME type
+    pub fn add(&mut self, mime_type: &str, text: String) {
+        if text.trim().is_empty() {
+            return;
+        }
+        match mime_type.to_ascii_lowercase().as_str() {
+            "text/plain" if self.plain.is_none() => self.plain = Some(text),
+            "text/html" if self.html.is_none() => self.html = Some(text),
+            _ => {}
+        }
+    }
+
+    /// Links in the HTML part, if there is one
+    pub fn links(&self) -> Vec<Link> {
+        self.html.as_deref().map(html_links).unwrap_or_default()
+    }
+
+    pub fn into_text(self) -> String {
+        match (self.plain, self.html) {
+            (Some(plain), _) => plain,
+            (None, Some(html)) => html_to_text(&html),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// The text parts of a raw MIME entity (a whole message or one of its parts),
+/// given its headers and undecoded body
+pub fn message_body(headers: &[(String, String)], body: &[u8]) -> BodyText {
+    let mut text = BodyText::default();
+    collect_text(headers, body, &mut text, 0);
+    text
+}
+
+fn collect_text(headers: &[(String, String)], body: &[u8], out: &mut BodyText, depth: usize) {
+    let is_attachment = header(headers, "content-disposition")
+        .is_some_and(|d| d.trim().to_ascii_lowercase().starts_with("attachment"));
+    if is_attachment {
+        return;
+    }
+
+    let (mime_type, params) = parse_content_type(header(headers, "content-type"));
+
+    if mime_type.starts_with("multipart/") {
+        let Some(boundary) = param(&params, "boundary") else {
+            return;
+        };
+        if depth >= MAX_MULTIPART_DEPTH {
+            return;
+        }
+        for part in split_multipart(body, boundary) {
+            let (head, part_body) = split_message(part);
+            let part_headers = parse_headers(&String::from_utf8_lossy(head));
+            collect_text(&part_headers, part_body, out, depth + 1);
+        }
+        return;
+    }
+
+    if mime_type == "text/plain" || mime_type == "text/html" {
+        let decoded = decode_transfer_encoding(header(headers, "content-transfer-encoding"), body);
+        out.add(
+            &mime_type,
+            decode_charset(&decoded, param(&params, "charset")),
+        );
+    }
+}
+
+/// Split a raw message or part into its header block and body
+pub fn split_message(raw: &[u8]) -> (&[u8], &[u8]) {
+    // A part with no headers starts with the blank line
+    if let Some(body) = raw.strip_prefix(b"\r\n") {
+        return (&[], body);
+    }
+    if let Some(body) = raw.strip_prefix(b"\n") {
+        return (&[], body);
+    }
+
+    match find(raw, b"\r\n\r\n") {
+        Some(pos) => (&raw[..pos], &raw[pos + 4..]),
+        None => match find(raw, b"\n\n") {
+            Some(pos) => (&raw[..pos], &raw[pos + 2..]),
+            None => (raw, &[]),
+        },
+    }
+}
+
+/// Parse a header block into lowercase names and unfolded values, in order
+pub fn parse_headers(head: &str) -> Vec<(String, String)> {
+    let mut current: Option<(String, String)> = None;
+    let mut headers = Vec::new();
+
+    for line in head.lines() {
+        if line.starts_with([' ', '\t']) {
+            // Folded continuation of the previous header
+            if let Some((_, value)) = current.as_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+        if let Some(done) = current.take() {
+            headers.push(done);
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            current = Some((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+    headers.extend(current);
+    headers
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// Split a `Content-Type` value into its lowercase MIME type and parameters.
+/// A missing header means `text/plain`.
+pub fn parse_content_type(value: Option<&str>) -> (String, Vec<(String, String)>) {
+    let Some(value) = value else {
+        return ("text/plain".to_string(), Vec::new());
+    };
+
+    let mut pieces = value.split(';');
+    let mime_type = pieces
+        .next()
+        .map(|t| t.trim().to_ascii_lowercase())
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| "text/plain".to_string());
+    let params = pieces
+        .filter_map(|piece| {
+            let (key, value) = piece.split_once('=')?;
+            Some((
+                key.trim().to_ascii_lowercase(),
+                value.trim().trim_matches('"').to_string(),
+            ))
+        })
+        .collect();
+
+    (mime_type, params)
+}
+
+/// The `charset` parameter of a `Content-Type` value
+pub fn content_type_charset(value: Option<&str>) -> Option<String> {
+    let (_, params) = parse_content_type(value);
+    param(&params, "charset").map(str::to_string)
+}
+
+fn param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// The parts between the `--boundary` lines of a multipart body
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let delimiter = delimiter.as_bytes();
+
+    let mut positions = Vec::new();
+    let mut offset = 0;
+    while let Some(pos) = find(&body[offset..], delimiter) {
+        let pos = offset + pos;
+        if pos == 0 || body[pos - 1] == b'\n' {
+            positions.push(pos);
+        }
+        offset = pos + delimiter.len();
+    }
+
+    let mut parts = Vec::new();
+    for (i, &pos) in positions.iter().enumerate() {
+        let after = pos + delimiter.len();
+        if body[after..].starts_with(b"--") {
+            break;
+        }
+        // The rest of the delimiter line isn't part of the content
+        let Some(line_end) = body[after..].iter().position(|&b| b == b'\n') else {
+            break;
+        };
+        let start = after + line_end + 1;
+        let mut end = positions.get(i + 1).copied().unwrap_or(body.len());
+        // The line break before the next delimiter belongs to it
+        if end > start && body[end - 1] == b'\n' {
+            end -= 1;
+            if end > start && body[end - 1] == b'\r' {
+                end -= 1;
+            }
+        }
+        parts.push(&body[start..end.max(start)]);
+    }
+    parts
+}
+
+fn decode_transfer_encoding(encoding: Option<&str>, body: &[u8]) -> Vec<u8> {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+
+    match encoding.map(|e| e.trim().to_ascii_lowercase()).as_deref() {
+        Some("base64") => {
+            let compact: Vec<u8> = body
+                .iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect();
+            STANDARD.decode(&compact).unwrap_or_else(|_| body.to_vec())
+        }
+        Some("quoted-printable") => decode_quoted_printable(body),
+        _ => body.to_vec(),
+    }
+}
+
+/// Decode a quoted-printable body, joining soft line breaks
+pub fn decode_quoted_printable(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+
+    while i < input.len() {
+        if input[i] != b'=' {
+            out.push(input[i]);
+            i += 1;
+            continue;
+        }
+
+        let rest = &input[i + 1..];
+        if rest.starts_with(b"\r\n") {
+            i += 3;
+        } else if rest.starts_with(b"\n") {
+            i += 2;
+        } else if let Some(byte) = rest
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(b'=');
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Decode text in the named charset, falling back to UTF-8 for missing or
+/// unknown labels
+pub fn decode_charset(bytes: &[u8], charset: Option<&str>) -> String {
+    let encoding = charset
+        .and_then(|label| encoding_rs::Encoding::for_label(label.trim().as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    encoding.decode(bytes).0.into_owned()
+}
+
+/// Readable text from an HTML body: scripts, styles and comments dropped,
+/// block elements on their own lines, entities decoded
+pub fn html_to_text(html: &str) -> String {
+    let text = COMMENT_RE.replace_all(html, "");
+    let text = HIDDEN_BLOCK_RE.replace_all(&text, "");
+    let text = BLOCK_TAG_RE.replace_all(&text, "\n");
+    let text = CELL_TAG_RE.replace_all(&text, " ");
+    let text = TAG_RE.replace_all(&text, "");
+    let text = decode_html_entities(&text);
+
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+    lines.join("\n")
+}
+
+/// The `<a href>` links of an HTML body, in order, with entity-decoded
+/// targets and their visible text
+pub fn html_links(html: &str) -> Vec<Link> {
+    let html = COMMENT_RE.replace_all(html, "");
+    let html = HIDDEN_BLOCK_RE.replace_all(&html, "");
+
+    ANCHOR_RE
+        .captures_iter(&html)
+        .filter_map(|caps| {
+            let href = HREF_RE.captures(&caps[1])?;
+            let raw = href.get(1).or(href.get(2)).or(href.get(3))?.as_str();
+            let url = decode_html_entities(raw).trim().to_string();
+            if url.is_empty() || url.starts_with('#') {
+                return None;
+            }
+            let text = html_to_text(&caps[2])
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            Some(Link { url, text })
+        })
+        .collect()
+}
+
+/// Decode named and numeric HTML character references
+pub fn decode_html_entities(text: &str) -> String {
+    ENTITY_RE
+        .replace_all(text, |caps: &Captures| {
+            let entity = &caps[1];
+            let decoded = if let Some(hex) = entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+            {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+            } else if let Some(decimal) = entity.strip_prefix('#') {
+                decimal.parse().ok().and_then(char::from_u32)
+            } else {
+                named_entity(entity)
+            };
+
+            match decoded {
+                // Invisible spacers used to pad email previews
+                Some('\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{FEFF}' | '\u{034F}') => {
+                    String::new()
+                }
+                Some('\u{00A0}') => " ".to_string(),
+                Some(c) => c.to_string(),
+                None => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+fn named_entity(name: &str) -> Option<char> {
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{00A0}',
+        "zwnj" => '\u{200C}',
+        "zwj" => '\u{200D}',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "hellip" => '…',
+        "ndash" => '–',
+        "mdash" => '—',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "bull" => '•',
+        "middot" => '·',
+        "euro" => '€',
+        "pound" => '£',
+        "yen" => '¥',
+        _ => return None,
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
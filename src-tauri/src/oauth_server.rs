@@ -1,7 +1,10 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use http_body_util::Full;
 use hyper::Method;
 use hyper::{server::conn::http1, service::service_fn, Request, Response, StatusCode};
 use hyper_util::rt::TokioIo;
+use sha2::{Digest, Sha256};
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 use tokio::net::TcpListener;
@@ -9,6 +12,41 @@ use tokio::sync::oneshot;
 
 type BoxBody = http_body_util::Full<hyper::body::Bytes>;
 
+/// PKCE (RFC 7636) code verifier and its S256 challenge
+pub struct Pkce {
+    pub verifier: String,
+    pub challenge: String,
+}
+
+impl Pkce {
+    pub fn generate() -> Self {
+        let verifier = random_token();
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        Pkce {
+            verifier,
+            challenge,
+        }
+    }
+}
+
+/// Random value for the OAuth `state` parameter, checked by the callback so a
+/// code injected by another site is never exchanged
+pub fn generate_state() -> String {
+    random_token()
+}
+
+fn random_token() -> String {
+    let bytes: [u8; 32] = rand::random();
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 fn html_response(html: String) -> Response<BoxBody> {
     Response::builder()
         .status(StatusCode::OK)
@@ -156,6 +194,7 @@ fn error_html(error: &str) -> String {
 async fn handle_callback(
     req: Request<hyper::body::Incoming>,
     tx: Arc<Mutex<Option<oneshot::Sender<String>>>>,
+    expected_state: Arc<String>,
 ) -> Result<Response<BoxBody>, hyper::Error> {
     let path = req.uri().path();
     let query = req.uri().query().unwrap_or("");
@@ -165,11 +204,21 @@ async fn handle_callback(
             .split('&')
             .filter_map(|s| {
                 let mut parts = s.splitn(2, '=');
-                Some((parts.next()?.to_string(), parts.next()?.to_string()))
+                let key = parts.next()?;
+                let value = urlencoding::decode(parts.next()?).ok()?;
+                Some((key.to_string(), value.into_owned()))
             })
             .collect();
 
         if let Some(code) = params.get("code") {
+            // Only release a code that belongs to the sign-in we started
+            if params.get("state") != Some(&*expected_state) {
+                log::warn!("OAuth callback rejected: state mismatch");
+                return Ok(html_response(error_html(
+                    "This sign-in link is invalid or has expired. Please try again.",
+                )));
+            }
+
             if let Some(tx) = tx.lock().unwrap().take() {
                 let _ = tx.send(code.clone());
             }
@@ -177,7 +226,7 @@ async fn handle_callback(
         }
 
         if let Some(error) = params.get("error") {
-            return Ok(html_response(error_html(error)));
+            return Ok(html_response(error_html(&html_escape(error))));
         }
 
         return Ok(html_response(error_html("No authorization code received")));
@@ -189,32 +238,38 @@ async fn handle_callback(
         .unwrap())
 }
 
+/// What a provider keeps between building the auth URL and exchanging the code
+pub struct PendingAuth {
+    pub verifier: String,
+    pub redirect_uri: String,
+}
+
 pub struct OAuthServer {
+    port: u16,
     shutdown_tx: Option<oneshot::Sender<()>>,
     code_rx: oneshot::Receiver<String>,
 }
 
 impl OAuthServer {
-    pub async fn start(port: u16) -> Result<Self, String> {
+    /// Listen for the OAuth redirect on `port`, or on any free port when it is
+    /// 0. Only a callback carrying `expected_state` releases its code to
+    /// `wait_for_code`.
+    pub async fn start(port: u16, expected_state: &str) -> Result<Self, String> {
+        let expected_state = Arc::new(expected_state.to_string());
         let (code_tx, code_rx) = oneshot::channel();
         let (shutdown_tx, shutdown_rx) = oneshot::channel();
         let code_tx = Arc::new(Mutex::new(Some(code_tx)));
 
         let addr = SocketAddr::from(([127, 0, 0, 1], port));
+        let listener = TcpListener::bind(addr)
+            .await
+            .map_err(|e| format!("Failed to bind OAuth callback port {}: {}", port, e))?;
+        let port = listener
+            .local_addr()
+            .map_err(|e| format!("Failed to read OAuth callback port: {}", e))?
+            .port();
 
         tokio::spawn(async move {
-            let listener = TcpListener::bind(addr)
-                .await
-                .map_err(|e| format!("Failed to bind to port {}: {}", port, e));
-
-            let listener = match listener {
-                Ok(l) => l,
-                Err(e) => {
-                    eprintln!("OAuth server error: {}", e);
-                    return;
-                }
-            };
-
             let code_tx_clone: Arc<Mutex<Option<oneshot::Sender<String>>>> = Arc::clone(&code_tx);
             let mut shutdown_rx = Some(shutdown_rx);
 
@@ -225,9 +280,10 @@ impl OAuthServer {
                             Ok((stream, _)) => {
                                 let io = TokioIo::new(stream);
                                 let tx_arc = Arc::clone(&code_tx_clone);
+                                let state_arc = Arc::clone(&expected_state);
 
                                 let service = service_fn(move |req| {
-                                    handle_callback(req, Arc::clone(&tx_arc))
+                                    handle_callback(req, Arc::clone(&tx_arc), Arc::clone(&state_arc))
                                 });
 
                                 tokio::task::spawn(async move {
@@ -263,11 +319,18 @@ impl OAuthServer {
         });
 
         Ok(OAuthServer {
+            port,
             shutdown_tx: Some(shutdown_tx),
             code_rx,
         })
     }
 
+    /// Redirect URI to register with the provider for this server. Uses the
+    /// IP we bind rather than `localhost`, which may resolve to `::1` first.
+    pub fn redirect_uri(&self) -> String {
+        format!("http://127.0.0.1:{}/callback", self.port)
+    }
+
     pub async fn wait_for_code(&mut self) -> Result<String, String> {
         tokio::time::timeout(tokio::time::Duration::from_secs(300), &mut self.code_rx)
             .await
@@ -1,123 +1,633 @@
+use crate::providers;
+use crate::sender_auth::from_domain;
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::Serialize;
+
+// Candidates scoring below this are treated as noise
+const MIN_SCORE: f32 = 1.5;
+// Score that maps to full confidence: a code-shaped number right next to a
+// keyword in the subject
+const MAX_SCORE: f32 = 7.0;
+const SUBJECT_BONUS: f32 = 1.0;
+const NEGATIVE_CONTEXT_PENALTY: f32 = 3.0;
+const YEAR_PENALTY: f32 = 1.5;
+// How close a letter-and-digit candidate must be to a specific keyword
+const STRONG_KEYWORD_DISTANCE: usize = 50;
+// How many characters back to look for words like "order" or "invoice"
+const CONTEXT_WINDOW: usize = 30;
+
+/// OTP keywords by locale, as regex fragments. Latin-script entries match
+/// whole words; Chinese and Japanese entries match anywhere, since those
+/// scripts don't put spaces between words.
+const KEYWORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "verification",
+            "verify",
+            "confirm",
+            "code",
+            "passcode",
+            "otp",
+            "pin",
+            "one[- ]time",
+            "2fa",
+            "two[- ]factor",
+            "security",
+            "log[- ]?in",
+            "sign[- ]?in",
+            r"authenticat\w*",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "bestätigungscode",
+            "sicherheitscode",
+            "verifizierungscode",
+            "einmalcode",
+            "anmeldecode",
+            "zugangscode",
+            "einmalpasswort",
+            "bestätigen",
+            "bestätigung",
+            "verifizierung",
+            "anmeldung",
+            "tan",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "c[óo]digo",
+            "verificaci[óo]n",
+            "verificar",
+            "confirmaci[óo]n",
+            "contraseña de un solo uso",
+            "clave",
+            "seguridad",
+            "inicio de sesi[óo]n",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "v[ée]rification",
+            "v[ée]rifier",
+            "confirmation",
+            "s[ée]curit[ée]",
+            "mot de passe à usage unique",
+            "connexion",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "c[óo]digo",
+            "verifica[çc][ãa]o",
+            "confirma[çc][ãa]o",
+            "senha",
+            "seguran[çc]a",
+            "acesso",
+        ],
+    ),
+    (
+        "ja",
+        &[
+            "認証コード",
+            "確認コード",
+            "ワンタイムパスワード",
+            "セキュリティコード",
+            "認証番号",
+            "確認番号",
+            "パスコード",
+            "コード",
+            "認証",
+            "確認",
+        ],
+    ),
+    (
+        "zh",
+        &[
+            "验证码",
+            "驗證碼",
+            "校验码",
+            "确认码",
+            "安全码",
+            "动态密码",
+            "動態密碼",
+            "验证",
+            "驗證",
+            "登录",
+            "登入",
+        ],
+    ),
+];
+
+/// Keywords too generic to vouch for a letter-and-digit code on their own,
+/// since coupons and vouchers are "codes" too
+const GENERIC_KEYWORDS: &[&str] = &[
+    "code",
+    "pin",
+    "c[óo]digo",
+    "clave",
+    "senha",
+    "tan",
+    "コード",
+];
+
+/// Words that mark a number as something other than a code, by locale.
+/// Checked before a candidate, and after it within the same sentence.
+const NEGATIVE_KEYWORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "order",
+            "invoice",
+            "receipt",
+            "ref",
+            "reference",
+            "tracking",
+            "shipment",
+            r"account\s+(?:number|no)",
+            "acct",
+            "zip",
+            "postal",
+            "phone",
+            "tel",
+            "call",
+            "fax",
+            "amount",
+            "total",
+            "price",
+            "balance",
+            r"promo\s+code",
+            "promo",
+            "coupon",
+            "discount",
+            r"check[- ]?out",
+            "sale",
+            r"\d+\s?%\s+off",
+        ],
+    ),
+    (
+        "de",
+        &["bestellung", "rechnung", "sendung", "betrag", "telefon"],
+    ),
+    ("es", &["pedido", "factura", "importe", "teléfono"]),
+    ("fr", &["commande", "facture", "montant", "téléphone"]),
+    ("pt", &["pedido", "fatura", "valor", "telefone"]),
+    ("ja", &["注文", "請求", "電話", "金額"]),
+    ("zh", &["订单", "訂單", "发票", "電話", "电话", "金额"]),
+];
+
+fn is_cjk(fragment: &str) -> bool {
+    fragment.chars().any(|c| c >= '\u{2E80}')
+}
+
+/// Build one case-insensitive regex from a locale table
+pub(crate) fn keyword_regex(table: &[(&str, &[&str])]) -> Regex {
+    words_regex(table.iter().flat_map(|(_, words)| words.iter().copied()))
+}
+
+fn words_regex<'a>(words: impl Iterator<Item = &'a str>) -> Regex {
+    let (cjk, latin): (Vec<&str>, Vec<&str>) = words.partition(|word| is_cjk(word));
+
+    Regex::new(&format!(
+        r"(?i)\b(?:{})\b|{}",
+        latin.join("|"),
+        cjk.join("|")
+    ))
+    .expect("Keyword regex should be valid")
+}
 
 lazy_static! {
-    static ref OTP_PATTERNS: Vec<Regex> = vec![
-        Regex::new(r"(?i)(?:code|verification|otp|pin)[:\s]+(\d{4,8})")
-            .expect("OTP regex pattern 1 should be valid"),
-        Regex::new(r"(?i)(\d{4,8})\s+(?:is\s+)?your\s+(?:code|otp|verification|pin)")
-            .expect("OTP regex pattern 2 should be valid"),
-        Regex::new(r"(?i)your\s+(?:code|otp|verification|pin)\s+(?:is[:\s]+)?(\d{4,8})")
-            .expect("OTP regex pattern 3 should be valid"),
-        Regex::new(r"(?i)enter[:\s]+(\d{4,8})\s+to\s+(?:verify|confirm)")
-            .expect("OTP regex pattern 4 should be valid"),
-        Regex::new(r"(?i)\b(\d{3}-\d{3})\b").expect("OTP regex pattern 5 should be valid"),
-        Regex::new(r"\b(\d{6})\b").expect("OTP regex pattern 6 should be valid"),
-    ];
+    // Alternatives are tried left to right, so grouped and prefixed forms win
+    // over their plain parts. Boundaries are ASCII-only so a code written
+    // straight after CJK text ("验证码123456") is still found.
+    static ref CANDIDATE_RE: Regex = Regex::new(
+        r"(?-u:\b)(?:(?P<prefixed>(?:G|FB)-[0-9]{5,8})|(?P<grouped>[0-9]{4}[- ][0-9]{4}|[0-9]{3}[- ][0-9]{3})|(?P<alnum_grouped>[A-Z0-9]{3,5}-[A-Z0-9]{3,5})|(?P<numeric>[0-9]{4,8})|(?P<alnum>[A-Z0-9]{4,8}))(?-u:\b)"
+    )
+    .expect("Candidate regex should be valid");
+    static ref KEYWORD_RE: Regex = keyword_regex(KEYWORDS);
+    static ref STRONG_KEYWORD_RE: Regex = words_regex(
+        KEYWORDS
+            .iter()
+            .flat_map(|(_, words)| words.iter().copied())
+            .filter(|word| !GENERIC_KEYWORDS.contains(word))
+    );
+    static ref NEGATIVE_RE: Regex = keyword_regex(NEGATIVE_KEYWORDS);
+    static ref CURRENCY_BEFORE_RE: Regex =
+        Regex::new(r"[$€£¥]\s*$").expect("Currency regex should be valid");
+    static ref UNIT_AFTER_RE: Regex = Regex::new(r"(?i)^\s*(?:%|usd|eur|gbp|dollars|euros)\b|^%")
+        .expect("Unit regex should be valid");
 }
 
+/// A code picked out of a message, with how sure the extractor is of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct OtpMatch {
+    /// Copyable form: group separators and prefixes like Google's `G-` removed
+    pub code: String,
+    /// The code as it appeared in the message
+    pub display: String,
+    /// Between 0.0 and 1.0
+    pub confidence: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CodeShape {
+    Numeric,
+    Grouped,
+    Prefixed,
+    Alphanumeric,
+}
+
+struct Candidate {
+    code: String,
+    display: String,
+    /// Name of the `CANDIDATE_RE` group that matched
+    rule: &'static str,
+    score: f32,
+    reasons: Vec<String>,
+    rejection: Option<String>,
+}
+
+/// How one code-shaped token in a text was scored, from `explain_extraction`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CandidateExplanation {
+    pub code: String,
+    pub display: String,
+    /// The candidate form that matched: "prefixed", "grouped",
+    /// "alnum_grouped", "numeric" or "alnum"
+    pub rule: String,
+    pub score: f32,
+    /// Each contribution to the score, in the order applied
+    pub reasons: Vec<String>,
+    /// Why the candidate can't be the code, if it can't
+    pub rejection: Option<String>,
+    /// Whether this is the code `extract_otp` returns
+    pub selected: bool,
+}
+
+/// Extract the most likely code from free text, in copyable form
+// The app reads messages with `extract_otp_from_message`; this is for
+// library callers and tests
+#[allow(dead_code)]
 pub fn extract_otp(text: &str) -> Option<String> {
-    for pattern in OTP_PATTERNS.iter() {
-        if let Some(caps) = pattern.captures(text) {
-            if let Some(code) = caps.get(1) {
-                return Some(code.as_str().to_string());
-            }
+    extract_otp_from_message("", text).map(|m| m.code)
+}
+
+/// Extract the most likely code from a message. Every code-shaped token is
+/// scored on its shape, how close it sits to words like "code" or "verify",
+/// whether it is in the subject, and context that suggests something else
+/// (order numbers, amounts, dates, phone numbers). The best candidate is
+/// returned if it clears the threshold.
+pub fn extract_otp_from_message(subject: &str, body: &str) -> Option<OtpMatch> {
+    let candidates = message_candidates(subject, body);
+    best_candidate(&candidates).map(|i| {
+        let c = &candidates[i];
+        OtpMatch {
+            code: c.code.clone(),
+            display: c.display.clone(),
+            confidence: (c.score / MAX_SCORE).clamp(0.0, 1.0),
+        }
+    })
+}
+
+/// Every candidate `extract_otp` considers in `text`, with its score and
+/// the reasons behind it, or why it was ruled out
+pub fn explain_extraction(text: &str) -> Vec<CandidateExplanation> {
+    let candidates = message_candidates("", text);
+    let selected = best_candidate(&candidates);
+    candidates
+        .into_iter()
+        .enumerate()
+        .map(|(i, c)| CandidateExplanation {
+            code: c.code,
+            display: c.display,
+            rule: c.rule.to_string(),
+            score: c.score,
+            reasons: c.reasons,
+            rejection: c.rejection,
+            selected: selected == Some(i),
+        })
+        .collect()
+}
+
+fn message_candidates(subject: &str, body: &str) -> Vec<Candidate> {
+    let mut candidates = score_candidates(&normalize_width(subject), SUBJECT_BONUS);
+    candidates.extend(score_candidates(&normalize_width(body), 0.0));
+    candidates
+}
+
+/// Index of the highest-scoring candidate, if it clears the threshold
+fn best_candidate(candidates: &[Candidate]) -> Option<usize> {
+    let mut best: Option<usize> = None;
+    for (i, candidate) in candidates.iter().enumerate() {
+        if candidate.rejection.is_some() {
+            continue;
+        }
+        // Ties go to the earlier candidate, subject first
+        if best.is_none_or(|b| candidate.score > candidates[b].score) {
+            best = Some(i);
         }
     }
-    None
+    best.filter(|&i| candidates[i].score >= MIN_SCORE)
 }
 
-pub fn extract_provider(sender: &str) -> String {
-    const MAPPINGS: &[(&str, &str)] = &[
-        ("google", "Google"),
-        ("gmail", "Google"),
-        ("apple", "Apple"),
-        ("microsoft", "Microsoft"),
-        ("outlook", "Microsoft"),
-        ("amazon", "Amazon"),
-        ("facebook", "Facebook"),
-        ("meta", "Meta"),
-        ("instagram", "Instagram"),
-        ("twitter", "Twitter"),
-        ("x.com", "X"),
-        ("github", "GitHub"),
-        ("linkedin", "LinkedIn"),
-        ("paypal", "PayPal"),
-        ("stripe", "Stripe"),
-        ("venmo", "Venmo"),
-        ("cashapp", "Cash App"),
-        ("square", "Square"),
-        ("coinbase", "Coinbase"),
-        ("binance", "Binance"),
-        ("robinhood", "Robinhood"),
-        ("chase", "Chase"),
-        ("wellsfargo", "Wells Fargo"),
-        ("bankofamerica", "Bank of America"),
-        ("aws", "AWS"),
-        ("heroku", "Heroku"),
-        ("digitalocean", "DigitalOcean"),
-        ("cloudflare", "Cloudflare"),
-        ("shopify", "Shopify"),
-        ("ebay", "eBay"),
-        ("etsy", "Etsy"),
-        ("doordash", "DoorDash"),
-        ("grubhub", "Grubhub"),
-        ("postmates", "Postmates"),
-        ("uber", "Uber"),
-        ("lyft", "Lyft"),
-        ("spotify", "Spotify"),
-        ("netflix", "Netflix"),
-        ("notion", "Notion"),
-        ("figma", "Figma"),
-        ("canva", "Canva"),
-        ("zoom", "Zoom"),
-        ("webex", "Webex"),
-        ("asana", "Asana"),
-        ("trello", "Trello"),
-        ("airbnb", "Airbnb"),
-        ("twilio", "Twilio"),
-        ("auth0", "Auth0"),
-        ("okta", "Okta"),
-        ("dropbox", "Dropbox"),
-        ("slack", "Slack"),
-        ("discord", "Discord"),
-        ("salesforce", "Salesforce"),
-        ("atlassian", "Atlassian"),
-        ("jira", "Jira"),
-        ("adobe", "Adobe"),
-        ("oracle", "Oracle"),
-        ("namecheap", "Namecheap"),
-        ("godaddy", "GoDaddy"),
-    ];
-
-    let sender_lower = sender.to_lowercase();
-
-    // Extract domain from email address first to avoid substring false matches
-    let domain = if let Some(at_pos) = sender_lower.find('@') {
-        &sender_lower[at_pos + 1..]
-    } else {
-        &sender_lower
+fn score_candidates(text: &str, bonus: f32) -> Vec<Candidate> {
+    let keywords: Vec<(usize, usize)> = KEYWORD_RE
+        .find_iter(text)
+        .map(|m| (m.start(), m.end()))
+        .collect();
+    let strong_keywords: Vec<(usize, usize)> = STRONG_KEYWORD_RE
+        .find_iter(text)
+        .map(|m| (m.start(), m.end()))
+        .collect();
+
+    CANDIDATE_RE
+        .captures_iter(text)
+        .filter_map(|caps| {
+            let m = caps.get(0)?;
+            let rule = ["prefixed", "grouped", "alnum_grouped", "numeric", "alnum"]
+                .into_iter()
+                .find(|name| caps.name(name).is_some())?;
+            let shape = match rule {
+                "prefixed" => CodeShape::Prefixed,
+                "grouped" => CodeShape::Grouped,
+                "numeric" => CodeShape::Numeric,
+                _ => CodeShape::Alphanumeric,
+            };
+
+            let display = m.as_str();
+            let code = normalize_code(display, shape);
+            let mut candidate = Candidate {
+                code,
+                display: display.to_string(),
+                rule,
+                score: 0.0,
+                reasons: Vec::new(),
+                rejection: None,
+            };
+
+            if is_part_of_larger_number(text, m.start(), m.end()) {
+                candidate.rejection = Some("part of a larger number".to_string());
+                return Some(candidate);
+            }
+            // Letter-and-digit tokens need both, and mustn't come from a link
+            // or an email address
+            if shape == CodeShape::Alphanumeric {
+                if !is_mixed(display) {
+                    candidate.rejection = Some("needs both letters and digits".to_string());
+                    return Some(candidate);
+                }
+                if is_inside_link(text, m.start(), m.end()) {
+                    candidate.rejection = Some("inside a link or email address".to_string());
+                    return Some(candidate);
+                }
+                // "Use code SAVE20" is a coupon; a real one says what it's for
+                if nearest_keyword(text, &strong_keywords, m.start(), m.end())
+                    .is_none_or(|(distance, _)| distance > STRONG_KEYWORD_DISTANCE)
+                {
+                    candidate.rejection =
+                        Some("needs a word like \"verification\" nearby".to_string());
+                    return Some(candidate);
+                }
+            }
+
+            if bonus != 0.0 {
+                candidate.add(bonus, "in the subject");
+            }
+            candidate.add(shape_score(&candidate.code, shape), "shape");
+
+            if let Some((distance, keyword)) = nearest_keyword(text, &keywords, m.start(), m.end())
+            {
+                let points = match distance {
+                    d if d <= 20 => 4.0,
+                    d if d <= 50 => 2.5,
+                    d if d <= 120 => 1.0,
+                    _ => 0.0,
+                };
+                if points > 0.0 {
+                    candidate.add(
+                        points,
+                        &format!("keyword \"{}\" {} chars away", keyword, distance),
+                    );
+                }
+            }
+
+            let before = window_before(text, m.start(), CONTEXT_WINDOW);
+            let after = &text[m.end()..];
+            if CURRENCY_BEFORE_RE.is_match(before) || UNIT_AFTER_RE.is_match(after) {
+                candidate.add(-MAX_SCORE, "amount or percentage");
+            }
+            if has_negative_context(before) {
+                candidate.add(-NEGATIVE_CONTEXT_PENALTY, "after a word like \"order\"");
+            }
+            if has_negative_context_after(after) {
+                candidate.add(-NEGATIVE_CONTEXT_PENALTY, "before a word like \"checkout\"");
+            }
+            if looks_like_year(&candidate.code) {
+                candidate.add(-YEAR_PENALTY, "looks like a year");
+            }
+
+            if candidate.score < MIN_SCORE {
+                candidate.rejection = Some(format!(
+                    "score {:.1} is below the threshold {:.1}",
+                    candidate.score, MIN_SCORE
+                ));
+            }
+            Some(candidate)
+        })
+        .collect()
+}
+
+impl Candidate {
+    fn add(&mut self, points: f32, reason: &str) {
+        self.score += points;
+        self.reasons.push(format!("{}: {:+.1}", reason, points));
+    }
+}
+
+/// Map full-width digits, letters and hyphens (common in Japanese and Chinese
+/// mail) to ASCII so they're read like any other code
+fn normalize_width(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\u{FF10}'..='\u{FF19}'
+            | '\u{FF21}'..='\u{FF3A}'
+            | '\u{FF41}'..='\u{FF5A}'
+            | '\u{FF0D}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+/// The form to paste into a code field: `G-482913` becomes `482913` and
+/// `4F7K-9Q2M` becomes `4F7K9Q2M`
+fn normalize_code(display: &str, shape: CodeShape) -> String {
+    let code = match (shape, display.split_once('-')) {
+        (CodeShape::Prefixed, Some((_, digits))) => digits,
+        _ => display,
     };
+    code.chars().filter(|c| *c != ' ' && *c != '-').collect()
+}
+
+/// Six digits is by far the most common code shape; a known prefix is a
+/// strong sign on its own
+fn shape_score(code: &str, shape: CodeShape) -> f32 {
+    match shape {
+        CodeShape::Prefixed => 3.0,
+        CodeShape::Numeric | CodeShape::Grouped if code.len() == 6 => 2.0,
+        CodeShape::Numeric | CodeShape::Grouped => 1.0,
+        CodeShape::Alphanumeric if code.len() >= 6 => 1.0,
+        CodeShape::Alphanumeric => 0.5,
+    }
+}
+
+fn is_mixed(token: &str) -> bool {
+    token.chars().any(|c| c.is_ascii_digit()) && token.chars().any(|c| c.is_ascii_uppercase())
+}
+
+fn is_inside_link(text: &str, start: usize, end: usize) -> bool {
+    let token_start = text[..start]
+        .rfind(char::is_whitespace)
+        .map_or(0, |i| i + 1);
+    let token_end = text[end..]
+        .find(char::is_whitespace)
+        .map_or(text.len(), |i| end + i);
+    let token = &text[token_start..token_end];
+    token.contains("://") || token.contains('@') || token.starts_with("www.")
+}
+
+/// The keyword closest to the token at `start..end`, and how far away it is.
+/// Counted in characters so CJK text isn't penalized for its longer UTF-8
+/// encoding.
+fn nearest_keyword<'t>(
+    text: &'t str,
+    keywords: &[(usize, usize)],
+    start: usize,
+    end: usize,
+) -> Option<(usize, &'t str)> {
+    keywords
+        .iter()
+        .map(|&(k_start, k_end)| {
+            let distance = if k_end <= start {
+                text[k_end..start].chars().count()
+            } else if k_start >= end {
+                text[end..k_start].chars().count()
+            } else {
+                0
+            };
+            (distance, &text[k_start..k_end])
+        })
+        .min_by_key(|&(distance, _)| distance)
+}
 
-    // Check domain and full sender with word boundary awareness
-    for (key, value) in MAPPINGS {
-        // For exact domain matches (e.g., "x.com")
-        if key.contains('.') && domain == *key {
-            return value.to_string();
+/// Words like "order" only describe the number right after them, so a
+/// keyword or another number in between cancels them out
+fn has_negative_context(before: &str) -> bool {
+    match NEGATIVE_RE.find_iter(before).last() {
+        Some(m) => {
+            let between = &before[m.end()..];
+            !KEYWORD_RE.is_match(between) && !between.chars().any(|c| c.is_ascii_digit())
         }
-        // For domain component matches (e.g., "netflix" in "netflix.com")
-        if domain.starts_with(key) || domain.contains(&format!(".{}", key)) {
-            return value.to_string();
+        None => false,
+    }
+}
+
+/// Coupon wording after a token ("SAVE20 at checkout"), up to the end of
+/// the sentence; as before it, a keyword or number in between cancels it
+fn has_negative_context_after(after: &str) -> bool {
+    let window = &after[..after
+        .char_indices()
+        .nth(CONTEXT_WINDOW)
+        .map_or(after.len(), |(i, _)| i)];
+    let sentence = window
+        .find(['.', '!', '?', '\n'])
+        .map_or(window, |i| &window[..i]);
+    match NEGATIVE_RE.find(sentence) {
+        Some(m) => {
+            let between = &sentence[..m.start()];
+            !KEYWORD_RE.is_match(between) && !between.chars().any(|c| c.is_ascii_digit())
         }
-        // Also check the full sender for name-based matches (e.g., "GitHub <noreply@github.com>")
-        if sender_lower.contains(&format!("{} ", key)) || sender_lower.starts_with(key) {
-            return value.to_string();
+        None => false,
+    }
+}
+
+fn looks_like_year(code: &str) -> bool {
+    code.len() == 4 && matches!(code.parse::<u32>(), Ok(1900..=2099))
+}
+
+/// Digits glued to other digits by separators are dates, phone numbers,
+/// amounts or IDs rather than codes. A space only glues groups of the same
+/// three or four digits, as in a card number; "482913 5 minutes" is a code.
+fn is_part_of_larger_number(text: &str, start: usize, end: usize) -> bool {
+    let token = &text[start..end];
+    let mut before = text[..start].chars().rev();
+    let mut after = text[end..].chars();
+
+    match (before.next(), before.next()) {
+        (Some('+' | '#'), _) => return true,
+        (Some('.' | ',' | '/' | '-' | ':'), Some(c)) if c.is_ascii_digit() => return true,
+        (Some(' '), Some(c)) if c.is_ascii_digit() => {
+            let neighbour = text[..start - 1]
+                .chars()
+                .rev()
+                .take_while(char::is_ascii_digit)
+                .count();
+            let group = token.chars().take_while(char::is_ascii_digit).count();
+            if is_same_group(neighbour, group) {
+                return true;
+            }
         }
+        _ => {}
+    }
+    match (after.next(), after.next()) {
+        (Some('.' | ',' | '/' | '-' | ':'), Some(c)) => c.is_ascii_digit(),
+        (Some(' '), Some(c)) if c.is_ascii_digit() => {
+            let neighbour = text[end + 1..]
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .count();
+            let group = token.chars().rev().take_while(char::is_ascii_digit).count();
+            is_same_group(neighbour, group)
+        }
+        _ => false,
+    }
+}
+
+// Whether two space-separated digit runs read as groups of one number
+fn is_same_group(a: usize, b: usize) -> bool {
+    a == b && (3..=4).contains(&a)
+}
+
+pub(crate) fn window_before(text: &str, index: usize, chars: usize) -> &str {
+    let start = text[..index]
+        .char_indices()
+        .rev()
+        .nth(chars.saturating_sub(1))
+        .map_or(0, |(i, _)| i);
+    &text[start..index]
+}
+
+/// A display name for the service that sent a message: a catalog match on
+/// the sender's domain, then the display name itself, then the registrable
+/// domain. A display name claiming a catalog provider from another domain is
+/// skipped, so a lookalike is labelled by its own domain.
+pub fn extract_provider(sender: &str) -> String {
+    let catalog = providers::catalog();
+    if let Some(provider) = catalog.provider_for(sender) {
+        return provider.name.clone();
     }
 
     // Try to extract name before email
     let name_re = Regex::new(r"^([^<@]+)").expect("Name regex should be valid");
-    if let Some(caps) = name_re.captures(sender) {
+    if let Some(caps) = name_re
+        .captures(sender)
+        .filter(|_| catalog.claimed_provider(sender).is_none())
+    {
         let name = caps[1].trim();
         let clean_re = Regex::new(r"\s*(no-?reply|noreply|support|security|verify|verification|accounts?|team|notifications?)\s*")
             .expect("Clean regex should be valid");
@@ -130,11 +640,11 @@ pub fn extract_provider(sender: &str) -> String {
         }
     }
 
-    // Try to extract domain
-    let domain_re = Regex::new(r"@([^.>]+)").expect("Domain regex should be valid");
-    if let Some(caps) = domain_re.captures(sender) {
-        let domain = &caps[1];
-        let mut chars = domain.chars();
+    // Fall back to the registrable domain, so mail.acme.co.uk reads as Acme
+    if let Some(domain) = from_domain(sender) {
+        let registrable = providers::registrable_domain(&domain).unwrap_or(domain);
+        let label = registrable.split('.').next().unwrap_or_default();
+        let mut chars = label.chars();
         if let Some(first) = chars.next() {
             return format!("{}{}", first.to_uppercase(), chars.as_str());
         }
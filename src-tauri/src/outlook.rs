@@ -0,0 +1,442 @@
+use crate::keychain::KeychainManager;
+use crate::mail_source::{EmailMessage, MailSource, RATE_LIMIT_ERROR};
+use crate::mime;
+use crate::oauth_server::{PendingAuth, Pkce};
+use crate::sender_auth;
+use async_trait::async_trait;
+use chrono::Utc;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+const GRAPH_API_URL: &str = "https://graph.microsoft.com/v1.0";
+const MICROSOFT_AUTHORITY_URL: &str = "https://login.microsoftonline.com";
+const OUTLOOK_SCOPES: &[&str] = &["offline_access", "https://graph.microsoft.com/Mail.Read"];
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct User {
+    #[serde(default)]
+    mail: Option<String>,
+    #[serde(default)]
+    user_principal_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MessageListResponse {
+    #[serde(default)]
+    value: Vec<MessageRef>,
+}
+
+#[derive(Deserialize)]
+struct MessageRef {
+    id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MessageDetail {
+    #[serde(default)]
+    subject: Option<String>,
+    #[serde(default)]
+    body_preview: String,
+    #[serde(default)]
+    body: Option<ItemBody>,
+    #[serde(default)]
+    from: Option<Recipient>,
+    #[serde(default)]
+    received_date_time: Option<String>,
+    #[serde(default)]
+    internet_message_headers: Vec<MessageHeader>,
+}
+
+#[derive(Deserialize)]
+struct MessageHeader {
+    name: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ItemBody {
+    #[serde(default)]
+    content_type: String,
+    #[serde(default)]
+    content: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Recipient {
+    email_address: EmailAddress,
+}
+
+#[derive(Deserialize)]
+struct EmailAddress {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    address: Option<String>,
+}
+
+/// Microsoft 365 / Outlook.com mailbox read through the Microsoft Graph API.
+///
+/// Uses the public-client PKCE flow, so no client secret is required.
+pub struct OutlookClient {
+    authenticated: AtomicBool,
+    // Address of the signed-in mailbox, from /me
+    account: Mutex<Option<String>>,
+    client_id: String,
+    api_base_url: String,
+    token_url: String,
+    authorize_url: String,
+    http_client: Client,
+    pending_auth: Mutex<Option<PendingAuth>>,
+    // Cached (token, expiry) so every Graph call doesn't hit the keychain
+    access_token: Mutex<Option<(String, i64)>>,
+}
+
+impl OutlookClient {
+    pub fn new() -> Self {
+        let client_id = std::env::var("MICROSOFT_CLIENT_ID").unwrap_or_default();
+        let tenant = std::env::var("MICROSOFT_TENANT").unwrap_or_else(|_| "common".to_string());
+        let api_base_url =
+            std::env::var("OTPBAR_GRAPH_API_URL").unwrap_or_else(|_| GRAPH_API_URL.to_string());
+        let authority_url = std::env::var("OTPBAR_MICROSOFT_AUTHORITY_URL")
+            .unwrap_or_else(|_| MICROSOFT_AUTHORITY_URL.to_string());
+
+        if client_id.is_empty() {
+            log::warn!("MICROSOFT_CLIENT_ID not set. Outlook authentication will fail.");
+        }
+
+        Self::with_endpoints(&client_id, &api_base_url, &authority_url, &tenant)
+    }
+
+    /// Build a client against explicit endpoints (used for local mocks)
+    pub fn with_endpoints(
+        client_id: &str,
+        api_base_url: &str,
+        authority_url: &str,
+        tenant: &str,
+    ) -> Self {
+        let authority = format!(
+            "{}/{}/oauth2/v2.0",
+            authority_url.trim_end_matches('/'),
+            tenant
+        );
+        OutlookClient {
+            authenticated: AtomicBool::new(false),
+            account: Mutex::new(None),
+            client_id: client_id.to_string(),
+            api_base_url: api_base_url.trim_end_matches('/').to_string(),
+            token_url: format!("{}/token", authority),
+            authorize_url: format!("{}/authorize", authority),
+            http_client: Client::new(),
+            pending_auth: Mutex::new(None),
+            access_token: Mutex::new(None),
+        }
+    }
+
+    /// Whether a previous Outlook sign-in left a refresh token in the keychain
+    pub fn has_stored_credentials() -> bool {
+        matches!(KeychainManager::get_outlook_refresh_token(), Ok(Some(_)))
+    }
+
+    fn store_tokens(&self, resp: &TokenResponse) -> Result<(), String> {
+        let expiry = Utc::now().timestamp() + resp.expires_in.unwrap_or(3600) as i64;
+        *self.access_token.lock().unwrap() = Some((resp.access_token.clone(), expiry));
+
+        KeychainManager::set_outlook_access_token(&resp.access_token)?;
+        KeychainManager::set_outlook_token_expiry(expiry)?;
+        // Microsoft rotates refresh tokens, so always keep the newest one
+        if let Some(refresh_token) = &resp.refresh_token {
+            KeychainManager::set_outlook_refresh_token(refresh_token)?;
+        }
+        Ok(())
+    }
+
+    async fn refresh_access_token(&self) -> Result<String, String> {
+        let refresh_token =
+            KeychainManager::get_outlook_refresh_token()?.ok_or("No refresh token stored")?;
+        let scope = OUTLOOK_SCOPES.join(" ");
+
+        let params = [
+            ("client_id", self.client_id.as_str()),
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token.as_str()),
+            ("scope", scope.as_str()),
+        ];
+
+        let resp: TokenResponse = self
+            .http_client
+            .post(&self.token_url)
+            .form(&params)
+            .send()
+            .await
+            .map_err(|e| format!("Refresh request failed: {}", e))?
+            .error_for_status()
+            .map_err(|e| format!("Refresh request rejected: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse refresh response: {}", e))?;
+
+        self.store_tokens(&resp)?;
+        Ok(resp.access_token)
+    }
+
+    async fn get_valid_access_token(&self) -> Result<String, String> {
+        let now = Utc::now().timestamp();
+
+        if let Some((token, expiry)) = self.access_token.lock().unwrap().clone() {
+            if now < expiry - 60 {
+                return Ok(token);
+            }
+        }
+
+        if let Ok(Some(token)) = KeychainManager::get_outlook_access_token() {
+            if let Ok(Some(expiry)) = KeychainManager::get_outlook_token_expiry() {
+                if now < expiry - 60 {
+                    *self.access_token.lock().unwrap() = Some((token.clone(), expiry));
+                    return Ok(token);
+                }
+            }
+        }
+
+        self.refresh_access_token().await
+    }
+
+    async fn get_account_address(&self) -> Result<String, String> {
+        let user: User = self
+            .graph_get(&format!(
+                "{}/me?$select=mail,userPrincipalName",
+                self.api_base_url
+            ))
+            .await?
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse user profile: {}", e))?;
+
+        user.mail
+            .or(user.user_principal_name)
+            .ok_or_else(|| "Failed to determine Outlook account address".to_string())
+    }
+
+    async fn graph_get(&self, url: &str) -> Result<reqwest::Response, String> {
+        let access_token = self.get_valid_access_token().await?;
+
+        let response = self
+            .http_client
+            .get(url)
+            .bearer_auth(access_token)
+            .header("Prefer", "outlook.body-content-type=\"text\"")
+            .send()
+            .await
+            .map_err(|e| format!("Graph API request failed: {}", e))?;
+
+        // Check for rate limit error (HTTP 429)
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            return Err(RATE_LIMIT_ERROR.to_string());
+        }
+
+        response
+            .error_for_status()
+            .map_err(|e| format!("Graph API error: {}", e))
+    }
+}
+
+impl Default for OutlookClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl MailSource for OutlookClient {
+    fn name(&self) -> &'static str {
+        "outlook"
+    }
+
+    fn account(&self) -> Option<String> {
+        self.account.lock().unwrap().clone()
+    }
+
+    fn is_authenticated(&self) -> bool {
+        self.authenticated.load(Ordering::Relaxed)
+    }
+
+    async fn try_restore_auth(&mut self) -> bool {
+        if self.client_id.is_empty() || !Self::has_stored_credentials() {
+            return false;
+        }
+
+        match self.get_account_address().await {
+            Ok(account) => {
+                *self.account.lock().unwrap() = Some(account);
+                self.authenticated.store(true, Ordering::Relaxed);
+                log::info!(
+                    "Successfully restored and validated Outlook authentication from keychain"
+                );
+                true
+            }
+            Err(e) => {
+                log::warn!("Outlook credential validation failed: {}", e);
+                let _ = self.clear_auth().await;
+                false
+            }
+        }
+    }
+
+    fn get_auth_url(&self, redirect_uri: &str, state: &str) -> Result<String, String> {
+        let pkce = Pkce::generate();
+        let url = format!(
+            "{}?client_id={}&response_type=code&redirect_uri={}&response_mode=query&scope={}&state={}&code_challenge={}&code_challenge_method=S256&prompt=select_account",
+            self.authorize_url,
+            urlencoding::encode(&self.client_id),
+            urlencoding::encode(redirect_uri),
+            urlencoding::encode(&OUTLOOK_SCOPES.join(" ")),
+            urlencoding::encode(state),
+            pkce.challenge
+        );
+        *self.pending_auth.lock().unwrap() = Some(PendingAuth {
+            verifier: pkce.verifier,
+            redirect_uri: redirect_uri.to_string(),
+        });
+        Ok(url)
+    }
+
+    async fn exchange_code(&mut self, code: &str) -> Result<(), String> {
+        let pending = self
+            .pending_auth
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or("Sign-in was not started")?;
+        let scope = OUTLOOK_SCOPES.join(" ");
+
+        let params = [
+            ("client_id", self.client_id.as_str()),
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", pending.redirect_uri.as_str()),
+            ("code_verifier", pending.verifier.as_str()),
+            ("scope", scope.as_str()),
+        ];
+
+        let resp: TokenResponse = self
+            .http_client
+            .post(&self.token_url)
+            .form(&params)
+            .send()
+            .await
+            .map_err(|e| format!("Token request failed: {}", e))?
+            .error_for_status()
+            .map_err(|e| format!("Token request rejected: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse token response: {}", e))?;
+
+        if resp.refresh_token.is_none() {
+            return Err("No refresh token received".to_string());
+        }
+        self.store_tokens(&resp)?;
+
+        let account = self.get_account_address().await?;
+        *self.account.lock().unwrap() = Some(account);
+        self.authenticated.store(true, Ordering::Relaxed);
+        log::info!("Outlook OAuth exchange successful, user authenticated");
+        Ok(())
+    }
+
+    async fn list_new_messages(&self) -> Result<Vec<String>, String> {
+        let since = (Utc::now() - chrono::Duration::days(1))
+            .format("%Y-%m-%dT%H:%M:%SZ")
+            .to_string();
+        let filter = format!("isRead eq false and receivedDateTime ge {}", since);
+        let url = format!(
+            "{}/me/messages?$filter={}&$select=id&$top=25",
+            self.api_base_url,
+            urlencoding::encode(&filter)
+        );
+
+        let list: MessageListResponse = self
+            .graph_get(&url)
+            .await?
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse message list: {}", e))?;
+
+        Ok(list.value.into_iter().map(|m| m.id).collect())
+    }
+
+    async fn fetch_message_detail(&self, msg_id: &str) -> Result<EmailMessage, String> {
+        let url = format!(
+            "{}/me/messages/{}?$select=subject,from,bodyPreview,body,receivedDateTime,internetMessageHeaders",
+            self.api_base_url,
+            urlencoding::encode(msg_id)
+        );
+
+        let detail: MessageDetail = self
+            .graph_get(&url)
+            .await?
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse message: {}", e))?;
+
+        let from = detail
+            .from
+            .map(|r| match (r.email_address.name, r.email_address.address) {
+                (Some(name), Some(address)) if !name.is_empty() => {
+                    format!("{} <{}>", name, address)
+                }
+                (_, Some(address)) => address,
+                (Some(name), None) => name,
+                (None, None) => String::new(),
+            })
+            .unwrap_or_default();
+
+        // We ask for text bodies, but Graph can still return HTML
+        let (body, links) = match detail.body {
+            Some(b) if b.content_type.eq_ignore_ascii_case("html") => {
+                (mime::html_to_text(&b.content), mime::html_links(&b.content))
+            }
+            Some(b) => (b.content, Vec::new()),
+            None => (String::new(), Vec::new()),
+        };
+
+        Ok(EmailMessage {
+            id: msg_id.to_string(),
+            subject: detail.subject.unwrap_or_default(),
+            snippet: detail.body_preview,
+            links,
+            body,
+            sent_at: detail
+                .received_date_time
+                .and_then(|t| chrono::DateTime::parse_from_rfc3339(&t).ok())
+                .map(|t| t.timestamp_millis()),
+            sender_auth: sender_auth::verify_sender(
+                detail
+                    .internet_message_headers
+                    .iter()
+                    .map(|h| (h.name.as_str(), h.value.as_str())),
+                &from,
+            ),
+            from,
+        })
+    }
+
+    async fn clear_auth(&self) -> Result<(), String> {
+        KeychainManager::delete_outlook_credentials()?;
+        *self.access_token.lock().unwrap() = None;
+        *self.account.lock().unwrap() = None;
+        self.authenticated.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
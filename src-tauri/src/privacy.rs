@@ -1,5 +1,10 @@
+use crate::gmail;
 use crate::history;
-use crate::keychain::KeychainManager;
+use crate::imap;
+use crate::keychain::{KeychainManager, HISTORY_KEY_ITEM};
+use crate::outlook;
+use crate::totp;
+use crate::types::{CodeEntry, RetentionPolicy};
 use serde::Serialize;
 
 #[derive(Debug, Serialize)]
@@ -50,11 +55,16 @@ struct Retention {
     max_history_size: usize,
     #[serde(rename = "currentSize")]
     current_size: usize,
+    #[serde(rename = "metadataOnly")]
+    metadata_only: bool,
 }
 
 const GMAIL_SCOPES: &[&str] = &["https://www.googleapis.com/auth/gmail.readonly"];
 
-pub fn get_privacy_data() -> Result<PrivacyData, String> {
+pub fn get_privacy_data(
+    policy: &RetentionPolicy,
+    codes: &[CodeEntry],
+) -> Result<PrivacyData, String> {
     // Get data locations
     let config_path = history::get_history_path()
         .map(|p| p.parent().unwrap().to_path_buf())
@@ -70,35 +80,50 @@ pub fn get_privacy_data() -> Result<PrivacyData, String> {
     let config_path_str = config_path.to_string_lossy().to_string();
     let history_path_str = history_path.to_string_lossy().to_string();
 
-    // Get keychain items
-    let keychain_items = vec![
-        "gmail-access-token".to_string(),
-        "gmail-refresh-token".to_string(),
-        "gmail-token-expiry".to_string(),
-    ];
+    // Get keychain items: one set per signed-in Gmail account, then the
+    // Outlook and IMAP credentials, authenticator secrets and history key
+    let accounts = gmail::load_gmail_accounts();
+    let mut keychain_items: Vec<String> = accounts
+        .iter()
+        .flat_map(|account| KeychainManager::gmail_keychain_items(account))
+        .collect();
+    if outlook::OutlookClient::has_stored_credentials() {
+        keychain_items.extend(KeychainManager::outlook_keychain_items());
+    }
+    if let Some(config) = imap::load_imap_config() {
+        keychain_items.push(KeychainManager::imap_keychain_item(&config.username));
+    }
+    keychain_items.extend(
+        totp::load_totp_accounts()
+            .iter()
+            .map(|account| KeychainManager::totp_keychain_item(&account.id)),
+    );
+    keychain_items.push(HISTORY_KEY_ITEM.to_string());
 
     // Get permissions
     let scopes: Vec<String> = GMAIL_SCOPES.iter().map(|s| s.to_string()).collect();
 
-    let has_access_token = KeychainManager::get_access_token()
-        .unwrap_or(None)
-        .is_some();
+    let has_access_token = accounts.iter().any(|account| {
+        KeychainManager::get_gmail_access_token(account)
+            .unwrap_or(None)
+            .is_some()
+    });
 
-    let has_refresh_token = KeychainManager::get_refresh_token()
-        .unwrap_or(None)
-        .is_some();
+    let has_refresh_token = accounts.iter().any(|account| {
+        KeychainManager::get_gmail_refresh_token(account)
+            .unwrap_or(None)
+            .is_some()
+    });
 
     // Get activity data
-    let codes = history::load_history();
     let total_codes = codes.len();
     let last_activity = codes.first().map(|c| c.timestamp);
 
-    // Currently we don't have a time-based retention, just size-based
-    // Return 0 to indicate "forever" for now
-    let history_retention = 0u32;
+    // 0 means entries are kept until the size limit pushes them out
+    let history_retention = policy.max_age_days.unwrap_or(0);
 
     // Get retention info
-    let max_history_size = 50; // From history.rs
+    let max_history_size = policy.max_count;
     let current_size = codes.len();
 
     Ok(PrivacyData {
@@ -120,11 +145,11 @@ pub fn get_privacy_data() -> Result<PrivacyData, String> {
         retention: Retention {
             max_history_size,
             current_size,
+            metadata_only: policy.metadata_only,
         },
     })
 }
 
-pub fn clear_history() -> Result<(), String> {
-    history::save_history(&[]);
-    Ok(())
+pub fn clear_history(store: &mut history::HistoryStore) -> Result<(), String> {
+    store.clear()
 }
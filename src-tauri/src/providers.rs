@@ -0,0 +1,217 @@
+use crate::sender_auth::from_domain;
+use lazy_static::lazy_static;
+use publicsuffix::{IcannList, Psl};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+const OVERRIDES_FILE: &str = "providers.json";
+const BUILT_IN_PROVIDERS: &str = include_str!("../data/providers.json");
+const PUBLIC_SUFFIX_LIST: &str = include_str!("../data/public_suffix_list.dat");
+
+/// A service that sends codes, recognised by the domains it mails from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provider {
+    pub name: String,
+    /// Sending domains; each also covers its subdomains
+    #[serde(default)]
+    pub domains: Vec<String>,
+    /// Display names that claim to be this provider, compared against the
+    /// whole name less words like "support" or "team". They never label a
+    /// message on their own; a sender using one from another domain is
+    /// treated as a lookalike.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProviderCatalog {
+    providers: Vec<Provider>,
+}
+
+lazy_static! {
+    // Only the ICANN section: private suffixes like atlassian.net would make
+    // every tenant its own registrable domain
+    static ref SUFFIXES: IcannList = PUBLIC_SUFFIX_LIST
+        .parse()
+        .expect("Public suffix list should be valid");
+    static ref CATALOG: ProviderCatalog =
+        ProviderCatalog::built_in().with_overrides(load_overrides());
+}
+
+/// The built-in catalog merged with the user's `providers.json`, read once
+/// at startup
+pub fn catalog() -> &'static ProviderCatalog {
+    &CATALOG
+}
+
+impl ProviderCatalog {
+    pub fn new(providers: Vec<Provider>) -> Self {
+        Self {
+            providers: providers.into_iter().map(normalize).collect(),
+        }
+    }
+
+    pub fn built_in() -> Self {
+        Self::new(
+            serde_json::from_str(BUILT_IN_PROVIDERS)
+                .expect("Built-in provider catalog should be valid"),
+        )
+    }
+
+    /// Merge user entries: one with the same name replaces the built-in
+    /// entry, others are added. User entries win ties.
+    pub fn with_overrides(self, overrides: Vec<Provider>) -> Self {
+        let mut providers: Vec<Provider> = overrides.into_iter().map(normalize).collect();
+        let built_in: Vec<Provider> = self
+            .providers
+            .into_iter()
+            .filter(|b| {
+                !providers
+                    .iter()
+                    .any(|p| p.name.eq_ignore_ascii_case(&b.name))
+            })
+            .collect();
+        providers.extend(built_in);
+        Self::new(providers)
+    }
+
+    pub fn providers(&self) -> &[Provider] {
+        &self.providers
+    }
+
+    /// The provider for a `From` header: the most specific domain containing
+    /// the sender's. The display name is the sender's to choose, so it is
+    /// never used here.
+    pub fn provider_for(&self, sender: &str) -> Option<&Provider> {
+        let domain = from_domain(sender)?;
+        self.best_match(|p| {
+            p.domains
+                .iter()
+                .filter(|d| is_within(&domain, d))
+                .map(|d| d.len())
+                .max()
+        })
+    }
+
+    /// The provider a sender's display name claims to be: one whose alias
+    /// is the whole name, less words like "support". "PayPal Security"
+    /// claims PayPal; "Chase Smith" and "Google Workspace via Acme" claim
+    /// nothing.
+    pub fn claimed_provider(&self, sender: &str) -> Option<&Provider> {
+        let name = bare_name(&display_name(sender)?);
+        self.providers.iter().find(|p| p.aliases.contains(&name))
+    }
+
+    /// Whether the display name claims a known provider but the address is
+    /// on none of the catalog's domains, as in
+    /// `"PayPal" <security@paypal-security.xyz>`
+    pub fn is_lookalike(&self, sender: &str) -> bool {
+        self.provider_for(sender).is_none() && self.claimed_provider(sender).is_some()
+    }
+
+    // The provider with the highest score, keeping the earlier of equal ones
+    fn best_match(&self, score: impl Fn(&Provider) -> Option<usize>) -> Option<&Provider> {
+        let mut best: Option<(usize, &Provider)> = None;
+        for provider in &self.providers {
+            if let Some(s) = score(provider) {
+                if best.is_none_or(|(b, _)| s > b) {
+                    best = Some((s, provider));
+                }
+            }
+        }
+        best.map(|(_, provider)| provider)
+    }
+}
+
+fn normalize(mut provider: Provider) -> Provider {
+    provider.domains = provider
+        .domains
+        .iter()
+        .map(|d| d.trim().trim_start_matches('.').to_lowercase())
+        .filter(|d| !d.is_empty())
+        .collect();
+    provider.aliases = provider
+        .aliases
+        .iter()
+        .map(|alias| bare_name(alias))
+        .filter(|alias| !alias.is_empty())
+        .collect();
+    provider
+}
+
+fn is_within(domain: &str, parent: &str) -> bool {
+    domain == parent || domain.ends_with(&format!(".{}", parent))
+}
+
+fn display_name(sender: &str) -> Option<String> {
+    let (name, _) = sender.split_once('<')?;
+    let name = name.trim().trim_matches('"').trim();
+    (!name.is_empty()).then(|| name.to_lowercase())
+}
+
+// Words a provider's own display name may add to its brand
+const GENERIC_NAME_WORDS: &[&str] = &[
+    "account",
+    "accounts",
+    "alerts",
+    "billing",
+    "help",
+    "no",
+    "noreply",
+    "notifications",
+    "reply",
+    "security",
+    "service",
+    "services",
+    "support",
+    "team",
+    "the",
+];
+
+// A display name as lowercase words, less generic ones
+fn bare_name(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !GENERIC_NAME_WORDS.contains(word))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The part of a domain its owner registered, one label below the public
+/// suffix: `mail.example.co.uk` -> `example.co.uk`
+pub fn registrable_domain(domain: &str) -> Option<String> {
+    let domain = domain.trim().trim_end_matches('.').to_lowercase();
+    let registrable = SUFFIXES.domain(domain.as_bytes())?;
+    std::str::from_utf8(registrable.as_bytes())
+        .ok()
+        .map(str::to_string)
+}
+
+pub fn get_overrides_path() -> Result<PathBuf, String> {
+    let mut path = dirs::config_dir().ok_or("Failed to get config directory")?;
+    path.push("otpbar");
+    fs::create_dir_all(&path).map_err(|e| format!("Failed to create config directory: {}", e))?;
+    path.push(OVERRIDES_FILE);
+    Ok(path)
+}
+
+pub fn load_overrides() -> Vec<Provider> {
+    match get_overrides_path() {
+        Ok(path) if path.exists() => match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                log::warn!("Failed to parse provider overrides file: {}", e);
+                Vec::new()
+            }),
+            Err(e) => {
+                log::warn!("Failed to read provider overrides file: {}", e);
+                Vec::new()
+            }
+        },
+        _ => Vec::new(),
+    }
+}
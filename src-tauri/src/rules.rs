@@ -0,0 +1,179 @@
+use crate::otp::OtpMatch;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+const RULES_FILE: &str = "extraction_rules.json";
+// Keeps a pasted pattern from compiling into something huge
+const MAX_REGEX_SIZE: usize = 1 << 20;
+const CODE_GROUP: &str = "code";
+
+/// A user-defined way to find the code in one sender's emails, tried before
+/// the built-in extractor
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtractionRule {
+    pub id: String,
+    /// An address (`noreply@example.com`) or a domain (`example.com`, which
+    /// also covers its subdomains)
+    pub sender: String,
+    #[serde(flatten)]
+    pub pattern: RulePattern,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RulePattern {
+    /// A regex with a `(?P<code>...)` group around the code
+    Regex { pattern: String },
+    /// The code is the first word after this phrase, e.g. "Your PIN is"
+    AfterPhrase { phrase: String },
+}
+
+impl RulePattern {
+    pub fn compile(&self) -> Result<Regex, String> {
+        let source = match self {
+            RulePattern::Regex { pattern } => pattern.clone(),
+            RulePattern::AfterPhrase { phrase } => {
+                let phrase = phrase.trim();
+                if phrase.is_empty() {
+                    return Err("Phrase must not be empty".to_string());
+                }
+                format!(
+                    r"(?i){}[\s:#=-]*(?P<code>[A-Za-z0-9]+(?:-[A-Za-z0-9]+)*)",
+                    regex::escape(phrase)
+                )
+            }
+        };
+
+        let regex = RegexBuilder::new(&source)
+            .size_limit(MAX_REGEX_SIZE)
+            .build()
+            .map_err(|e| format!("Invalid pattern: {}", e))?;
+        if !regex.capture_names().any(|name| name == Some(CODE_GROUP)) {
+            return Err("Pattern needs a named group (?P<code>...) around the code".to_string());
+        }
+        Ok(regex)
+    }
+}
+
+/// Run one pattern over pasted sample text, so a rule can be checked before
+/// it is saved
+pub fn test_rule(pattern: &RulePattern, sample: &str) -> Result<Option<String>, String> {
+    let regex = pattern.compile()?;
+    Ok(find_code(&regex, sample))
+}
+
+/// The code from the first rule for this sender that matches the subject or
+/// body
+pub fn apply_rules(
+    rules: &[ExtractionRule],
+    from: &str,
+    subject: &str,
+    body: &str,
+) -> Option<OtpMatch> {
+    rules
+        .iter()
+        .filter(|rule| sender_matches(&rule.sender, from))
+        .find_map(|rule| {
+            let regex = rule
+                .pattern
+                .compile()
+                .map_err(|e| log::warn!("Skipping extraction rule {}: {}", rule.id, e))
+                .ok()?;
+            find_code(&regex, subject).or_else(|| find_code(&regex, body))
+        })
+        .map(|code| OtpMatch {
+            display: code.clone(),
+            code,
+            confidence: 1.0,
+        })
+}
+
+fn find_code(regex: &Regex, text: &str) -> Option<String> {
+    regex
+        .captures_iter(text)
+        .filter_map(|caps| caps.name(CODE_GROUP))
+        .map(|m| m.as_str().trim().to_string())
+        .find(|code| !code.is_empty())
+}
+
+/// Whether a `From` header belongs to a rule's sender
+pub fn sender_matches(matcher: &str, from: &str) -> bool {
+    let matcher = matcher.trim().to_lowercase();
+    if matcher.is_empty() {
+        return false;
+    }
+
+    let from = from.to_lowercase();
+    let address = match (from.rfind('<'), from.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &from[start + 1..end],
+        _ => from.trim(),
+    };
+
+    if matcher.contains('@') {
+        return address == matcher;
+    }
+    let domain = address.rsplit_once('@').map_or("", |(_, domain)| domain);
+    domain == matcher || domain.ends_with(&format!(".{}", matcher))
+}
+
+pub fn get_rules_path() -> Result<PathBuf, String> {
+    let mut path = dirs::config_dir().ok_or("Failed to get config directory")?;
+    path.push("otpbar");
+    fs::create_dir_all(&path).map_err(|e| format!("Failed to create config directory: {}", e))?;
+    path.push(RULES_FILE);
+    Ok(path)
+}
+
+pub fn load_rules() -> Vec<ExtractionRule> {
+    match get_rules_path() {
+        Ok(path) if path.exists() => match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                log::warn!("Failed to parse extraction rules file: {}", e);
+                Vec::new()
+            }),
+            Err(e) => {
+                log::warn!("Failed to read extraction rules file: {}", e);
+                Vec::new()
+            }
+        },
+        _ => Vec::new(),
+    }
+}
+
+fn save_rules(rules: &[ExtractionRule]) -> Result<(), String> {
+    let path = get_rules_path()?;
+    let json = serde_json::to_string_pretty(rules)
+        .map_err(|e| format!("Failed to serialize extraction rules: {}", e))?;
+    fs::write(&path, json).map_err(|e| format!("Failed to save extraction rules: {}", e))
+}
+
+/// Validate and store a new rule
+pub fn add_rule(sender: &str, pattern: RulePattern) -> Result<ExtractionRule, String> {
+    let sender = sender.trim();
+    if sender.is_empty() {
+        return Err("Sender must not be empty".to_string());
+    }
+    pattern.compile()?;
+
+    let rule = ExtractionRule {
+        id: hex::encode(rand::random::<[u8; 8]>()),
+        sender: sender.to_lowercase(),
+        pattern,
+    };
+    let mut rules = load_rules();
+    rules.push(rule.clone());
+    save_rules(&rules)?;
+    Ok(rule)
+}
+
+pub fn remove_rule(id: &str) -> Result<(), String> {
+    let mut rules = load_rules();
+    let before = rules.len();
+    rules.retain(|r| r.id != id);
+    if rules.len() == before {
+        return Err("Extraction rule not found".to_string());
+    }
+    save_rules(&rules)
+}
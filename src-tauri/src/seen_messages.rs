@@ -0,0 +1,125 @@
+use crate::mail_source::hash_message_id;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+const SEEN_MESSAGES_FILE: &str = "seen_messages.json";
+const SEEN_TTL_MS: i64 = 7 * 24 * 60 * 60 * 1000; // 7 days
+const MAX_SEEN_MESSAGES: usize = 5000;
+
+pub fn get_seen_messages_path() -> Result<PathBuf, String> {
+    let mut path = dirs::config_dir().ok_or("Failed to get config directory")?;
+    path.push("otpbar");
+    fs::create_dir_all(&path).map_err(|e| format!("Failed to create config directory: {}", e))?;
+    path.push(SEEN_MESSAGES_FILE);
+    Ok(path)
+}
+
+/// Messages we've already downloaded, so polls can skip them before fetching
+/// the full body. Keys are hashed (see `hash_message_id`) so the file can't be
+/// correlated with provider message IDs. `SeenMessages::default()` is an
+/// in-memory cache that is never written to disk.
+#[derive(Debug, Default)]
+pub struct SeenMessages {
+    // hashed message ID -> when it was seen (ms since epoch)
+    entries: HashMap<String, i64>,
+    path: Option<PathBuf>,
+    dirty: bool,
+}
+
+impl SeenMessages {
+    /// Load the cache from disk, dropping expired entries
+    pub fn load() -> Self {
+        let path = get_seen_messages_path().ok();
+        let entries = path
+            .as_ref()
+            .filter(|p| p.exists())
+            .and_then(|p| match fs::read_to_string(p) {
+                Ok(content) => serde_json::from_str::<HashMap<String, i64>>(&content)
+                    .map_err(|e| log::warn!("Failed to parse seen messages file: {}", e))
+                    .ok(),
+                Err(e) => {
+                    log::warn!("Failed to read seen messages file: {}", e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        let mut seen = SeenMessages {
+            entries,
+            path,
+            dirty: false,
+        };
+        seen.prune(chrono::Utc::now().timestamp_millis());
+        seen
+    }
+
+    fn key(scope: &str, id: &str) -> String {
+        hash_message_id(&format!("{}:{}", scope, id))
+    }
+
+    /// Whether message `id` was seen within `scope`, usually
+    /// `mail_source::seen_scope`
+    pub fn contains(&self, scope: &str, id: &str) -> bool {
+        self.entries.contains_key(&Self::key(scope, id))
+    }
+
+    pub fn insert(&mut self, scope: &str, id: &str) {
+        self.insert_at(scope, id, chrono::Utc::now().timestamp_millis());
+    }
+
+    /// Record a message as seen at a specific time
+    pub fn insert_at(&mut self, scope: &str, id: &str, seen_at_ms: i64) {
+        if self
+            .entries
+            .insert(Self::key(scope, id), seen_at_ms)
+            .is_none()
+        {
+            self.dirty = true;
+        }
+    }
+
+    /// Drop entries older than the TTL and cap the cache size
+    pub fn prune(&mut self, now_ms: i64) {
+        let before = self.entries.len();
+        self.entries
+            .retain(|_, seen_at| now_ms - *seen_at < SEEN_TTL_MS);
+
+        if self.entries.len() > MAX_SEEN_MESSAGES {
+            let mut by_age: Vec<(String, i64)> = self.entries.drain().collect();
+            by_age.sort_by_key(|(_, seen_at)| std::cmp::Reverse(*seen_at));
+            by_age.truncate(MAX_SEEN_MESSAGES);
+            self.entries = by_age.into_iter().collect();
+        }
+
+        if self.entries.len() != before {
+            self.dirty = true;
+        }
+    }
+
+    /// Write the cache to disk if it changed since the last save
+    pub fn save(&mut self) {
+        self.prune(chrono::Utc::now().timestamp_millis());
+        if !self.dirty {
+            return;
+        }
+        let Some(path) = self.path.as_ref() else {
+            return;
+        };
+        match serde_json::to_string(&self.entries) {
+            Ok(json) => match fs::write(path, json) {
+                Ok(()) => self.dirty = false,
+                Err(e) => log::warn!("Failed to save seen messages: {}", e),
+            },
+            Err(e) => {
+                log::warn!("Failed to serialize seen messages: {}", e);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.dirty = true;
+        self.save();
+    }
+}
@@ -0,0 +1,157 @@
+use crate::providers::registrable_domain;
+use crate::types::SenderAuth;
+
+/// One `method=result` clause of an `Authentication-Results` header, e.g.
+/// `dkim=pass header.d=paypal.com`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodResult {
+    pub method: String,
+    pub result: String,
+    pub properties: Vec<(String, String)>,
+}
+
+impl MethodResult {
+    fn property(&self, name: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn passed(&self) -> bool {
+        self.result == "pass"
+    }
+}
+
+/// Check SPF, DKIM and DMARC results recorded by the receiving server against
+/// the `From` address.
+///
+/// Only the topmost `Authentication-Results` header is read, since the
+/// receiving server adds its own above any the sender could forge.
+/// `ARC-Authentication-Results` is ignored: without validating the ARC chain
+/// it is just another header the sender could have written.
+///
+/// A sender is verified when DMARC passes for the `From` domain, or when
+/// DKIM or SPF passes for a domain aligned with it. Verified only vouches for
+/// that domain, not for the display name or the brand it resembles.
+pub fn verify_sender<'a>(
+    headers: impl IntoIterator<Item = (&'a str, &'a str)>,
+    from: &str,
+) -> SenderAuth {
+    let results = headers
+        .into_iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("authentication-results"))
+        .map(|(_, value)| parse_authentication_results(value));
+
+    let Some(results) = results.filter(|r| !r.is_empty()) else {
+        return SenderAuth::Unknown;
+    };
+    let Some(from_domain) = from_domain(from) else {
+        return SenderAuth::Unverified;
+    };
+
+    // Anything other than pass or fail ("none", Gmail's "bestguesspass")
+    // means the domain has no policy, so DKIM and SPF decide
+    match results.iter().find(|r| r.method == "dmarc") {
+        Some(dmarc) if dmarc.passed() => {
+            let for_from = dmarc
+                .property("header.from")
+                .is_none_or(|d| d.eq_ignore_ascii_case(&from_domain));
+            return if for_from {
+                SenderAuth::Verified
+            } else {
+                SenderAuth::Unverified
+            };
+        }
+        Some(dmarc) if dmarc.result == "fail" => return SenderAuth::Unverified,
+        _ => {}
+    }
+
+    let aligned = results.iter().filter(|r| r.passed()).any(|r| {
+        let domain = match r.method.as_str() {
+            "dkim" => r.property("header.d").or(r.property("header.i")),
+            "spf" => r.property("smtp.mailfrom"),
+            _ => None,
+        };
+        domain
+            .map(|d| d.rsplit('@').next().unwrap_or(d).to_lowercase())
+            .is_some_and(|d| is_aligned(&d, &from_domain))
+    });
+    if aligned {
+        SenderAuth::Verified
+    } else {
+        SenderAuth::Unverified
+    }
+}
+
+/// Parse the `method=result property=value` clauses of an
+/// `Authentication-Results` or `ARC-Authentication-Results` value, skipping
+/// the server identifier, the ARC instance and comments
+pub fn parse_authentication_results(value: &str) -> Vec<MethodResult> {
+    strip_comments(value)
+        .split(';')
+        .filter_map(|clause| {
+            let mut tokens = clause.split_whitespace();
+            let (method, result) = tokens.next()?.split_once('=')?;
+            let method = method.to_ascii_lowercase();
+            // The ARC instance tag looks like a result
+            if method == "i" {
+                return None;
+            }
+            let properties = tokens
+                .filter_map(|token| {
+                    let (name, value) = token.split_once('=')?;
+                    Some((
+                        name.to_ascii_lowercase(),
+                        value.trim_matches('"').to_string(),
+                    ))
+                })
+                .collect();
+            Some(MethodResult {
+                method,
+                result: result.to_ascii_lowercase(),
+                properties,
+            })
+        })
+        .collect()
+}
+
+fn strip_comments(value: &str) -> String {
+    let mut depth = 0usize;
+    value
+        .chars()
+        .filter(|&c| {
+            match c {
+                '(' => depth += 1,
+                ')' if depth > 0 => {
+                    depth -= 1;
+                    return false;
+                }
+                _ => {}
+            }
+            depth == 0
+        })
+        .collect()
+}
+
+pub(crate) fn from_domain(from: &str) -> Option<String> {
+    let from = from.to_lowercase();
+    let address = match (from.rfind('<'), from.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &from[start + 1..end],
+        _ => from.trim(),
+    };
+    address
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.to_string())
+        .filter(|domain| !domain.is_empty())
+}
+
+/// DMARC relaxed alignment: both domains have the same organizational
+/// domain, so `mail.example.co.uk` aligns with `example.co.uk` but
+/// `evil.co.uk` doesn't
+fn is_aligned(domain: &str, from_domain: &str) -> bool {
+    match (registrable_domain(domain), registrable_domain(from_domain)) {
+        (Some(a), Some(b)) => a == b,
+        _ => domain == from_domain,
+    }
+}
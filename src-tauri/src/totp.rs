@@ -0,0 +1,426 @@
+use crate::keychain::KeychainManager;
+use crate::types::{CodeEntry, EntryKind, SenderAuth};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+const TOTP_ACCOUNTS_FILE: &str = "totp_accounts.json";
+const DEFAULT_DIGITS: u32 = 6;
+const DEFAULT_PERIOD: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TotpAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl TotpAlgorithm {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_ascii_uppercase().replace('-', "").as_str() {
+            "SHA1" => Ok(TotpAlgorithm::Sha1),
+            "SHA256" => Ok(TotpAlgorithm::Sha256),
+            "SHA512" => Ok(TotpAlgorithm::Sha512),
+            other => Err(format!("Unsupported OTP algorithm: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OtpKind {
+    #[default]
+    Totp,
+    Hotp,
+}
+
+/// Settings for one authenticator account. The secret itself lives in the
+/// keychain under the account ID.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TotpAccount {
+    pub id: String,
+    pub kind: OtpKind,
+    pub issuer: String,
+    pub label: String,
+    pub algorithm: TotpAlgorithm,
+    pub digits: u32,
+    pub period: u64,
+    // Next HOTP counter value; unused for TOTP
+    #[serde(default)]
+    pub counter: u64,
+}
+
+/// An account parsed from a URI or export file that hasn't been stored yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedAccount {
+    pub account: TotpAccount,
+    pub secret: Vec<u8>,
+}
+
+impl ImportedAccount {
+    /// Check the account's parameters before it is stored
+    pub fn new(account: TotpAccount, secret: Vec<u8>) -> Result<Self, String> {
+        if secret.is_empty() {
+            return Err("OTP secret is empty".to_string());
+        }
+        if !(4..=10).contains(&account.digits) {
+            return Err(format!(
+                "Unsupported number of OTP digits: {}",
+                account.digits
+            ));
+        }
+        if account.period == 0 {
+            return Err("OTP period must be greater than zero".to_string());
+        }
+
+        Ok(ImportedAccount { account, secret })
+    }
+}
+
+fn hmac_digest(algorithm: TotpAlgorithm, key: &[u8], message: &[u8]) -> Vec<u8> {
+    // HMAC accepts keys of any length, so new_from_slice cannot fail
+    match algorithm {
+        TotpAlgorithm::Sha1 => {
+            let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC takes any key length");
+            mac.update(message);
+            mac.finalize().into_bytes().to_vec()
+        }
+        TotpAlgorithm::Sha256 => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes any key length");
+            mac.update(message);
+            mac.finalize().into_bytes().to_vec()
+        }
+        TotpAlgorithm::Sha512 => {
+            let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC takes any key length");
+            mac.update(message);
+            mac.finalize().into_bytes().to_vec()
+        }
+    }
+}
+
+/// RFC 4226 HOTP value for `counter`
+pub fn hotp(secret: &[u8], counter: u64, digits: u32, algorithm: TotpAlgorithm) -> String {
+    let digest = hmac_digest(algorithm, secret, &counter.to_be_bytes());
+
+    // Dynamic truncation
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+
+    let code = binary as u64 % 10u64.pow(digits);
+    format!("{:0width$}", code, width = digits as usize)
+}
+
+/// RFC 6238 TOTP value at `unix_time`
+pub fn totp(
+    secret: &[u8],
+    unix_time: u64,
+    period: u64,
+    digits: u32,
+    algorithm: TotpAlgorithm,
+) -> String {
+    hotp(secret, unix_time / period, digits, algorithm)
+}
+
+/// Seconds until the TOTP value at `unix_time` rolls over
+pub fn remaining_seconds(unix_time: u64, period: u64) -> u64 {
+    period - unix_time % period
+}
+
+/// Decode a base32 secret as shown by most services (case-insensitive,
+/// spaces and padding optional)
+pub fn decode_secret(secret: &str) -> Result<Vec<u8>, String> {
+    let normalized: String = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-' && *c != '=')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    BASE32_NOPAD
+        .decode(normalized.as_bytes())
+        .map_err(|e| format!("Invalid OTP secret: {}", e))
+}
+
+pub fn encode_secret(secret: &[u8]) -> String {
+    BASE32_NOPAD.encode(secret)
+}
+
+/// Split an "Issuer:account" label, which carries the issuer when it isn't
+/// given separately
+pub(crate) fn split_label(label: &str) -> (Option<String>, String) {
+    match label.split_once(':') {
+        Some((issuer, name)) => (Some(issuer.trim().to_string()), name.trim().to_string()),
+        None => (None, label.trim().to_string()),
+    }
+}
+
+/// Parse an `otpauth://totp/...` or `otpauth://hotp/...` URI
+pub fn parse_otpauth_uri(uri: &str) -> Result<ImportedAccount, String> {
+    let uri = uri.trim();
+    let rest = uri
+        .get(..10)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("otpauth://"))
+        .map(|_| &uri[10..])
+        .ok_or("Not an otpauth:// URI")?;
+
+    let (kind, rest) = rest.split_once('/').ok_or("Missing OTP type in URI")?;
+    let kind = match kind.to_ascii_lowercase().as_str() {
+        "totp" => OtpKind::Totp,
+        "hotp" => OtpKind::Hotp,
+        other => return Err(format!("Unsupported OTP type: {}", other)),
+    };
+
+    let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let label = urlencoding::decode(label)
+        .map_err(|e| format!("Invalid label in URI: {}", e))?
+        .into_owned();
+
+    let params: HashMap<String, String> = query
+        .split('&')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            let value = value.replace('+', " ");
+            let value = urlencoding::decode(&value).ok()?.into_owned();
+            Some((key.to_ascii_lowercase(), value))
+        })
+        .collect();
+
+    let secret = decode_secret(params.get("secret").ok_or("Missing secret in URI")?)?;
+
+    let (label_issuer, account_name) = split_label(&label);
+    let issuer = params
+        .get("issuer")
+        .cloned()
+        .or(label_issuer)
+        .unwrap_or_default();
+
+    let algorithm = match params.get("algorithm") {
+        Some(name) => TotpAlgorithm::from_name(name)?,
+        None => TotpAlgorithm::default(),
+    };
+    let digits = match params.get("digits") {
+        Some(digits) => digits
+            .parse()
+            .map_err(|_| format!("Invalid digits in URI: {}", digits))?,
+        None => DEFAULT_DIGITS,
+    };
+    let period = match params.get("period") {
+        Some(period) => period
+            .parse()
+            .map_err(|_| format!("Invalid period in URI: {}", period))?,
+        None => DEFAULT_PERIOD,
+    };
+    let counter = match (kind, params.get("counter")) {
+        (_, Some(counter)) => counter
+            .parse()
+            .map_err(|_| format!("Invalid counter in URI: {}", counter))?,
+        (OtpKind::Hotp, None) => return Err("Missing counter in HOTP URI".to_string()),
+        (OtpKind::Totp, None) => 0,
+    };
+
+    ImportedAccount::new(
+        TotpAccount {
+            id: String::new(),
+            kind,
+            issuer: issuer.trim().to_string(),
+            label: account_name.trim().to_string(),
+            algorithm,
+            digits,
+            period,
+            counter,
+        },
+        secret,
+    )
+}
+
+pub fn get_totp_accounts_path() -> Result<PathBuf, String> {
+    let mut path = dirs::config_dir().ok_or("Failed to get config directory")?;
+    path.push("otpbar");
+    fs::create_dir_all(&path).map_err(|e| format!("Failed to create config directory: {}", e))?;
+    path.push(TOTP_ACCOUNTS_FILE);
+    Ok(path)
+}
+
+pub fn load_totp_accounts() -> Vec<TotpAccount> {
+    match get_totp_accounts_path() {
+        Ok(path) if path.exists() => match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                log::warn!("Failed to parse TOTP accounts file: {}", e);
+                Vec::new()
+            }),
+            Err(e) => {
+                log::warn!("Failed to read TOTP accounts file: {}", e);
+                Vec::new()
+            }
+        },
+        _ => Vec::new(),
+    }
+}
+
+fn save_totp_accounts(accounts: &[TotpAccount]) -> Result<(), String> {
+    let path = get_totp_accounts_path()?;
+    let json = serde_json::to_string_pretty(accounts)
+        .map_err(|e| format!("Failed to serialize TOTP accounts: {}", e))?;
+    fs::write(&path, json).map_err(|e| format!("Failed to save TOTP accounts: {}", e))
+}
+
+/// Store an imported account, keeping its secret in the keychain
+pub fn add_totp_account(imported: ImportedAccount) -> Result<TotpAccount, String> {
+    add_totp_accounts(vec![imported])?
+        .pop()
+        .ok_or_else(|| "Failed to add authenticator account".to_string())
+}
+
+/// Store several imported accounts at once. Nothing is kept if any secret
+/// can't be written.
+pub fn add_totp_accounts(imported: Vec<ImportedAccount>) -> Result<Vec<TotpAccount>, String> {
+    let mut added: Vec<TotpAccount> = Vec::with_capacity(imported.len());
+
+    for entry in imported {
+        let mut account = entry.account;
+        account.id = hex::encode(rand::random::<[u8; 8]>());
+
+        if let Err(e) = KeychainManager::set_totp_secret(&account.id, &encode_secret(&entry.secret))
+        {
+            for account in &added {
+                let _ = KeychainManager::delete_totp_secret(&account.id);
+            }
+            return Err(e);
+        }
+        added.push(account);
+    }
+
+    let mut accounts = load_totp_accounts();
+    accounts.extend(added.iter().cloned());
+    if let Err(e) = save_totp_accounts(&accounts) {
+        for account in &added {
+            let _ = KeychainManager::delete_totp_secret(&account.id);
+        }
+        return Err(e);
+    }
+
+    for account in &added {
+        log::info!("Added {:?} account for {}", account.kind, account.issuer);
+    }
+    Ok(added)
+}
+
+pub fn remove_totp_account(id: &str) -> Result<(), String> {
+    let mut accounts = load_totp_accounts();
+    let before = accounts.len();
+    accounts.retain(|a| a.id != id);
+    if accounts.len() == before {
+        return Err("Authenticator account not found".to_string());
+    }
+
+    save_totp_accounts(&accounts)?;
+    KeychainManager::delete_totp_secret(id)
+}
+
+/// Move an HOTP account on to its next code
+pub fn advance_hotp_counter(id: &str) -> Result<(), String> {
+    let mut accounts = load_totp_accounts();
+    let account = accounts
+        .iter_mut()
+        .find(|a| a.id == id && a.kind == OtpKind::Hotp)
+        .ok_or("HOTP account not found")?;
+    account.counter = account.counter.saturating_add(1);
+    save_totp_accounts(&accounts)
+}
+
+/// Stored accounts with their decoded secrets. Codes are shown every second,
+/// so the accounts file and keychain are read once and kept until the
+/// accounts change.
+#[derive(Default)]
+pub struct TotpCache {
+    accounts: Option<Vec<(TotpAccount, Vec<u8>)>>,
+}
+
+impl TotpCache {
+    /// Current code for every stored account, shaped like an email code
+    pub fn current_codes(&mut self, now_ms: i64) -> Vec<CodeEntry> {
+        let unix_time = (now_ms / 1000).max(0) as u64;
+
+        self.accounts
+            .get_or_insert_with(load_secrets)
+            .iter()
+            .map(|(account, secret)| code_entry(account, secret, unix_time, now_ms))
+            .collect()
+    }
+
+    /// Forget the cached accounts after one is added, removed or advanced
+    pub fn invalidate(&mut self) {
+        self.accounts = None;
+    }
+}
+
+fn load_secrets() -> Vec<(TotpAccount, Vec<u8>)> {
+    load_totp_accounts()
+        .into_iter()
+        .filter_map(|account| {
+            match KeychainManager::get_totp_secret(&account.id)
+                .and_then(|s| s.ok_or_else(|| "No secret stored".to_string()))
+                .and_then(|s| decode_secret(&s))
+            {
+                Ok(secret) => Some((account, secret)),
+                Err(e) => {
+                    log::warn!("Failed to load secret for {}: {}", account.issuer, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Build the displayed entry for one account at `unix_time`
+pub fn code_entry(account: &TotpAccount, secret: &[u8], unix_time: u64, now_ms: i64) -> CodeEntry {
+    let (code, remaining) = match account.kind {
+        OtpKind::Totp => (
+            totp(
+                secret,
+                unix_time,
+                account.period,
+                account.digits,
+                account.algorithm,
+            ),
+            Some(remaining_seconds(unix_time, account.period)),
+        ),
+        OtpKind::Hotp => (
+            hotp(secret, account.counter, account.digits, account.algorithm),
+            None,
+        ),
+    };
+
+    let provider = if account.issuer.is_empty() {
+        account.label.clone()
+    } else {
+        account.issuer.clone()
+    };
+
+    CodeEntry {
+        code,
+        sender: account.label.clone(),
+        provider,
+        timestamp: now_ms,
+        message_id: format!("totp:{}", account.id),
+        account: None,
+        remaining_seconds: remaining,
+        display_code: None,
+        kind: EntryKind::Code,
+        expires_at: None,
+        expired: false,
+        sender_auth: SenderAuth::Unknown,
+        used_at: None,
+    }
+}
@@ -0,0 +1,480 @@
+use crate::totp::{self, ImportedAccount, OtpKind, TotpAccount, TotpAlgorithm};
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+const MIGRATION_SCHEME: &str = "otpauth-migration://";
+const ANDOTP_SALT_LENGTH: usize = 12;
+const ANDOTP_NONCE_LENGTH: usize = 12;
+const ANDOTP_KEY_LENGTH: usize = 32;
+// Exports set their own key derivation cost; refuse ones that would hang the
+// app or exhaust memory. Aegis uses N = 2^15, r = 8, p = 1 and andOTP
+// around 150,000 iterations.
+const MAX_SCRYPT_MEMORY: u64 = 256 * 1024 * 1024;
+const MAX_SCRYPT_P: u32 = 4;
+const MAX_ANDOTP_ITERATIONS: u32 = 1_000_000;
+
+/// Accounts read from an export, and a note for each entry that was left out
+#[derive(Default)]
+pub struct ParsedExport {
+    pub accounts: Vec<ImportedAccount>,
+    pub skipped: Vec<String>,
+}
+
+impl ParsedExport {
+    fn add(&mut self, name: &str, account: Result<ImportedAccount, String>) {
+        match account {
+            Ok(account) => self.accounts.push(account),
+            Err(e) => self.skip(name, &e),
+        }
+    }
+
+    fn skip(&mut self, name: &str, reason: &str) {
+        log::warn!("Skipping imported account: {}", reason);
+        let name = if name.trim().is_empty() {
+            "Unnamed account"
+        } else {
+            name.trim()
+        };
+        self.skipped.push(format!("{}: {}", name, reason));
+    }
+
+    fn extend(&mut self, other: ParsedExport) {
+        self.accounts.extend(other.accounts);
+        self.skipped.extend(other.skipped);
+    }
+}
+
+/// What an import added, and the entries it had to leave out
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportSummary {
+    pub accounts: Vec<TotpAccount>,
+    pub skipped: Vec<String>,
+}
+
+// Google Authenticator's export payload (`otpauth-migration://offline?data=`)
+
+#[derive(Clone, PartialEq, Message)]
+struct MigrationPayload {
+    #[prost(message, repeated, tag = "1")]
+    otp_parameters: Vec<MigrationOtpParameters>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct MigrationOtpParameters {
+    #[prost(bytes = "vec", tag = "1")]
+    secret: Vec<u8>,
+    #[prost(string, tag = "2")]
+    name: String,
+    #[prost(string, tag = "3")]
+    issuer: String,
+    #[prost(int32, tag = "4")]
+    algorithm: i32,
+    #[prost(int32, tag = "5")]
+    digits: i32,
+    #[prost(int32, tag = "6")]
+    otp_type: i32,
+    #[prost(int64, tag = "7")]
+    counter: i64,
+}
+
+/// Parse a Google Authenticator `otpauth-migration://` export URI
+pub fn parse_migration_uri(uri: &str) -> Result<ParsedExport, String> {
+    let uri = uri.trim();
+    let rest = uri
+        .get(..MIGRATION_SCHEME.len())
+        .filter(|scheme| scheme.eq_ignore_ascii_case(MIGRATION_SCHEME))
+        .map(|_| &uri[MIGRATION_SCHEME.len()..])
+        .ok_or("Not an otpauth-migration:// URI")?;
+
+    let (_, query) = rest
+        .split_once('?')
+        .ok_or("Missing data in migration URI")?;
+    let data = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "data")
+        .map(|(_, value)| value)
+        .ok_or("Missing data in migration URI")?;
+
+    // Some scanners leave the base64 `+` as a space
+    let data = urlencoding::decode(data)
+        .map_err(|e| format!("Invalid data in migration URI: {}", e))?
+        .replace(' ', "+");
+    let bytes = STANDARD
+        .decode(data.as_bytes())
+        .map_err(|e| format!("Invalid data in migration URI: {}", e))?;
+    let payload = MigrationPayload::decode(bytes.as_slice())
+        .map_err(|e| format!("Failed to decode migration payload: {}", e))?;
+
+    let mut imported = ParsedExport::default();
+    for params in payload.otp_parameters {
+        let algorithm = match params.algorithm {
+            0 | 1 => TotpAlgorithm::Sha1,
+            2 => TotpAlgorithm::Sha256,
+            3 => TotpAlgorithm::Sha512,
+            other => {
+                imported.skip(&params.issuer, &format!("Unsupported algorithm {}", other));
+                continue;
+            }
+        };
+        let digits = match params.digits {
+            2 => 8,
+            _ => 6,
+        };
+        let kind = match params.otp_type {
+            1 => OtpKind::Hotp,
+            _ => OtpKind::Totp,
+        };
+
+        let (label_issuer, label) = totp::split_label(&params.name);
+        let issuer = if params.issuer.is_empty() {
+            label_issuer.unwrap_or_default()
+        } else {
+            params.issuer
+        };
+
+        let name = issuer.clone();
+        let account = ImportedAccount::new(
+            TotpAccount {
+                id: String::new(),
+                kind,
+                issuer,
+                label,
+                algorithm,
+                digits,
+                period: 30,
+                counter: params.counter.max(0) as u64,
+            },
+            params.secret,
+        );
+        imported.add(&name, account);
+    }
+
+    Ok(imported)
+}
+
+// Aegis vault export, either plain or password-encrypted
+
+#[derive(Deserialize)]
+struct AegisVault {
+    header: AegisHeader,
+    db: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct AegisHeader {
+    slots: Option<Vec<AegisSlot>>,
+    params: Option<AegisKeyParams>,
+}
+
+#[derive(Deserialize)]
+struct AegisSlot {
+    #[serde(rename = "type")]
+    slot_type: u32,
+    key: String,
+    key_params: AegisKeyParams,
+    n: Option<u64>,
+    r: Option<u32>,
+    p: Option<u32>,
+    salt: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AegisKeyParams {
+    nonce: String,
+    tag: String,
+}
+
+#[derive(Deserialize)]
+struct AegisDb {
+    entries: Vec<AegisEntry>,
+}
+
+#[derive(Deserialize)]
+struct AegisEntry {
+    #[serde(rename = "type")]
+    entry_type: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    issuer: String,
+    info: AegisInfo,
+}
+
+#[derive(Deserialize)]
+struct AegisInfo {
+    secret: String,
+    #[serde(default)]
+    algo: Option<String>,
+    #[serde(default)]
+    digits: Option<u32>,
+    #[serde(default)]
+    period: Option<u64>,
+    #[serde(default)]
+    counter: Option<u64>,
+}
+
+// Aegis slot of type 1 is unlocked with the user's password
+const AEGIS_PASSWORD_SLOT: u32 = 1;
+
+/// Parse an Aegis JSON export. Encrypted vaults need the export password.
+pub fn parse_aegis_export(content: &str, password: Option<&str>) -> Result<ParsedExport, String> {
+    let vault: AegisVault = serde_json::from_str(content)
+        .map_err(|e| format!("Failed to parse Aegis export: {}", e))?;
+
+    let db: AegisDb = match (&vault.header.slots, &vault.header.params, vault.db) {
+        (Some(slots), Some(params), serde_json::Value::String(ciphertext)) => {
+            let password =
+                password.ok_or("This Aegis export is encrypted; a password is needed")?;
+            let master_key = unlock_aegis_slots(slots, password)?;
+            let plaintext = aes_gcm_decrypt(
+                &master_key,
+                &decode_hex(&params.nonce)?,
+                &STANDARD
+                    .decode(ciphertext.as_bytes())
+                    .map_err(|e| format!("Invalid Aegis vault data: {}", e))?,
+                &decode_hex(&params.tag)?,
+            )
+            .map_err(|_| "Failed to decrypt Aegis vault".to_string())?;
+            serde_json::from_slice(&plaintext)
+                .map_err(|e| format!("Failed to parse Aegis vault: {}", e))?
+        }
+        (_, _, db @ serde_json::Value::Object(_)) => {
+            serde_json::from_value(db).map_err(|e| format!("Failed to parse Aegis vault: {}", e))?
+        }
+        _ => return Err("Unrecognized Aegis export".to_string()),
+    };
+
+    let mut imported = ParsedExport::default();
+    for entry in db.entries {
+        let kind = match entry.entry_type.as_str() {
+            "totp" => OtpKind::Totp,
+            "hotp" => OtpKind::Hotp,
+            other => {
+                imported.skip(&entry.issuer, &format!("Unsupported type {}", other));
+                continue;
+            }
+        };
+
+        let account = entry
+            .info
+            .algo
+            .as_deref()
+            .map_or(Ok(TotpAlgorithm::default()), TotpAlgorithm::from_name)
+            .and_then(|algorithm| {
+                ImportedAccount::new(
+                    TotpAccount {
+                        id: String::new(),
+                        kind,
+                        issuer: entry.issuer.trim().to_string(),
+                        label: entry.name.trim().to_string(),
+                        algorithm,
+                        digits: entry.info.digits.unwrap_or(6),
+                        period: entry.info.period.unwrap_or(30),
+                        counter: entry.info.counter.unwrap_or(0),
+                    },
+                    totp::decode_secret(&entry.info.secret)?,
+                )
+            });
+        imported.add(&entry.issuer, account);
+    }
+
+    Ok(imported)
+}
+
+/// Recover the vault's master key from the first password slot that opens
+fn unlock_aegis_slots(slots: &[AegisSlot], password: &str) -> Result<Vec<u8>, String> {
+    let mut found_slot = false;
+
+    for slot in slots.iter().filter(|s| s.slot_type == AEGIS_PASSWORD_SLOT) {
+        found_slot = true;
+
+        let (Some(n), Some(r), Some(p), Some(salt)) = (slot.n, slot.r, slot.p, &slot.salt) else {
+            continue;
+        };
+        if !n.is_power_of_two() || n < 2 {
+            return Err("Invalid Aegis key parameters: N must be a power of two".to_string());
+        }
+        if 128 * n.saturating_mul(r as u64) > MAX_SCRYPT_MEMORY || p > MAX_SCRYPT_P {
+            return Err("Aegis key parameters are too large".to_string());
+        }
+
+        let params = scrypt::Params::new(n.trailing_zeros() as u8, r, p, 32)
+            .map_err(|e| format!("Invalid Aegis key parameters: {}", e))?;
+        let mut slot_key = [0u8; 32];
+        scrypt::scrypt(
+            password.as_bytes(),
+            &decode_hex(salt)?,
+            &params,
+            &mut slot_key,
+        )
+        .map_err(|e| format!("Failed to derive Aegis key: {}", e))?;
+
+        if let Ok(master_key) = aes_gcm_decrypt(
+            &slot_key,
+            &decode_hex(&slot.key_params.nonce)?,
+            &decode_hex(&slot.key)?,
+            &decode_hex(&slot.key_params.tag)?,
+        ) {
+            return Ok(master_key);
+        }
+    }
+
+    if found_slot {
+        Err("Incorrect password for Aegis export".to_string())
+    } else {
+        Err("Aegis export has no password slot".to_string())
+    }
+}
+
+// andOTP backup, either a plain JSON array or the encrypted `.json.aes` file
+
+#[derive(Deserialize)]
+struct AndOtpEntry {
+    secret: String,
+    #[serde(default)]
+    issuer: String,
+    #[serde(default)]
+    label: String,
+    #[serde(default)]
+    digits: Option<u32>,
+    #[serde(rename = "type", default)]
+    entry_type: Option<String>,
+    #[serde(default)]
+    algorithm: Option<String>,
+    #[serde(default)]
+    period: Option<u64>,
+    #[serde(default)]
+    counter: Option<u64>,
+}
+
+/// Parse an andOTP backup. Encrypted backups need the backup password.
+pub fn parse_andotp_export(data: &[u8], password: Option<&str>) -> Result<ParsedExport, String> {
+    let entries: Vec<AndOtpEntry> = match serde_json::from_slice(data) {
+        Ok(entries) => entries,
+        Err(_) => {
+            let password =
+                password.ok_or("This andOTP backup is encrypted; a password is needed")?;
+            let plaintext = decrypt_andotp(data, password)?;
+            serde_json::from_slice(&plaintext)
+                .map_err(|e| format!("Failed to parse andOTP backup: {}", e))?
+        }
+    };
+
+    let mut imported = ParsedExport::default();
+    for entry in entries {
+        // Older backups fold the issuer into the label
+        let (label_issuer, label) = totp::split_label(&entry.label);
+        let issuer = if entry.issuer.trim().is_empty() {
+            label_issuer.unwrap_or_default()
+        } else {
+            entry.issuer.trim().to_string()
+        };
+
+        let kind = match entry.entry_type.as_deref().unwrap_or("TOTP") {
+            "TOTP" => OtpKind::Totp,
+            "HOTP" => OtpKind::Hotp,
+            other => {
+                imported.skip(&issuer, &format!("Unsupported type {}", other));
+                continue;
+            }
+        };
+
+        let name = issuer.clone();
+        let account = entry
+            .algorithm
+            .as_deref()
+            .map_or(Ok(TotpAlgorithm::default()), TotpAlgorithm::from_name)
+            .and_then(|algorithm| {
+                ImportedAccount::new(
+                    TotpAccount {
+                        id: String::new(),
+                        kind,
+                        issuer,
+                        label,
+                        algorithm,
+                        digits: entry.digits.unwrap_or(6),
+                        period: entry.period.unwrap_or(30),
+                        counter: entry.counter.unwrap_or(0),
+                    },
+                    totp::decode_secret(&entry.secret)?,
+                )
+            });
+        imported.add(&name, account);
+    }
+
+    Ok(imported)
+}
+
+/// andOTP's encrypted layout: PBKDF2 iteration count (4 bytes, big-endian),
+/// salt, nonce, then AES-256-GCM ciphertext with the tag appended
+fn decrypt_andotp(data: &[u8], password: &str) -> Result<Vec<u8>, String> {
+    let header_length = 4 + ANDOTP_SALT_LENGTH + ANDOTP_NONCE_LENGTH;
+    if data.len() <= header_length {
+        return Err("andOTP backup is too short".to_string());
+    }
+
+    let iterations = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+    if iterations == 0 || iterations > MAX_ANDOTP_ITERATIONS {
+        return Err("andOTP backup has an invalid iteration count".to_string());
+    }
+    let salt = &data[4..4 + ANDOTP_SALT_LENGTH];
+    let nonce = &data[4 + ANDOTP_SALT_LENGTH..header_length];
+    let ciphertext = &data[header_length..];
+
+    let mut key = [0u8; ANDOTP_KEY_LENGTH];
+    pbkdf2::pbkdf2_hmac::<sha1::Sha1>(password.as_bytes(), salt, iterations, &mut key);
+
+    aes_gcm_decrypt(&key, nonce, ciphertext, &[])
+        .map_err(|_| "Incorrect password for andOTP backup".to_string())
+}
+
+/// Detect the export format and parse it. Accepts `otpauth://` and
+/// `otpauth-migration://` URIs (one per line), Aegis exports and andOTP
+/// backups.
+pub fn parse_export(data: &[u8], password: Option<&str>) -> Result<ParsedExport, String> {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let text = text.trim_start_matches('\u{feff}').trim();
+
+        if text.to_ascii_lowercase().starts_with("otpauth") {
+            let mut imported = ParsedExport::default();
+            for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                if line.to_ascii_lowercase().starts_with(MIGRATION_SCHEME) {
+                    imported.extend(parse_migration_uri(line)?);
+                } else {
+                    imported.accounts.push(totp::parse_otpauth_uri(line)?);
+                }
+            }
+            return Ok(imported);
+        }
+
+        if text.starts_with('{') {
+            return parse_aegis_export(text, password);
+        }
+    }
+
+    parse_andotp_export(data, password)
+}
+
+fn aes_gcm_decrypt(key: &[u8], nonce: &[u8], ciphertext: &[u8], tag: &[u8]) -> Result<Vec<u8>, ()> {
+    if nonce.len() != 12 {
+        return Err(());
+    }
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| ())?;
+
+    let mut sealed = Vec::with_capacity(ciphertext.len() + tag.len());
+    sealed.extend_from_slice(ciphertext);
+    sealed.extend_from_slice(tag);
+
+    cipher
+        .decrypt(Nonce::from_slice(nonce), sealed.as_slice())
+        .map_err(|_| ())
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, String> {
+    hex::decode(value).map_err(|e| format!("Invalid hex value in export: {}", e))
+}
@@ -1,6 +1,28 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// What a `CodeEntry` holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    #[default]
+    Code,
+    /// A sign-in or verification link; `code` holds the URL
+    Link,
+}
+
+/// Whether the receiving server vouched for the sender (SPF, DKIM, DMARC)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SenderAuth {
+    /// No authentication results to go on
+    #[default]
+    Unknown,
+    Verified,
+    /// Checks failed or didn't cover the `From` domain
+    Unverified,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CodeEntry {
     pub code: String,
@@ -8,6 +30,31 @@ pub struct CodeEntry {
     pub provider: String,
     pub timestamp: i64,
     pub message_id: String,
+    // Mailbox the code was read from; None for entries saved before
+    // multiple accounts were supported
+    #[serde(default)]
+    pub account: Option<String>,
+    // Seconds until an authenticator code rolls over; None for email codes
+    #[serde(default)]
+    pub remaining_seconds: Option<u64>,
+    // The code as the email showed it (e.g. "G-482913" or "123 456") when
+    // that differs from the copyable `code`
+    #[serde(default)]
+    pub display_code: Option<String>,
+    #[serde(default)]
+    pub kind: EntryKind,
+    // When the code stops working, in ms, if the email said how long it is
+    // valid for
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    // Set on entries past `expires_at` when they are sent to the UI
+    #[serde(default)]
+    pub expired: bool,
+    #[serde(default)]
+    pub sender_auth: SenderAuth,
+    // When the code was copied from the list, in ms
+    #[serde(default)]
+    pub used_at: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,6 +63,13 @@ pub struct AuthResult {
     pub error: Option<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountInfo {
+    pub provider: String,
+    pub account: Option<String>,
+    pub authenticated: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClipboardConfig {
     pub timeout_seconds: u64,
@@ -33,6 +87,63 @@ impl Default for ClipboardConfig {
 pub struct PrivacyPreferences {
     pub auto_copy_enabled: bool,
     pub provider_auto_copy: HashMap<String, bool>,
+    // Auto-copy codes from senders that failed SPF/DKIM/DMARC checks
+    #[serde(default = "default_true")]
+    pub auto_copy_unverified: bool,
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+    #[serde(default)]
+    pub used_codes: UsedCodeDisplay,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// How the menubar list shows codes that have already been copied
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UsedCodeDisplay {
+    #[default]
+    Dim,
+    Hide,
+}
+
+/// How much code history is kept, in memory and on disk
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    // Entries older than this are dropped; None keeps them until `max_count`
+    // pushes them out
+    #[serde(default)]
+    pub max_age_days: Option<u32>,
+    #[serde(default = "default_max_count")]
+    pub max_count: usize,
+    // How many of the newest entries the menubar list shows; older ones are
+    // reached through the history commands
+    #[serde(default = "default_recent_count")]
+    pub recent_count: usize,
+    // Save when and from whom codes arrived, but not the codes themselves
+    #[serde(default)]
+    pub metadata_only: bool,
+}
+
+fn default_max_count() -> usize {
+    50
+}
+
+fn default_recent_count() -> usize {
+    10
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_age_days: None,
+            max_count: default_max_count(),
+            recent_count: default_recent_count(),
+            metadata_only: false,
+        }
+    }
 }
 
 impl Default for PrivacyPreferences {
@@ -43,18 +154,24 @@ impl Default for PrivacyPreferences {
         Self {
             auto_copy_enabled: true,
             provider_auto_copy,
+            auto_copy_unverified: true,
+            retention: RetentionPolicy::default(),
+            used_codes: UsedCodeDisplay::default(),
         }
     }
 }
 
 // Forward declaration - actual module is in main.rs
 pub struct AppState {
-    pub gmail_client: tokio::sync::Mutex<Option<crate::gmail::GmailClient>>,
-    pub recent_codes: tokio::sync::Mutex<Vec<CodeEntry>>,
+    // Shared so the poll loop can fetch from a snapshot without holding the lock
+    pub mail_sources: tokio::sync::Mutex<Vec<std::sync::Arc<dyn crate::mail_source::MailSource>>>,
+    pub history: tokio::sync::Mutex<crate::history::HistoryStore>,
     pub last_notification: tokio::sync::Mutex<u64>,
     pub is_polling: tokio::sync::Mutex<bool>,
     pub clipboard_config: tokio::sync::Mutex<ClipboardConfig>,
     pub privacy_preferences: tokio::sync::Mutex<PrivacyPreferences>,
     pub backoff_until: tokio::sync::Mutex<Option<i64>>,
     pub backoff_logged: tokio::sync::Mutex<bool>,
+    pub seen_messages: tokio::sync::Mutex<crate::seen_messages::SeenMessages>,
+    pub totp_cache: tokio::sync::Mutex<crate::totp::TotpCache>,
 }
@@ -0,0 +1,68 @@
+// Tests for reading a code's stated lifetime from the email
+
+use otpbar::expiry::stated_validity;
+
+#[test]
+fn english_phrasings() {
+    let cases = [
+        ("Your code is 482913. It expires in 10 minutes.", 600),
+        ("This code is valid for 5 min", 300),
+        ("Enter it within 15 mins to continue", 900),
+        ("Please enter the code within 5 minutes", 300),
+        ("Good for 30 seconds only", 30),
+        ("The link will expire after 1 hour", 3600),
+        ("Use it in the next 10 minutes; after that it expires", 600),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(stated_validity(text), Some(expected), "{}", text);
+    }
+}
+
+#[test]
+fn other_languages() {
+    let cases = [
+        ("Der Code ist 10 Minuten gültig.", 600),
+        ("Ihr Code läuft in 5 Minuten ab", 300),
+        ("El código caduca en 15 minutos", 900),
+        ("Este código es válido durante 2 horas", 7200),
+        ("Ce code expire dans 10 minutes", 600),
+        ("Code valable 30 secondes", 30),
+        ("O código expira em 10 minutos", 600),
+        ("このコードは10分間有効です", 600),
+        ("验证码10分钟内有效", 600),
+        ("请在5分钟内输入验证码", 300),
+        ("驗證碼有效期為5分鐘", 300),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(stated_validity(text), Some(expected), "{}", text);
+    }
+}
+
+#[test]
+fn durations_without_validity_wording_are_ignored() {
+    for text in [
+        "Your code is 482913",
+        "Your order ships in 30 minutes",
+        "We last saw you 10 minutes ago",
+        "Your subscription is valid for 30 days",
+        "Delivered 2 hours late",
+        "We'll get back to you within 2 hours",
+        "我们将在2小时内回复您",
+    ] {
+        assert_eq!(stated_validity(text), None, "{}", text);
+    }
+}
+
+#[test]
+fn stunden_is_hours() {
+    assert_eq!(stated_validity("Der Link ist 2 Stunden gültig"), Some(7200));
+}
+
+#[test]
+fn imap_message_date_is_read() {
+    let raw = b"Date: Tue, 14 Oct 2025 09:30:00 +0200\r\n\
+Subject: Code\r\n\r\n\
+Your code is 482913\r\n";
+    let message = otpbar::imap::parse_raw_message("1", raw);
+    assert_eq!(message.sent_at, Some(1_760_427_000_000));
+}
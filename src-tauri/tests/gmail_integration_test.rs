@@ -0,0 +1,230 @@
+// Integration tests for the Gmail mail source against a local mock of the Gmail API
+
+use http_body_util::{BodyExt, Full};
+use hyper::{server::conn::http1, service::service_fn, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use otpbar::gmail::GmailClient;
+use otpbar::mail_source::{self, MailSource};
+use otpbar::seen_messages::SeenMessages;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+
+struct MockGmail {
+    // Unique per test, since the history cursor is saved per account
+    account: String,
+    history_expired: AtomicBool,
+    broken_message: AtomicBool,
+    requested_paths: Mutex<Vec<String>>,
+}
+
+fn json(status: StatusCode, body: &str) -> Response<Full<hyper::body::Bytes>> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Full::new(body.to_string().into()))
+        .unwrap()
+}
+
+fn detail(code: &str) -> String {
+    format!(
+        r#"{{
+            "snippet": "Your verification code is {}",
+            "internalDate": "1760427000000",
+            "payload": {{
+                "mimeType": "text/plain",
+                "headers": [
+                    {{"name": "From", "value": "GitHub <noreply@github.com>"}},
+                    {{"name": "Subject", "value": "Your GitHub launch code"}}
+                ],
+                "body": {{"data": ""}}
+            }}
+        }}"#,
+        code
+    )
+}
+
+async fn handle(
+    req: Request<hyper::body::Incoming>,
+    mock: Arc<MockGmail>,
+) -> Result<Response<Full<hyper::body::Bytes>>, hyper::Error> {
+    let path = req.uri().path().to_string();
+    let query = req.uri().query().unwrap_or_default().to_string();
+    mock.requested_paths
+        .lock()
+        .unwrap()
+        .push(req.uri().to_string());
+
+    if path == "/token" {
+        req.into_body().collect().await?;
+        return Ok(json(
+            StatusCode::OK,
+            r#"{"access_token":"mock-access","refresh_token":"mock-refresh","expires_in":3600}"#,
+        ));
+    }
+
+    let authorized = req
+        .headers()
+        .get("authorization")
+        .map(|v| v == "Bearer mock-access")
+        .unwrap_or(false);
+    if !authorized {
+        return Ok(json(
+            StatusCode::UNAUTHORIZED,
+            r#"{"error":"unauthorized"}"#,
+        ));
+    }
+
+    let response = match path.as_str() {
+        "/gmail/v1/users/me/profile" => json(
+            StatusCode::OK,
+            &format!(r#"{{"emailAddress":"{}","historyId":"100"}}"#, mock.account),
+        ),
+        "/gmail/v1/users/me/messages" => json(StatusCode::OK, r#"{"messages":[{"id":"m1"}]}"#),
+        "/gmail/v1/users/me/history" if mock.history_expired.load(Ordering::SeqCst) => json(
+            StatusCode::NOT_FOUND,
+            r#"{"error":{"code":404,"message":"Requested entity was not found."}}"#,
+        ),
+        "/gmail/v1/users/me/history" if query.contains("startHistoryId=100") => json(
+            StatusCode::OK,
+            r#"{
+                "history": [
+                    {"messagesAdded": [{"message": {"id": "m2", "labelIds": ["INBOX", "UNREAD"]}}]},
+                    {"messagesAdded": [{"message": {"id": "m3", "labelIds": ["INBOX"]}}]}
+                ],
+                "historyId": "105"
+            }"#,
+        ),
+        "/gmail/v1/users/me/history" => json(StatusCode::OK, r#"{"historyId":"105"}"#),
+        "/gmail/v1/users/me/messages/m1" => json(StatusCode::OK, &detail("482913")),
+        "/gmail/v1/users/me/messages/m2" if mock.broken_message.load(Ordering::SeqCst) => json(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            r#"{"error":"backend error"}"#,
+        ),
+        "/gmail/v1/users/me/messages/m2" => json(StatusCode::OK, &detail("739204")),
+        _ => json(StatusCode::NOT_FOUND, r#"{"error":"not found"}"#),
+    };
+    Ok(response)
+}
+
+async fn start_mock() -> (String, Arc<MockGmail>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let mock = Arc::new(MockGmail {
+        account: format!(
+            "test-{}@example.com",
+            hex::encode(rand::random::<[u8; 6]>())
+        ),
+        history_expired: AtomicBool::new(false),
+        broken_message: AtomicBool::new(false),
+        requested_paths: Mutex::new(Vec::new()),
+    });
+    let shared = Arc::clone(&mock);
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mock = Arc::clone(&shared);
+            tokio::spawn(async move {
+                let service = service_fn(move |req| handle(req, Arc::clone(&mock)));
+                let _ = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+
+    (base, mock)
+}
+
+async fn signed_in_client(base: &str) -> GmailClient {
+    let mut client = GmailClient::with_endpoints(
+        "test-client",
+        "",
+        &format!("{}/gmail/v1/users/me", base),
+        &format!("{}/token", base),
+    );
+    client
+        .get_auth_url("http://127.0.0.1:53682/callback", "test-state")
+        .unwrap();
+    client
+        .exchange_code("auth-code")
+        .await
+        .expect("Exchange should succeed");
+    client
+}
+
+fn history_starts(mock: &MockGmail) -> Vec<String> {
+    mock.requested_paths
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|p| p.starts_with("/gmail/v1/users/me/history?"))
+        .filter_map(|p| p.split("startHistoryId=").nth(1))
+        .map(|rest| rest.split('&').next().unwrap_or_default().to_string())
+        .collect()
+}
+
+async fn poll(client: &GmailClient, seen: &mut SeenMessages) -> Vec<String> {
+    mail_source::fetch_new_messages(&[client], seen)
+        .await
+        .remove(0)
+        .unwrap()
+        .into_iter()
+        .map(|m| m.id)
+        .collect()
+}
+
+#[tokio::test]
+async fn gmail_syncs_new_messages_from_history() {
+    let (base, mock) = start_mock().await;
+    let client = signed_in_client(&base).await;
+    let mut seen = SeenMessages::default();
+
+    // No cursor yet: list unread mail and start from the profile's historyId
+    assert_eq!(poll(&client, &mut seen).await, ["m1"]);
+    // Read messages (no UNREAD label) are left out
+    assert_eq!(poll(&client, &mut seen).await, ["m2"]);
+    assert!(poll(&client, &mut seen).await.is_empty());
+    assert_eq!(history_starts(&mock), ["100", "105"]);
+
+    client.clear_auth().await.unwrap();
+}
+
+#[tokio::test]
+async fn gmail_keeps_the_cursor_until_messages_are_fetched() {
+    let (base, mock) = start_mock().await;
+    let client = signed_in_client(&base).await;
+    let mut seen = SeenMessages::default();
+    poll(&client, &mut seen).await;
+
+    mock.broken_message.store(true, Ordering::SeqCst);
+    assert!(poll(&client, &mut seen).await.is_empty());
+
+    mock.broken_message.store(false, Ordering::SeqCst);
+    assert_eq!(poll(&client, &mut seen).await, ["m2"]);
+    assert_eq!(history_starts(&mock), ["100", "100"]);
+
+    client.clear_auth().await.unwrap();
+}
+
+#[tokio::test]
+async fn gmail_falls_back_to_a_full_sync_when_history_expires() {
+    let (base, mock) = start_mock().await;
+    let client = signed_in_client(&base).await;
+    let mut seen = SeenMessages::default();
+    poll(&client, &mut seen).await;
+
+    mock.history_expired.store(true, Ordering::SeqCst);
+    let ids = client.list_new_messages().await.unwrap();
+    assert_eq!(ids, ["m1"]);
+
+    let paths = mock.requested_paths.lock().unwrap().clone();
+    let lists = paths
+        .iter()
+        .filter(|p| p.starts_with("/gmail/v1/users/me/messages?"))
+        .count();
+    assert_eq!(lists, 2);
+
+    client.clear_auth().await.unwrap();
+}
@@ -0,0 +1,173 @@
+// Tests for filtering, sorting and paging the code history
+
+use otpbar::history_query::{query_history, HistoryQuery, HistorySort, MAX_PAGE_SIZE};
+use otpbar::types::{CodeEntry, EntryKind};
+
+// 2025-10-14T00:00:00Z
+const OCT_14: i64 = 1_760_400_000_000;
+const HOUR_MS: i64 = 60 * 60 * 1000;
+
+fn fixture() -> Vec<CodeEntry> {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/history.json");
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+fn codes(query: &HistoryQuery) -> Vec<String> {
+    query_history(&fixture(), query)
+        .entries
+        .into_iter()
+        .map(|e| e.code)
+        .collect()
+}
+
+#[test]
+fn stripe_code_from_this_morning() {
+    let query = HistoryQuery {
+        provider: Some("stripe".to_string()),
+        since: Some(OCT_14),
+        until: Some(OCT_14 + 12 * HOUR_MS),
+        ..Default::default()
+    };
+    assert_eq!(codes(&query), ["482913", "550912"]);
+}
+
+#[test]
+fn empty_query_returns_a_default_page_newest_first() {
+    let page = query_history(&fixture(), &HistoryQuery::default());
+    assert_eq!(page.total, 8);
+    assert_eq!(page.entries.len(), 8);
+    assert!(page
+        .entries
+        .windows(2)
+        .all(|w| w[0].timestamp >= w[1].timestamp));
+}
+
+#[test]
+fn filters_by_account_kind_and_sender() {
+    let work_links = HistoryQuery {
+        account: Some("WORK@example.com".to_string()),
+        kind: Some(EntryKind::Link),
+        ..Default::default()
+    };
+    let page = query_history(&fixture(), &work_links);
+    assert_eq!(page.total, 2);
+    assert!(page.entries.iter().all(|e| e.kind == EntryKind::Link));
+
+    let acme = HistoryQuery {
+        sender: Some("acme bank".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(codes(&acme), ["9931"]);
+
+    // Entries without an account only match when no account is asked for
+    let none = HistoryQuery {
+        account: Some("other@example.com".to_string()),
+        ..Default::default()
+    };
+    assert!(codes(&none).is_empty());
+}
+
+#[test]
+fn free_text_matches_sender_provider_or_account() {
+    let query = HistoryQuery {
+        text: Some("  GIT ".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(codes(&query), ["731045"]);
+
+    let query = HistoryQuery {
+        text: Some("WORK@example".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(codes(&query).len(), 4);
+}
+
+#[test]
+fn history_pages_and_search() {
+    // The queries behind get_history and search_history
+    let page = query_history(&fixture(), &HistoryQuery::page(2, 3));
+    assert_eq!(page.total, 8);
+    let page_codes: Vec<&str> = page.entries.iter().map(|c| c.code.as_str()).collect();
+    assert_eq!(
+        page_codes,
+        ["https://slack.com/z/abc", "550912", "G-118822"]
+    );
+    assert!(query_history(&fixture(), &HistoryQuery::page(10, 3))
+        .entries
+        .is_empty());
+
+    let found = query_history(&fixture(), &HistoryQuery::search("STRIPE", 0, 10));
+    assert_eq!(found.total, 3);
+    assert_eq!(found.entries[0].code, "482913");
+    let mine = query_history(&fixture(), &HistoryQuery::search("me@example", 0, 10));
+    assert_eq!(mine.total, 3);
+}
+
+#[test]
+fn sorts_oldest_first_and_by_provider() {
+    let oldest = HistoryQuery {
+        sort: HistorySort::OldestFirst,
+        limit: Some(2),
+        ..Default::default()
+    };
+    assert_eq!(
+        codes(&oldest),
+        ["204488", "https://www.notion.so/loginwithemail?token=x"]
+    );
+
+    let by_provider = HistoryQuery {
+        sort: HistorySort::Provider,
+        ..Default::default()
+    };
+    let page = query_history(&fixture(), &by_provider);
+    let providers: Vec<&str> = page.entries.iter().map(|e| e.provider.as_str()).collect();
+    assert_eq!(
+        providers,
+        [
+            "Acme Bank",
+            "GitHub",
+            "Google",
+            "Notion",
+            "Slack",
+            "Stripe",
+            "Stripe",
+            "Stripe"
+        ]
+    );
+    let stripe: Vec<&str> = page.entries[5..].iter().map(|e| e.code.as_str()).collect();
+    assert_eq!(stripe, ["482913", "550912", "204488"]);
+}
+
+#[test]
+fn pages_through_results() {
+    let page = |offset| HistoryQuery {
+        offset,
+        limit: Some(3),
+        ..Default::default()
+    };
+    let first = query_history(&fixture(), &page(0));
+    let last = query_history(&fixture(), &page(6));
+    assert_eq!(first.total, 8);
+    assert_eq!(first.entries.len(), 3);
+    assert_eq!(last.entries.len(), 2);
+    assert!(query_history(&fixture(), &page(8)).entries.is_empty());
+
+    let huge = HistoryQuery {
+        limit: Some(10_000),
+        ..Default::default()
+    };
+    let many: Vec<CodeEntry> = (0..150).flat_map(|_| fixture()).collect();
+    assert_eq!(query_history(&many, &huge).entries.len(), MAX_PAGE_SIZE);
+}
+
+#[test]
+fn queries_deserialize_from_the_frontend() {
+    let query: HistoryQuery = serde_json::from_str(
+        r#"{"provider": "Stripe", "kind": "code", "sort": "oldest_first", "limit": 5}"#,
+    )
+    .unwrap();
+    assert_eq!(query.sort, HistorySort::OldestFirst);
+    assert_eq!(query.kind, Some(EntryKind::Code));
+    assert_eq!(query.offset, 0);
+    assert_eq!(codes(&query), ["204488", "550912", "482913"]);
+}
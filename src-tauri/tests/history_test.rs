@@ -0,0 +1,422 @@
+// Tests for the encrypted code history store and its retention policy
+
+use otpbar::history::{
+    apply_retention, decrypt_history, encrypt_history, entries_to_persist, generate_key,
+    parse_plaintext_history, HistoryStore,
+};
+use otpbar::types::{CodeEntry, EntryKind, PrivacyPreferences, RetentionPolicy, UsedCodeDisplay};
+use std::fs;
+use std::path::PathBuf;
+
+const NOW: i64 = 1_760_427_000_000;
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+fn entry(code: &str, sender: &str) -> CodeEntry {
+    CodeEntry {
+        code: code.to_string(),
+        sender: sender.to_string(),
+        provider: "GitHub".to_string(),
+        timestamp: 1_760_427_000_000,
+        message_id: "18c2f0a9b7d4e1f3".to_string(),
+        account: Some("me@example.com".to_string()),
+        remaining_seconds: None,
+        display_code: None,
+        kind: Default::default(),
+        expires_at: None,
+        expired: false,
+        sender_auth: Default::default(),
+        used_at: None,
+    }
+}
+
+fn aged(code: &str, days_old: i64) -> CodeEntry {
+    CodeEntry {
+        timestamp: NOW - days_old * DAY_MS,
+        ..entry(code, "a@b.com")
+    }
+}
+
+fn policy(max_age_days: Option<u32>, max_count: usize, metadata_only: bool) -> RetentionPolicy {
+    RetentionPolicy {
+        max_age_days,
+        max_count,
+        metadata_only,
+        ..RetentionPolicy::default()
+    }
+}
+
+// A history file in its own temp directory, removed when dropped
+struct TempHistory {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl Drop for TempHistory {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn temp_history() -> TempHistory {
+    let dir = std::env::temp_dir().join(format!(
+        "otpbar-history-{}",
+        hex::encode(rand::random::<[u8; 8]>())
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    TempHistory {
+        path: dir.join("code_history.json"),
+        dir,
+    }
+}
+
+fn fresh(code: &str) -> CodeEntry {
+    CodeEntry {
+        timestamp: chrono::Utc::now().timestamp_millis(),
+        ..entry(code, "noreply@github.com")
+    }
+}
+
+fn codes(store: &HistoryStore) -> Vec<&str> {
+    store.entries().iter().map(|c| c.code.as_str()).collect()
+}
+
+fn line_count(path: &PathBuf) -> usize {
+    fs::read_to_string(path).unwrap().lines().count()
+}
+
+#[test]
+fn history_round_trips_without_leaking_codes() {
+    let key = generate_key();
+    let codes = vec![entry("482913", "noreply@github.com")];
+
+    let sealed = encrypt_history(&codes, &key).unwrap();
+    for secret in ["482913", "noreply@github.com", "18c2f0a9b7d4e1f3"] {
+        assert!(!sealed.contains(secret), "{} is in the clear", secret);
+    }
+
+    let opened = decrypt_history(&sealed, &key).unwrap();
+    assert_eq!(opened.len(), 1);
+    assert_eq!(opened[0].code, "482913");
+    assert_eq!(opened[0].account.as_deref(), Some("me@example.com"));
+}
+
+#[test]
+fn each_save_uses_a_fresh_nonce() {
+    let key = generate_key();
+    let codes = vec![entry("482913", "noreply@github.com")];
+    assert_ne!(
+        encrypt_history(&codes, &key).unwrap(),
+        encrypt_history(&codes, &key).unwrap()
+    );
+}
+
+#[test]
+fn wrong_key_or_tampering_is_rejected() {
+    let key = generate_key();
+    let sealed = encrypt_history(&[entry("482913", "a@b.com")], &key).unwrap();
+
+    let err = decrypt_history(&sealed, &generate_key()).unwrap_err();
+    assert!(err.contains("wrong key"), "{}", err);
+
+    let mut value: serde_json::Value = serde_json::from_str(&sealed).unwrap();
+    let ciphertext = value["ciphertext"].as_str().unwrap().to_string();
+    let flipped = if ciphertext.starts_with('A') {
+        "B"
+    } else {
+        "A"
+    };
+    value["ciphertext"] = format!("{}{}", flipped, &ciphertext[1..]).into();
+    assert!(decrypt_history(&value.to_string(), &key).is_err());
+}
+
+#[test]
+fn plaintext_history_is_recognised_for_migration() {
+    let legacy = serde_json::to_string_pretty(&[entry("482913", "a@b.com")]).unwrap();
+    let entries = parse_plaintext_history(&legacy).unwrap();
+    assert_eq!(entries[0].code, "482913");
+
+    let key = generate_key();
+    let sealed = encrypt_history(&entries, &key).unwrap();
+    assert!(parse_plaintext_history(&sealed).is_none());
+    assert!(decrypt_history(&legacy, &key).is_err());
+}
+
+#[test]
+fn retention_drops_old_entries_and_caps_the_count() {
+    let codes = [aged("111111", 0), aged("222222", 6), aged("333333", 8)];
+
+    let kept = apply_retention(&codes, &policy(Some(7), 50, false), NOW);
+    let kept: Vec<&str> = kept.iter().map(|c| c.code.as_str()).collect();
+    assert_eq!(kept, ["111111", "222222"]);
+
+    let kept = apply_retention(&codes, &policy(None, 2, false), NOW);
+    assert_eq!(kept.len(), 2);
+    assert_eq!(
+        apply_retention(&codes, &policy(None, 50, false), NOW).len(),
+        3
+    );
+}
+
+#[test]
+fn metadata_only_history_keeps_no_codes() {
+    let mut link = aged("https://example.com/login?token=abc", 0);
+    link.display_code = Some("482 913".to_string());
+    let codes = [aged("482913", 0), link];
+
+    let persisted = entries_to_persist(&codes, &policy(None, 50, true), NOW);
+    assert_eq!(persisted.len(), 2);
+    for entry in &persisted {
+        assert!(entry.code.is_empty());
+        assert!(entry.display_code.is_none());
+        assert_eq!(entry.sender, "a@b.com");
+        assert_eq!(entry.provider, "GitHub");
+    }
+
+    let persisted = entries_to_persist(&codes, &policy(None, 50, false), NOW);
+    assert_eq!(persisted[0].code, "482913");
+}
+
+#[test]
+fn older_preferences_get_the_default_policy() {
+    let prefs: PrivacyPreferences = serde_json::from_str(
+        r#"{"auto_copy_enabled": true, "provider_auto_copy": {"default": true}}"#,
+    )
+    .unwrap();
+    assert_eq!(prefs.retention, RetentionPolicy::default());
+    assert_eq!(prefs.retention.max_count, 50);
+    assert_eq!(prefs.retention.max_age_days, None);
+}
+
+#[test]
+fn store_appends_one_record_per_code() {
+    let history = temp_history();
+    let path = history.path.clone();
+    let key = generate_key();
+    let policy = RetentionPolicy::default();
+
+    let mut store = HistoryStore::open(path.clone(), Some(key), &policy).unwrap();
+    for code in ["111111", "222222", "333333"] {
+        store.append(fresh(code), &policy);
+    }
+    assert_eq!(line_count(&path), 3);
+    assert_eq!(store.recent(2, true).len(), 2);
+
+    let reopened = HistoryStore::open(path.clone(), Some(key), &policy).unwrap();
+    assert_eq!(codes(&reopened), ["333333", "222222", "111111"]);
+}
+
+#[test]
+fn store_rewrites_the_log_periodically_and_on_removal() {
+    let history = temp_history();
+    let path = history.path.clone();
+    let key = generate_key();
+    let policy = RetentionPolicy::default();
+
+    let mut store = HistoryStore::open(path.clone(), Some(key), &policy).unwrap();
+    for i in 0..20 {
+        store.append(fresh(&format!("{:06}", i)), &policy);
+    }
+    assert!(line_count(&path) < 20);
+    assert!(!path.with_extension("json.tmp").exists());
+
+    store.retain(|c| c.code != "000019", &policy);
+    assert_eq!(line_count(&path), 1);
+    let reopened = HistoryStore::open(path.clone(), Some(key), &policy).unwrap();
+    assert_eq!(reopened.entries().len(), 19);
+    assert_eq!(reopened.entries()[0].code, "000018");
+
+    store.clear().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "");
+}
+
+#[test]
+fn persisted_depth_is_separate_from_the_visible_list() {
+    let history = temp_history();
+    let path = history.path.clone();
+    let key = generate_key();
+    let policy = RetentionPolicy {
+        max_count: 30,
+        recent_count: 5,
+        ..RetentionPolicy::default()
+    };
+
+    let mut store = HistoryStore::open(path.clone(), Some(key), &policy).unwrap();
+    for i in 0..40 {
+        store.append(fresh(&format!("{:06}", i)), &policy);
+    }
+    assert_eq!(store.recent(policy.recent_count, true).len(), 5);
+    assert_eq!(store.entries().len(), 30);
+
+    let reopened = HistoryStore::open(path, Some(key), &policy).unwrap();
+    assert_eq!(reopened.entries().len(), 30);
+}
+
+#[test]
+fn torn_last_record_is_dropped() {
+    let history = temp_history();
+    let path = history.path.clone();
+    let key = generate_key();
+    let policy = RetentionPolicy::default();
+
+    let mut store = HistoryStore::open(path.clone(), Some(key), &policy).unwrap();
+    store.append(fresh("111111"), &policy);
+    store.append(fresh("222222"), &policy);
+    let content = fs::read_to_string(&path).unwrap();
+    fs::write(&path, &content[..content.len() - 20]).unwrap();
+
+    let reopened = HistoryStore::open(path.clone(), Some(key), &policy).unwrap();
+    assert_eq!(codes(&reopened), ["111111"]);
+    assert_eq!(line_count(&path), 1);
+}
+
+#[test]
+fn plaintext_history_is_encrypted_on_open() {
+    let history = temp_history();
+    let path = history.path.clone();
+    let key = generate_key();
+    let legacy = serde_json::to_string_pretty(&[fresh("482913")]).unwrap();
+    fs::write(&path, legacy).unwrap();
+
+    let store = HistoryStore::open(path.clone(), Some(key), &RetentionPolicy::default()).unwrap();
+    assert_eq!(codes(&store), ["482913"]);
+    let content = fs::read_to_string(&path).unwrap();
+    assert!(!content.contains("482913"));
+    assert_eq!(decrypt_history(content.trim(), &key).unwrap().len(), 1);
+}
+
+#[test]
+fn unreadable_history_is_moved_aside() {
+    let history = temp_history();
+    let path = history.path.clone();
+    let policy = RetentionPolicy::default();
+    let mut store = HistoryStore::open(path.clone(), Some(generate_key()), &policy).unwrap();
+    store.append(fresh("482913"), &policy);
+
+    let store = HistoryStore::open(path.clone(), Some(generate_key()), &policy).unwrap();
+    assert!(store.entries().is_empty());
+    assert!(!path.exists());
+
+    // A second unreadable file doesn't replace the first
+    let mut store = HistoryStore::open(path.clone(), Some(generate_key()), &policy).unwrap();
+    store.append(fresh("739204"), &policy);
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    HistoryStore::open(path.clone(), Some(generate_key()), &policy).unwrap();
+    let set_aside = fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            e.file_name()
+                .to_string_lossy()
+                .starts_with("code_history.json.unreadable-")
+        })
+        .count();
+    assert_eq!(set_aside, 2);
+}
+
+#[test]
+fn history_that_cant_be_read_is_left_alone_until_it_can() {
+    let history = temp_history();
+    let path = history.path.clone();
+    let key = generate_key();
+    let policy = RetentionPolicy::default();
+    let mut store = HistoryStore::open(path.clone(), Some(key), &policy).unwrap();
+    store.append(fresh("111111"), &policy);
+    let saved = fs::read_to_string(&path).unwrap();
+
+    // A directory in its place fails to read, as a keychain error would
+    fs::remove_file(&path).unwrap();
+    fs::create_dir(&path).unwrap();
+    let mut store = HistoryStore::open_or_lock(path.clone(), Some(key), &policy);
+    store.append(fresh("222222"), &policy);
+    assert!(store.compact(&policy).is_err());
+    assert!(path.is_dir());
+    assert_eq!(codes(&store), ["222222"]);
+
+    // Once it reads again, the codes from before are merged in
+    fs::remove_dir(&path).unwrap();
+    fs::write(&path, saved).unwrap();
+    store.append(fresh("333333"), &policy);
+    assert_eq!(codes(&store), ["333333", "222222", "111111"]);
+    let reopened = HistoryStore::open(path, Some(key), &policy).unwrap();
+    assert_eq!(codes(&reopened), ["333333", "222222", "111111"]);
+}
+
+#[test]
+fn emptied_history_reopens_without_a_key() {
+    let history = temp_history();
+    let path = history.path.clone();
+    let policy = RetentionPolicy::default();
+    let mut store = HistoryStore::open(path.clone(), Some(generate_key()), &policy).unwrap();
+    store.compact(&policy).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+    // No key is needed, so the file is neither rejected nor moved aside
+    let store = HistoryStore::open(path.clone(), None, &policy).unwrap();
+    assert!(store.entries().is_empty());
+    assert!(path.exists());
+}
+
+#[test]
+fn copied_codes_are_marked_used_and_persisted() {
+    let history = temp_history();
+    let path = history.path.clone();
+    let key = generate_key();
+    let policy = RetentionPolicy::default();
+
+    let mut store = HistoryStore::open(path.clone(), Some(key), &policy).unwrap();
+    store.append(fresh("111111"), &policy);
+    // The same code from another message stays unused
+    store.append(
+        CodeEntry {
+            message_id: "18c2f0a9b7d4e1f4".to_string(),
+            ..fresh("111111")
+        },
+        &policy,
+    );
+
+    assert!(store.mark_used("18c2f0a9b7d4e1f4", NOW, &policy));
+    assert!(!store.mark_used("18c2f0a9b7d4e1f4", NOW + 1, &policy));
+    assert!(!store.mark_used("totp:github", NOW, &policy));
+    assert_eq!(store.recent(10, true).len(), 2);
+    assert_eq!(store.recent(10, false)[0].message_id, "18c2f0a9b7d4e1f3");
+
+    let reopened = HistoryStore::open(path, Some(key), &policy).unwrap();
+    assert_eq!(reopened.entries()[0].used_at, Some(NOW));
+    assert_eq!(reopened.entries()[1].used_at, None);
+}
+
+#[test]
+fn links_are_never_marked_used() {
+    let history = temp_history();
+    let path = history.path.clone();
+    let policy = RetentionPolicy::default();
+    let mut store = HistoryStore::open(path, Some(generate_key()), &policy).unwrap();
+    store.append(
+        CodeEntry {
+            kind: EntryKind::Link,
+            ..fresh("https://github.com/login/device")
+        },
+        &policy,
+    );
+
+    assert!(!store.mark_used("18c2f0a9b7d4e1f3", NOW, &policy));
+}
+
+#[test]
+fn used_codes_are_dimmed_by_default() {
+    let prefs: PrivacyPreferences = serde_json::from_str(
+        r#"{"auto_copy_enabled": true, "provider_auto_copy": {"default": true}}"#,
+    )
+    .unwrap();
+    assert_eq!(prefs.used_codes, UsedCodeDisplay::Dim);
+
+    let entry: CodeEntry = serde_json::from_value(serde_json::json!({
+        "code": "482913",
+        "sender": "noreply@github.com",
+        "provider": "GitHub",
+        "timestamp": NOW,
+        "message_id": "18c2f0a9b7d4e1f3",
+    }))
+    .unwrap();
+    assert_eq!(entry.used_at, None);
+}
@@ -0,0 +1,196 @@
+// Integration tests for the IMAP mail source against a local stand-in server
+
+use otpbar::imap::{self, ImapAuthMethod, ImapClient, ImapConfig};
+use otpbar::mail_source::MailSource;
+use otpbar::otp;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+const OTP_MESSAGE: &str = "From: GitHub <noreply@github.com>\r\n\
+Subject: =?UTF-8?B?WW91ciBHaXRIdWIgbGF1bmNoIGNvZGU=?=\r\n\
+\r\n\
+Your verification code is 482913.\r\n";
+
+struct StandIn {
+    port: u16,
+    commands: Arc<Mutex<Vec<String>>>,
+}
+
+/// Minimal scripted IMAP server: accepts `LOGIN user secret` or an XOAUTH2
+/// token for `user`, serves UIDs 7 and 9 and pushes `EXISTS` during IDLE.
+async fn start_stand_in() -> StandIn {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let commands = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&commands);
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let log = Arc::clone(&log);
+            tokio::spawn(async move {
+                let (read, mut write) = stream.into_split();
+                let mut lines = BufReader::new(read).lines();
+                write.write_all(b"* OK IMAP4rev1 ready\r\n").await.unwrap();
+
+                while let Ok(Some(line)) = lines.next_line().await {
+                    log.lock().unwrap().push(line.clone());
+                    let (tag, command) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+                    let reply = if command == "LOGIN \"user\" \"secret\"" {
+                        format!("{} OK LOGIN completed\r\n", tag)
+                    } else if command.starts_with("LOGIN") {
+                        format!("{} NO [AUTHENTICATIONFAILED] Invalid credentials\r\n", tag)
+                    } else if let Some(token) = command.strip_prefix("AUTHENTICATE XOAUTH2 ") {
+                        let expected = base64_encode("user=user\x01auth=Bearer token\x01\x01");
+                        if token == expected {
+                            format!("{} OK AUTHENTICATE completed\r\n", tag)
+                        } else {
+                            write
+                                .write_all(b"+ eyJzdGF0dXMiOiI0MDAifQ==\r\n")
+                                .await
+                                .unwrap();
+                            let _ = lines.next_line().await;
+                            format!("{} NO [AUTHENTICATIONFAILED] Invalid token\r\n", tag)
+                        }
+                    } else if command.starts_with("EXAMINE") {
+                        format!(
+                            "* 9 EXISTS\r\n* OK [UIDVALIDITY 3857529045] UIDs valid\r\n{} OK [READ-ONLY] EXAMINE completed\r\n",
+                            tag
+                        )
+                    } else if command.starts_with("UID SEARCH UNSEEN SINCE") {
+                        format!("* SEARCH 7 9\r\n{} OK SEARCH completed\r\n", tag)
+                    } else if command.starts_with("UID FETCH 9") {
+                        format!(
+                            "* 2 FETCH (UID 9 BODY[] {{{}}}\r\n{})\r\n{} OK FETCH completed\r\n",
+                            OTP_MESSAGE.len(),
+                            OTP_MESSAGE,
+                            tag
+                        )
+                    } else if command.starts_with("UID FETCH") {
+                        format!("{} OK FETCH completed\r\n", tag)
+                    } else if command == "IDLE" {
+                        write.write_all(b"+ idling\r\n").await.unwrap();
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        write.write_all(b"* 10 EXISTS\r\n").await.unwrap();
+                        let _ = lines.next_line().await; // DONE
+                        format!("{} OK IDLE terminated\r\n", tag)
+                    } else if command == "LOGOUT" {
+                        format!("* BYE\r\n{} OK LOGOUT completed\r\n", tag)
+                    } else {
+                        format!("{} BAD Unknown command\r\n", tag)
+                    };
+                    if write.write_all(reply.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    StandIn { port, commands }
+}
+
+fn base64_encode(input: &str) -> String {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    STANDARD.encode(input)
+}
+
+fn config(port: u16, auth_method: ImapAuthMethod) -> ImapConfig {
+    ImapConfig {
+        host: "127.0.0.1".to_string(),
+        port,
+        username: "user".to_string(),
+        use_tls: false,
+        auth_method,
+        mailbox: "INBOX".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn imap_login_and_list_unseen_messages() {
+    let server = start_stand_in().await;
+    let mut client = ImapClient::new(config(server.port, ImapAuthMethod::Login), "secret".into());
+
+    client.connect().await.expect("Login should succeed");
+    assert!(client.is_authenticated());
+    assert_eq!(client.account(), Some("user".to_string()));
+
+    let ids = client.list_new_messages().await.unwrap();
+    // UIDs are only unique within one UIDVALIDITY
+    assert_eq!(ids, ["3857529045:9", "3857529045:7"]);
+
+    let commands = server.commands.lock().unwrap().clone();
+    assert!(commands.iter().any(|c| c.contains("EXAMINE \"INBOX\"")));
+    assert!(commands
+        .iter()
+        .any(|c| c.contains("UID SEARCH UNSEEN SINCE")));
+}
+
+#[tokio::test]
+async fn imap_fetch_produces_email_message() {
+    let server = start_stand_in().await;
+    let mut client = ImapClient::new(config(server.port, ImapAuthMethod::Login), "secret".into());
+    client.connect().await.unwrap();
+
+    let message = client.fetch_message_detail("3857529045:9").await.unwrap();
+    assert_eq!(message.id, "3857529045:9");
+    assert_eq!(message.from, "GitHub <noreply@github.com>");
+    assert_eq!(message.subject, "Your GitHub launch code");
+
+    let text = format!("{} {} {}", message.subject, message.snippet, message.body);
+    assert_eq!(otp::extract_otp(&text), Some("482913".to_string()));
+
+    let commands = server.commands.lock().unwrap().clone();
+    assert!(commands.iter().any(|c| c.contains("BODY.PEEK[]")));
+
+    // A UID listed under another UIDVALIDITY may be a different message now
+    assert!(client.fetch_message_detail("1:9").await.is_err());
+    assert!(client.fetch_message_detail("9").await.is_err());
+}
+
+#[tokio::test]
+async fn imap_rejects_bad_password() {
+    let server = start_stand_in().await;
+    let mut client = ImapClient::new(config(server.port, ImapAuthMethod::Login), "wrong".into());
+
+    let err = client.connect().await.unwrap_err();
+    assert!(err.contains("AUTHENTICATIONFAILED"), "{}", err);
+    assert!(!client.is_authenticated());
+}
+
+#[tokio::test]
+async fn imap_xoauth2_authentication() {
+    let server = start_stand_in().await;
+    let mut client = ImapClient::new(config(server.port, ImapAuthMethod::XOAuth2), "token".into());
+    client.connect().await.expect("XOAUTH2 should succeed");
+
+    let mut rejected = ImapClient::new(
+        config(server.port, ImapAuthMethod::XOAuth2),
+        "expired".into(),
+    );
+    assert!(rejected.connect().await.is_err());
+}
+
+#[tokio::test]
+async fn imap_idle_notifies_on_new_mail() {
+    let server = start_stand_in().await;
+    let mut client = ImapClient::new(config(server.port, ImapAuthMethod::Login), "secret".into());
+    client.connect().await.unwrap();
+
+    let notify = client.new_mail_notifier().expect("IMAP supports push");
+    tokio::time::timeout(Duration::from_secs(5), notify.notified())
+        .await
+        .expect("IDLE should report new mail");
+}
+
+#[test]
+fn parse_raw_message_unfolds_headers() {
+    let raw = b"From: Example\r\n <noreply@example.com>\r\nSubject: Sign in\r\n\r\nCode: 1234";
+    let message = imap::parse_raw_message("1", raw);
+    assert_eq!(message.from, "Example <noreply@example.com>");
+    assert_eq!(message.subject, "Sign in");
+    assert_eq!(message.body, "Code: 1234");
+}
@@ -0,0 +1,162 @@
+// Tests for sign-in and verification link detection
+
+use otpbar::magic_link::extract_magic_link;
+use otpbar::mime::{self, Link};
+
+const SLACK_HTML: &str = r#"<html><body>
+  <p>Click the button below to sign in to your workspace.</p>
+  <a href="https://slack.com/z-app-123-456/magic-login/AbC123dEf456GhI789jKl?x=1&amp;y=2"
+     style="background:#4A154B">Sign in to Slack</a>
+  <p>This link expires in 24 hours.</p>
+  <a href="https://slack.com/help">Help Center</a>
+  <a href="https://slack.com/unsubscribe?u=AbC123dEf456GhI789jKl">Unsubscribe</a>
+</body></html>"#;
+
+fn link(url: &str, text: &str) -> Link {
+    Link {
+        url: url.to_string(),
+        text: text.to_string(),
+    }
+}
+
+#[test]
+fn finds_sign_in_button_in_html() {
+    let links = mime::html_links(SLACK_HTML);
+    let found = extract_magic_link(
+        "Slack confirmation code",
+        &mime::html_to_text(SLACK_HTML),
+        &links,
+    )
+    .unwrap();
+    assert_eq!(
+        found.url,
+        "https://slack.com/z-app-123-456/magic-login/AbC123dEf456GhI789jKl?x=1&y=2"
+    );
+    assert!(found.confidence > 0.8, "{}", found.confidence);
+}
+
+#[test]
+fn finds_bare_url_under_its_call_to_action() {
+    let body = "Hi,\n\nClick the link below to log in to Notion:\n\n\
+https://www.notion.so/loginwithemail?token=8f14e45fceea167a5a36dedd4bea2543.\n\n\
+If you didn't request this, ignore this email.";
+    let found = extract_magic_link("Your login link", body, &[]).unwrap();
+    assert_eq!(
+        found.url,
+        "https://www.notion.so/loginwithemail?token=8f14e45fceea167a5a36dedd4bea2543"
+    );
+}
+
+#[test]
+fn verification_link_through_click_tracking() {
+    let links = [
+        link("https://example.com/", "Example"),
+        link(
+            "https://click.mailer.example.com/ls/click?upn=u001.9x8y7zAbC123dEf456GhI789",
+            "Verify email address",
+        ),
+    ];
+    let found = extract_magic_link("Welcome to Example", "", &links).unwrap();
+    assert_eq!(
+        found.url,
+        "https://click.mailer.example.com/ls/click?upn=u001.9x8y7zAbC123dEf456GhI789"
+    );
+}
+
+#[test]
+fn link_text_alone_is_not_enough() {
+    let digest = [
+        link("https://www.linkedin.com/", "Sign in"),
+        link("https://www.linkedin.com/feed/", "See all updates"),
+    ];
+    assert_eq!(extract_magic_link("Your weekly digest", "", &digest), None);
+
+    let shipping = "Your order has shipped.\n\
+You can log in to your account at https://shop.example.com/account to track it.";
+    assert_eq!(
+        extract_magic_link("Your order has shipped", shipping, &[]),
+        None
+    );
+
+    let tracked = [link(
+        "https://click.mailer.example.com/ls/click?upn=u001.9x8y7z",
+        "Verify email address",
+    )];
+    assert_eq!(extract_magic_link("Verify your email", "", &tracked), None);
+}
+
+#[test]
+fn footer_and_plain_links_are_ignored() {
+    let links = [
+        link("https://example.com/account/preferences", "Manage settings"),
+        link(
+            "https://example.com/unsubscribe?token=8f14e45fceea167a5a36dedd4bea2543",
+            "Unsubscribe",
+        ),
+        link("https://example.com/privacy", "Privacy policy"),
+        link("https://example.com/blog/launch", "Read the announcement"),
+    ];
+    assert_eq!(
+        extract_magic_link("Our spring launch", "Big news!", &links),
+        None
+    );
+}
+
+#[test]
+fn requires_https() {
+    let links = [link(
+        "http://example.com/login?token=8f14e45fceea167a5a36dedd4bea2543",
+        "Sign in",
+    )];
+    assert_eq!(extract_magic_link("Sign in", "", &links), None);
+
+    let links = [link("javascript:alert(1)", "Sign in")];
+    assert_eq!(extract_magic_link("Sign in", "", &links), None);
+}
+
+#[test]
+fn localized_call_to_action() {
+    let links = [link(
+        "https://example.de/a/8f14e45fceea167a5a36dedd4bea2543",
+        "Jetzt anmelden",
+    )];
+    assert!(extract_magic_link("Ihr Anmeldelink", "", &links).is_some());
+}
+
+#[test]
+fn html_links_decode_targets_and_skip_anchors() {
+    let html = r##"<!-- <a href="https://hidden.example.com">x</a> -->
+<a name="top"></a><a href="#top">Top</a>
+<A HREF='https://example.com/a?x=1&amp;y=2'><b>Log</b> <i>in</i></A>
+<a class="btn" href=https://example.com/b>Go</a>"##;
+    assert_eq!(
+        mime::html_links(html),
+        vec![
+            link("https://example.com/a?x=1&y=2", "Log in"),
+            link("https://example.com/b", "Go"),
+        ]
+    );
+}
+
+#[test]
+fn imap_message_keeps_html_links() {
+    let raw = b"Subject: Sign in\r\n\
+Content-Type: multipart/alternative; boundary=b1\r\n\r\n\
+--b1\r\n\
+Content-Type: text/plain\r\n\r\n\
+Sign in with the button in this email.\r\n\
+--b1\r\n\
+Content-Type: text/html\r\n\r\n\
+<a href=\"https://example.com/magic/8f14e45fceea167a5a36dedd4bea2543\">Sign in</a>\r\n\
+--b1--\r\n";
+
+    let message = otpbar::imap::parse_raw_message("9", raw);
+    assert_eq!(message.body, "Sign in with the button in this email.");
+    assert_eq!(
+        message.links,
+        vec![link(
+            "https://example.com/magic/8f14e45fceea167a5a36dedd4bea2543",
+            "Sign in"
+        )]
+    );
+}
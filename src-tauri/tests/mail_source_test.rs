@@ -0,0 +1,268 @@
+// Tests for the MailSource abstraction using an in-memory inbox
+
+use async_trait::async_trait;
+use otpbar::mail_source::{self, EmailMessage, MailSource, RATE_LIMIT_ERROR};
+use otpbar::seen_messages::SeenMessages;
+use otpbar::types::SenderAuth;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+struct FakeMailSource {
+    messages: Vec<EmailMessage>,
+    broken_ids: Vec<String>,
+    rate_limited: bool,
+    authenticated: AtomicBool,
+    fetched_ids: Mutex<Vec<String>>,
+    commits: Mutex<usize>,
+    account: Option<String>,
+}
+
+impl FakeMailSource {
+    fn with_messages(messages: Vec<EmailMessage>) -> Self {
+        Self {
+            messages,
+            broken_ids: Vec::new(),
+            rate_limited: false,
+            authenticated: AtomicBool::new(true),
+            fetched_ids: Mutex::new(Vec::new()),
+            commits: Mutex::new(0),
+            account: None,
+        }
+    }
+}
+
+#[async_trait]
+impl MailSource for FakeMailSource {
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+
+    fn account(&self) -> Option<String> {
+        self.account.clone()
+    }
+
+    fn is_authenticated(&self) -> bool {
+        self.authenticated.load(Ordering::Relaxed)
+    }
+
+    async fn try_restore_auth(&mut self) -> bool {
+        self.is_authenticated()
+    }
+
+    async fn list_new_messages(&self) -> Result<Vec<String>, String> {
+        if self.rate_limited {
+            return Err(RATE_LIMIT_ERROR.to_string());
+        }
+        Ok(self.messages.iter().map(|m| m.id.clone()).collect())
+    }
+
+    async fn fetch_message_detail(&self, msg_id: &str) -> Result<EmailMessage, String> {
+        self.fetched_ids.lock().unwrap().push(msg_id.to_string());
+        if self.broken_ids.iter().any(|id| id == msg_id) {
+            return Err("boom".to_string());
+        }
+        self.messages
+            .iter()
+            .find(|m| m.id == msg_id)
+            .cloned()
+            .ok_or_else(|| "not found".to_string())
+    }
+
+    fn commit_listing(&self) {
+        *self.commits.lock().unwrap() += 1;
+    }
+
+    async fn clear_auth(&self) -> Result<(), String> {
+        self.authenticated.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+async fn fetch(
+    source: &FakeMailSource,
+    seen: &mut SeenMessages,
+) -> Result<Vec<EmailMessage>, String> {
+    mail_source::fetch_new_messages(&[source], seen)
+        .await
+        .remove(0)
+}
+
+fn message(id: &str, subject: &str) -> EmailMessage {
+    EmailMessage {
+        id: id.to_string(),
+        from: "Example <noreply@example.com>".to_string(),
+        subject: subject.to_string(),
+        snippet: String::new(),
+        body: String::new(),
+        links: Vec::new(),
+        sent_at: None,
+        sender_auth: SenderAuth::Unknown,
+    }
+}
+
+#[tokio::test]
+async fn fetch_new_messages_returns_all_listed_messages() {
+    let source = FakeMailSource::with_messages(vec![
+        message("a", "Your code is 123456"),
+        message("b", "Hello"),
+    ]);
+
+    let fetched = fetch(&source, &mut SeenMessages::default()).await.unwrap();
+    let ids: Vec<_> = fetched.iter().map(|m| m.id.as_str()).collect();
+    assert_eq!(ids, vec!["a", "b"]);
+}
+
+#[tokio::test]
+async fn fetch_new_messages_skips_messages_that_fail_to_load() {
+    let mut source = FakeMailSource::with_messages(vec![
+        message("a", "Your code is 123456"),
+        message("b", "Your code is 654321"),
+    ]);
+    source.broken_ids.push("a".to_string());
+
+    let fetched = fetch(&source, &mut SeenMessages::default()).await.unwrap();
+    assert_eq!(fetched.len(), 1);
+    assert_eq!(fetched[0].id, "b");
+}
+
+#[tokio::test]
+async fn fetch_new_messages_propagates_rate_limit() {
+    let mut source = FakeMailSource::with_messages(vec![message("a", "Hi")]);
+    source.rate_limited = true;
+
+    let err = fetch(&source, &mut SeenMessages::default())
+        .await
+        .unwrap_err();
+    assert_eq!(err, RATE_LIMIT_ERROR);
+}
+
+#[tokio::test]
+async fn fetch_new_messages_skips_seen_messages() {
+    let source = FakeMailSource::with_messages(vec![
+        message("a", "Your code is 123456"),
+        message("b", "Your code is 654321"),
+    ]);
+    let mut seen = SeenMessages::default();
+
+    let first = fetch(&source, &mut seen).await.unwrap();
+    assert_eq!(first.len(), 2);
+    assert!(
+        seen.contains(&mail_source::seen_scope(&source), "a")
+            && seen.contains(&mail_source::seen_scope(&source), "b")
+    );
+
+    let second = fetch(&source, &mut seen).await.unwrap();
+    assert!(second.is_empty());
+    assert_eq!(source.fetched_ids.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn fetch_new_messages_retries_failed_messages() {
+    let mut source = FakeMailSource::with_messages(vec![message("a", "Your code is 123456")]);
+    source.broken_ids.push("a".to_string());
+    let mut seen = SeenMessages::default();
+
+    fetch(&source, &mut seen).await.unwrap();
+    assert!(!seen.contains(&mail_source::seen_scope(&source), "a"));
+
+    source.broken_ids.clear();
+    let fetched = fetch(&source, &mut seen).await.unwrap();
+    assert_eq!(fetched.len(), 1);
+    assert!(seen.contains(&mail_source::seen_scope(&source), "a"));
+}
+
+#[tokio::test]
+async fn listing_is_committed_only_when_every_message_loads() {
+    let mut source = FakeMailSource::with_messages(vec![
+        message("a", "Your code is 123456"),
+        message("b", "Your code is 654321"),
+    ]);
+    source.broken_ids.push("b".to_string());
+    let mut seen = SeenMessages::default();
+
+    fetch(&source, &mut seen).await.unwrap();
+    assert_eq!(*source.commits.lock().unwrap(), 0);
+
+    source.broken_ids.clear();
+    fetch(&source, &mut seen).await.unwrap();
+    assert_eq!(*source.commits.lock().unwrap(), 1);
+}
+
+#[tokio::test]
+async fn fetch_new_messages_preserves_listing_order() {
+    let ids: Vec<String> = (0..12).map(|i| format!("m{}", i)).collect();
+    let source = FakeMailSource::with_messages(ids.iter().map(|id| message(id, "Hi")).collect());
+
+    let fetched = fetch(&source, &mut SeenMessages::default()).await.unwrap();
+    let fetched_ids: Vec<String> = fetched.into_iter().map(|m| m.id).collect();
+    assert_eq!(fetched_ids, ids);
+}
+
+#[tokio::test]
+async fn fetch_new_messages_polls_every_source() {
+    let work = FakeMailSource::with_messages(vec![message("w1", "Your code is 111111")]);
+    let mut personal = FakeMailSource::with_messages(vec![message("p1", "Your code is 222222")]);
+    personal.rate_limited = true;
+
+    let results =
+        mail_source::fetch_new_messages(&[&work, &personal], &mut SeenMessages::default()).await;
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].as_ref().unwrap()[0].id, "w1");
+    assert_eq!(results[1].as_ref().unwrap_err(), RATE_LIMIT_ERROR);
+}
+
+#[tokio::test]
+async fn seen_messages_are_tracked_per_account() {
+    let mut work = FakeMailSource::with_messages(vec![message("a", "Your code is 111111")]);
+    work.account = Some("work@example.com".to_string());
+    let mut personal = FakeMailSource::with_messages(vec![message("a", "Your code is 222222")]);
+    personal.account = Some("me@example.com".to_string());
+    let mut seen = SeenMessages::default();
+
+    assert_eq!(fetch(&work, &mut seen).await.unwrap().len(), 1);
+    // The same ID in another account is another message
+    assert_eq!(fetch(&personal, &mut seen).await.unwrap().len(), 1);
+    assert!(fetch(&work, &mut seen).await.unwrap().is_empty());
+}
+
+#[test]
+fn seen_messages_are_keyed_per_source_and_hashed() {
+    let mut seen = SeenMessages::default();
+    seen.insert("imap", "42");
+
+    assert!(seen.contains("imap", "42"));
+    assert!(!seen.contains("gmail", "42"));
+}
+
+#[test]
+fn seen_messages_expire_after_ttl() {
+    let now = 1_700_000_000_000;
+    let mut seen = SeenMessages::default();
+    seen.insert_at("gmail", "old", now - 8 * 24 * 60 * 60 * 1000);
+    seen.insert_at("gmail", "recent", now - 60 * 60 * 1000);
+
+    seen.prune(now);
+    assert!(!seen.contains("gmail", "old"));
+    assert!(seen.contains("gmail", "recent"));
+}
+
+#[tokio::test]
+async fn default_oauth_methods_report_unsupported() {
+    let mut source: Box<dyn MailSource> = Box::new(FakeMailSource::with_messages(Vec::new()));
+
+    assert!(source
+        .get_auth_url("http://127.0.0.1/callback", "state")
+        .is_err());
+    assert!(source.exchange_code("code").await.is_err());
+
+    source.clear_auth().await.unwrap();
+    assert!(!source.is_authenticated());
+}
+
+#[test]
+fn hash_message_id_is_stable_and_opaque() {
+    let hash = mail_source::hash_message_id("18c2f0a1b2c3d4e5");
+    assert_eq!(hash, mail_source::hash_message_id("18c2f0a1b2c3d4e5"));
+    assert_eq!(hash.len(), 64);
+    assert!(!hash.contains("18c2f0a1b2c3d4e5"));
+}
@@ -0,0 +1,134 @@
+// Tests for MIME body decoding and HTML-to-text conversion
+
+use otpbar::{imap, mime, otp};
+
+const HTML_EMAIL: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+  <title>Verify 778899</title>
+  <style>.code { color: #123456; background: #654321; }</style>
+</head>
+<body>
+  <script>var tracking = "445566";</script>
+  <!-- build 998877 -->
+  <div class="code">Your verification code&nbsp;is</div>
+  <p><b>482913</b></p>
+  <p>Don&#39;t share it &amp; don&rsquo;t reply.</p>
+  <a href="https://click.example.com/track?id=112233&amp;u=556677">Manage preferences</a>
+</body>
+</html>"##;
+
+#[test]
+fn html_to_text_drops_markup_styles_and_scripts() {
+    let text = mime::html_to_text(HTML_EMAIL);
+    assert!(
+        text.contains("Your verification code is\n482913"),
+        "{}",
+        text
+    );
+    assert!(
+        text.contains("Don't share it & don\u{2019}t reply."),
+        "{}",
+        text
+    );
+    for hidden in ["#123456", "778899", "445566", "998877", "112233", "<b>"] {
+        assert!(!text.contains(hidden), "{} leaked into {}", hidden, text);
+    }
+}
+
+#[test]
+fn html_body_yields_the_real_code() {
+    assert_eq!(
+        otp::extract_otp(&mime::html_to_text(HTML_EMAIL)),
+        Some("482913".to_string())
+    );
+}
+
+#[test]
+fn decode_html_entities_handles_numeric_and_named() {
+    assert_eq!(
+        mime::decode_html_entities("&#x41;&#66;&lt;&gt;&quot;&unknown;&zwnj;"),
+        "AB<>\"&unknown;"
+    );
+}
+
+#[test]
+fn decode_quoted_printable_joins_soft_breaks() {
+    let decoded = mime::decode_quoted_printable(b"Your code is =\r\n48=3D2913 caf=C3=A9");
+    assert_eq!(
+        String::from_utf8(decoded).unwrap(),
+        "Your code is 48=2913 café"
+    );
+}
+
+#[test]
+fn decode_charset_uses_label() {
+    assert_eq!(mime::decode_charset(b"caf\xe9", Some("ISO-8859-1")), "café");
+    assert_eq!(
+        mime::decode_charset(b"\x94\x46\x8f\xd8", Some("Shift_JIS")),
+        "認証"
+    );
+    assert_eq!(mime::decode_charset("ok".as_bytes(), Some("bogus")), "ok");
+}
+
+#[test]
+fn multipart_alternative_prefers_plain_text() {
+    let raw = b"Content-Type: multipart/alternative; boundary=\"b1\"\r\n\r\n\
+preamble\r\n\
+--b1\r\n\
+Content-Type: text/html; charset=utf-8\r\n\r\n\
+<p style=\"color:#123456\">Code 111111</p>\r\n\
+--b1\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\r\n\
+Your code is 482913 =E2=80=94 thanks\r\n\
+--b1--\r\n";
+
+    let (head, body) = mime::split_message(raw);
+    let headers = mime::parse_headers(&String::from_utf8_lossy(head));
+    assert_eq!(
+        mime::message_body(&headers, body).into_text(),
+        "Your code is 482913 \u{2014} thanks"
+    );
+}
+
+#[test]
+fn html_only_message_is_converted() {
+    let raw = b"From: Example <noreply@example.com>\r\n\
+Subject: Sign in\r\n\
+Content-Type: multipart/mixed; boundary=outer\r\n\r\n\
+--outer\r\n\
+Content-Type: multipart/alternative; boundary=inner\r\n\r\n\
+--inner\r\n\
+Content-Type: text/html; charset=iso-8859-1\r\n\
+Content-Transfer-Encoding: base64\r\n\r\n\
+PHN0eWxlPnB7Y29sb3I6IzEyMzQ1Nn08L3N0eWxlPjxwPkNvZGU6IDQ4MjkxMyAtIGNhZuk8L3A+\r\n\
+--inner--\r\n\
+--outer\r\n\
+Content-Type: text/plain\r\n\
+Content-Disposition: attachment; filename=\"invoice.txt\"\r\n\r\n\
+Invoice 777777\r\n\
+--outer--\r\n";
+
+    let message = imap::parse_raw_message("7", raw);
+    assert_eq!(message.subject, "Sign in");
+    assert_eq!(message.body, "Code: 482913 - café");
+}
+
+#[test]
+fn single_part_base64_body() {
+    let raw = b"Content-Type: text/plain; charset=utf-8\r\n\
+Content-Transfer-Encoding: base64\r\n\r\n\
+WW91ciBjb2RlIGlzIDQ4MjkxMw==\r\n";
+
+    let message = imap::parse_raw_message("8", raw);
+    assert_eq!(message.body, "Your code is 482913");
+}
+
+#[test]
+fn q_encoded_subject_with_raw_non_ascii_text() {
+    let raw = "Subject: =?UTF-8?Q?=aé_=C3=A9t=E9?=\r\n\r\nYour code is 482913\r\n";
+
+    let message = imap::parse_raw_message("9", raw.as_bytes());
+    assert!(message.subject.starts_with("=aé ét"));
+}
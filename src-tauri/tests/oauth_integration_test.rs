@@ -1,27 +1,47 @@
 // Integration tests for OAuth flow
 
-use otpbar::oauth_server::OAuthServer;
+use otpbar::oauth_server::{self, OAuthServer};
 use std::time::Duration;
 use tokio::time::sleep;
 
 #[tokio::test]
 async fn oauth_server_starts_and_binds() {
-    let server = OAuthServer::start(8234)
+    let server = OAuthServer::start(0, "test-state")
         .await
         .expect("Server should start on available port");
+    assert!(server.redirect_uri().starts_with("http://127.0.0.1:"));
+    assert!(!server.redirect_uri().starts_with("http://127.0.0.1:0/"));
     drop(server); // Clean shutdown
 }
 
+#[tokio::test]
+async fn oauth_server_reports_bind_error() {
+    let taken = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = taken.local_addr().unwrap().port();
+
+    let err = match OAuthServer::start(port, "test-state").await {
+        Ok(_) => panic!("Binding a port in use should fail"),
+        Err(e) => e,
+    };
+    assert!(err.contains(&port.to_string()), "{}", err);
+}
+
 #[tokio::test]
 async fn oauth_server_handles_callback_success() {
-    let mut server = OAuthServer::start(8235).await.expect("Server should start");
+    let mut server = OAuthServer::start(0, "test-state")
+        .await
+        .expect("Server should start");
+    let url = format!(
+        "{}?code=test_auth_code_123&state=test-state",
+        server.redirect_uri()
+    );
 
     // Spawn a task to simulate OAuth callback
     tokio::spawn(async move {
         sleep(Duration::from_millis(100)).await;
         let client = reqwest::Client::new();
         let resp = client
-            .get("http://127.0.0.1:8235/callback?code=test_auth_code_123")
+            .get(url)
             .send()
             .await
             .expect("Request should succeed");
@@ -37,14 +57,17 @@ async fn oauth_server_handles_callback_success() {
 
 #[tokio::test]
 async fn oauth_server_handles_callback_error() {
-    let server = OAuthServer::start(8236).await.expect("Server should start");
+    let server = OAuthServer::start(0, "test-state")
+        .await
+        .expect("Server should start");
+    let url = format!("{}?error=access_denied", server.redirect_uri());
 
     // Spawn a task to simulate OAuth error callback
     tokio::spawn(async move {
         sleep(Duration::from_millis(100)).await;
         let client = reqwest::Client::new();
         let resp = client
-            .get("http://127.0.0.1:8236/callback?error=access_denied")
+            .get(url)
             .send()
             .await
             .expect("Request should succeed");
@@ -61,7 +84,9 @@ async fn oauth_server_handles_callback_error() {
 
 #[tokio::test]
 async fn oauth_server_timeout() {
-    let mut server = OAuthServer::start(8237).await.expect("Server should start");
+    let server = OAuthServer::start(0, "test-state")
+        .await
+        .expect("Server should start");
 
     // Wait for timeout (server has 300s timeout, but we'll use a shorter test)
     // For testing, we just verify the server doesn't immediately return
@@ -73,13 +98,15 @@ async fn oauth_server_timeout() {
 
 #[tokio::test]
 async fn oauth_server_not_found_path() {
-    let _server = OAuthServer::start(8238).await.expect("Server should start");
+    let server = OAuthServer::start(0, "test-state")
+        .await
+        .expect("Server should start");
 
     sleep(Duration::from_millis(100)).await;
 
     let client = reqwest::Client::new();
     let resp = client
-        .get("http://127.0.0.1:8238/unknown")
+        .get(server.redirect_uri().replace("/callback", "/unknown"))
         .send()
         .await
         .expect("Request should succeed");
@@ -89,14 +116,20 @@ async fn oauth_server_not_found_path() {
 
 #[tokio::test]
 async fn oauth_callback_parse_query_params() {
-    let _server = OAuthServer::start(8239).await.expect("Server should start");
+    let server = OAuthServer::start(0, "test-state")
+        .await
+        .expect("Server should start");
+    let url = format!(
+        "{}?code=abc123&state=test-state&scope=email",
+        server.redirect_uri()
+    );
 
     tokio::spawn(async move {
         sleep(Duration::from_millis(100)).await;
         let client = reqwest::Client::new();
         // Test with multiple query params
         let resp = client
-            .get("http://127.0.0.1:8239/callback?code=abc123&state=xyz789&scope=email")
+            .get(url)
             .send()
             .await
             .expect("Request should succeed");
@@ -106,3 +139,64 @@ async fn oauth_callback_parse_query_params() {
 
     sleep(Duration::from_millis(200)).await;
 }
+
+#[tokio::test]
+async fn oauth_callback_rejects_mismatched_state() {
+    let mut server = OAuthServer::start(0, "expected-state")
+        .await
+        .expect("Server should start");
+
+    sleep(Duration::from_millis(100)).await;
+
+    let client = reqwest::Client::new();
+    for query in ["code=injected&state=attacker-state", "code=injected"] {
+        let url = format!("{}?{}", server.redirect_uri(), query);
+        let body = client.get(url).send().await.unwrap().text().await.unwrap();
+        assert!(body.contains("Sign In Failed"));
+    }
+
+    let result = tokio::time::timeout(Duration::from_millis(200), server.wait_for_code()).await;
+    assert!(
+        result.is_err(),
+        "Code must not be released without a matching state"
+    );
+}
+
+#[tokio::test]
+async fn oauth_callback_decodes_code() {
+    let state = oauth_server::generate_state();
+    let mut server = OAuthServer::start(0, &state)
+        .await
+        .expect("Server should start");
+
+    let url = format!(
+        "{}?code=4%2F0AbCd&state={}",
+        server.redirect_uri(),
+        urlencoding::encode(&state)
+    );
+    tokio::spawn(async move {
+        sleep(Duration::from_millis(100)).await;
+        reqwest::get(url).await.expect("Request should succeed");
+    });
+
+    let code = server.wait_for_code().await.expect("Should receive code");
+    assert_eq!(code, "4/0AbCd");
+}
+
+#[test]
+fn pkce_challenge_is_s256_of_verifier() {
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use sha2::{Digest, Sha256};
+
+    let pkce = oauth_server::Pkce::generate();
+    assert_eq!(pkce.verifier.len(), 43);
+    assert_eq!(
+        pkce.challenge,
+        URL_SAFE_NO_PAD.encode(Sha256::digest(pkce.verifier.as_bytes()))
+    );
+    assert_ne!(
+        oauth_server::generate_state(),
+        oauth_server::generate_state()
+    );
+}
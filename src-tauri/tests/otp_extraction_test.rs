@@ -1,6 +1,15 @@
 // Unit tests for OTP extraction patterns
 
 use otpbar::otp;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct CorpusSample {
+    subject: String,
+    body: String,
+    code: Option<String>,
+    display: Option<String>,
+}
 
 #[test]
 fn test_extract_otp_code_prefix() {
@@ -59,10 +68,13 @@ fn test_extract_otp_enter_to_verify() {
 
 #[test]
 fn test_extract_otp_hyphenated() {
+    // Copied without the separator, shown as sent
     assert_eq!(
         otp::extract_otp("Your code is 123-456"),
-        Some("123-456".to_string())
+        Some("123456".to_string())
     );
+    let found = otp::extract_otp_from_message("", "Your code is 123-456").unwrap();
+    assert_eq!(found.display, "123-456");
 }
 
 #[test]
@@ -121,6 +133,342 @@ fn test_extract_otp_real_world_examples() {
     }
 }
 
+#[test]
+fn test_extract_otp_ignores_non_code_numbers() {
+    let cases = [
+        "Your order 482913 has shipped",
+        "Invoice #558201 is attached",
+        "Total: $129400 due today",
+        "Tracking number 772910 for your parcel",
+        "Call us at 555-123-4567",
+        "Meeting on 2024-01-15 at noon",
+        "© 2024 Example Inc. All rights reserved.",
+        "Ship to Springfield, 62704",
+    ];
+
+    for text in cases {
+        assert_eq!(otp::extract_otp(text), None, "Should not match: {}", text);
+    }
+}
+
+#[test]
+fn test_extract_otp_prefers_keyword_over_first_number() {
+    let cases = [
+        (
+            "Order 482913 update. Your verification code is 730215.",
+            "730215",
+        ),
+        ("Ref 991823: use 4411 as your login PIN", "4411"),
+        (
+            "Thanks for your purchase of $25.00 on 01/02/2024. Code: 668102",
+            "668102",
+        ),
+    ];
+
+    for (text, expected) in cases {
+        assert_eq!(
+            otp::extract_otp(text),
+            Some(expected.to_string()),
+            "Failed for: {}",
+            text
+        );
+    }
+}
+
+#[test]
+fn test_extract_otp_from_message_uses_subject() {
+    let found = otp::extract_otp_from_message(
+        "482913 is your sign-in code",
+        "Questions? Call 1-800-555-0199. Order 112233 ships tomorrow.",
+    )
+    .expect("Subject code should be found");
+    assert_eq!(found.code, "482913");
+    assert!(found.confidence > 0.9, "{}", found.confidence);
+}
+
+#[test]
+fn test_extract_otp_confidence_reflects_context() {
+    let strong = otp::extract_otp_from_message("", "Your verification code is 123456").unwrap();
+    let weak = otp::extract_otp_from_message("", "Use 123456 to complete").unwrap();
+    assert!(strong.confidence > weak.confidence);
+    assert!(weak.confidence > 0.0 && strong.confidence <= 1.0);
+}
+
+#[test]
+fn test_extract_otp_corpus() {
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/otp_corpus.json"
+    );
+    let samples: Vec<CorpusSample> =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+
+    for sample in samples {
+        let found = otp::extract_otp_from_message(&sample.subject, &sample.body);
+        assert_eq!(
+            found.as_ref().map(|m| m.code.clone()),
+            sample.code,
+            "Wrong code for: {}",
+            sample.subject
+        );
+        assert_eq!(
+            found.map(|m| m.display),
+            sample.display,
+            "Wrong display form for: {}",
+            sample.subject
+        );
+    }
+}
+
+#[test]
+fn test_explain_extraction_agrees_with_extract_otp() {
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/otp_corpus.json"
+    );
+    let samples: Vec<CorpusSample> =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+
+    for sample in samples {
+        let text = format!("{}\n{}", sample.subject, sample.body);
+        let selected: Vec<_> = otp::explain_extraction(&text)
+            .into_iter()
+            .filter(|c| c.selected)
+            .collect();
+        assert!(selected.len() <= 1, "{:?}", selected);
+        assert_eq!(
+            selected.first().map(|c| c.code.clone()),
+            otp::extract_otp(&text),
+            "Explanation disagrees for: {}",
+            sample.subject
+        );
+    }
+}
+
+#[test]
+fn test_explain_extraction_reports_reasons() {
+    let explained =
+        otp::explain_extraction("Order 5521 shipped on 12/03/2024. Your code is 482913. AB");
+
+    let order = explained.iter().find(|c| c.code == "5521").unwrap();
+    assert_eq!(order.rule, "numeric");
+    assert!(!order.selected);
+    assert!(
+        order.reasons.iter().any(|r| r.contains("\"order\"")),
+        "{:?}",
+        order.reasons
+    );
+    assert!(order
+        .rejection
+        .as_deref()
+        .is_some_and(|r| r.contains("below the threshold")));
+
+    let date = explained.iter().find(|c| c.code == "2024").unwrap();
+    assert_eq!(date.rejection.as_deref(), Some("part of a larger number"));
+
+    let code = explained.iter().find(|c| c.code == "482913").unwrap();
+    assert!(code.selected);
+    assert_eq!(code.rejection, None);
+    assert!(
+        code.reasons
+            .iter()
+            .any(|r| r.starts_with("keyword \"code\"") && r.ends_with("+4.0")),
+        "{:?}",
+        code.reasons
+    );
+}
+
+#[test]
+fn test_explain_extraction_rejects_plain_words_and_links() {
+    let explained = otp::explain_extraction(
+        "Code: HELLO at https://example.com/R2D2XY or mail A1B2C3@example.com",
+    );
+    let word = explained.iter().find(|c| c.display == "HELLO").unwrap();
+    assert_eq!(
+        word.rejection.as_deref(),
+        Some("needs both letters and digits")
+    );
+    for link in ["R2D2XY", "A1B2C3"] {
+        let candidate = explained.iter().find(|c| c.display == link).unwrap();
+        assert_eq!(
+            candidate.rejection.as_deref(),
+            Some("inside a link or email address")
+        );
+    }
+    assert!(explained.iter().all(|c| !c.selected));
+}
+
+#[test]
+fn test_extract_otp_alphanumeric_needs_letters_and_digits() {
+    assert_eq!(otp::extract_otp("Your verification code is HELLO"), None);
+    assert_eq!(
+        otp::extract_otp("Your verification code is X7K9P2"),
+        Some("X7K9P2".to_string())
+    );
+    assert_eq!(
+        otp::extract_otp("Your code: https://example.com/c/AB12CD"),
+        None
+    );
+}
+
+#[test]
+fn test_extract_otp_coupons_are_not_codes() {
+    // "code" alone doesn't vouch for a letter-and-digit token
+    assert_eq!(otp::extract_otp("Your code is X7K9P2"), None);
+    assert_eq!(
+        otp::extract_otp("Use code SAVE20 at checkout for 20% off"),
+        None
+    );
+    assert_eq!(otp::extract_otp("Enter code SUMMER24 at checkout"), None);
+    assert_eq!(
+        otp::extract_otp("Your verification code is 482913. Don't miss our sale!"),
+        Some("482913".to_string())
+    );
+}
+
+#[test]
+fn test_extract_otp_single_digits_after_a_space_are_not_glue() {
+    assert_eq!(
+        otp::extract_otp("Your code is 482913 5 minutes"),
+        Some("482913".to_string())
+    );
+    assert_eq!(
+        otp::extract_otp("Your code is 482913 2 of 2"),
+        Some("482913".to_string())
+    );
+    // Groups that run on are still one longer number
+    assert_eq!(otp::extract_otp("Card 4111 1111 1111 1111 charged"), None);
+}
+
+#[test]
+fn test_extract_otp_german() {
+    let cases = [
+        ("Ihr Bestätigungscode lautet 482913", "482913"),
+        (
+            "Ihr Sicherheitscode: 730215. Bestellung 112233 ist unterwegs.",
+            "730215",
+        ),
+        ("Verwenden Sie 5521 als Einmalcode", "5521"),
+    ];
+
+    for (text, expected) in cases {
+        assert_eq!(
+            otp::extract_otp(text),
+            Some(expected.to_string()),
+            "{}",
+            text
+        );
+    }
+}
+
+#[test]
+fn test_extract_otp_spanish() {
+    let cases = [
+        ("Tu código de verificación es 482913", "482913"),
+        ("Usa el codigo 7302 para iniciar sesión", "7302"),
+        (
+            "Pedido 998877 enviado. Tu clave de seguridad: 551203",
+            "551203",
+        ),
+    ];
+
+    for (text, expected) in cases {
+        assert_eq!(
+            otp::extract_otp(text),
+            Some(expected.to_string()),
+            "{}",
+            text
+        );
+    }
+}
+
+#[test]
+fn test_extract_otp_french() {
+    let cases = [
+        ("Votre code de vérification est 482913", "482913"),
+        ("Saisissez 7302 pour confirmer votre connexion", "7302"),
+        (
+            "Commande 998877 expédiée. Code de sécurité : 551203",
+            "551203",
+        ),
+    ];
+
+    for (text, expected) in cases {
+        assert_eq!(
+            otp::extract_otp(text),
+            Some(expected.to_string()),
+            "{}",
+            text
+        );
+    }
+}
+
+#[test]
+fn test_extract_otp_portuguese() {
+    let cases = [
+        ("Seu código de verificação é 482913", "482913"),
+        ("Use a senha 7302 para acessar sua conta", "7302"),
+        (
+            "Pedido 998877 enviado. Código de confirmação: 551203",
+            "551203",
+        ),
+    ];
+
+    for (text, expected) in cases {
+        assert_eq!(
+            otp::extract_otp(text),
+            Some(expected.to_string()),
+            "{}",
+            text
+        );
+    }
+}
+
+#[test]
+fn test_extract_otp_japanese() {
+    let cases = [
+        ("認証コード：１２３４５６", "123456"),
+        ("確認コードは 482913 です。10分間有効です。", "482913"),
+        (
+            "ご注文番号 998877。ワンタイムパスワード：７３０２１５",
+            "730215",
+        ),
+    ];
+
+    for (text, expected) in cases {
+        assert_eq!(
+            otp::extract_otp(text),
+            Some(expected.to_string()),
+            "{}",
+            text
+        );
+    }
+}
+
+#[test]
+fn test_extract_otp_chinese() {
+    let cases = [
+        ("您的验证码是123456，5分钟内有效。", "123456"),
+        ("【某某银行】驗證碼：４８２９１３，請勿告知他人。", "482913"),
+        ("订单998877已发货。您的动态密码为 730215", "730215"),
+    ];
+
+    for (text, expected) in cases {
+        assert_eq!(
+            otp::extract_otp(text),
+            Some(expected.to_string()),
+            "{}",
+            text
+        );
+    }
+}
+
+#[test]
+fn test_extract_otp_full_width_alphanumeric() {
+    let found = otp::extract_otp_from_message("", "認証コード：ＡＢ１２ＣＤ").unwrap();
+    assert_eq!(found.code, "AB12CD");
+}
+
 #[test]
 fn test_extract_provider_known_services() {
     let cases = [
@@ -135,11 +483,12 @@ fn test_extract_provider_known_services() {
         ("service@paypal.com", "PayPal"),
         ("support@stripe.com", "Stripe"),
         ("venmo@venmo.com", "Venmo"),
-        ("cashapp@squareup.com", "Cash App"),
+        ("cash@cash.app", "Cash App"),
+        ("receipts@squareup.com", "Square"),
         ("coinbase@coinbase.com", "Coinbase"),
         ("binance@binance.com", "Binance"),
         ("robinhood@robinhood.com", "Robinhood"),
-        ("security-noreply@aws.amazon.com", "Amazon"), // amazon pattern matches first
+        ("security-noreply@aws.amazon.com", "AWS"),
         ("notify@heroku.com", "Heroku"),
         ("cloudflare@cloudflare.com", "Cloudflare"),
         ("shopify@shopify.com", "Shopify"),
@@ -156,7 +505,6 @@ fn test_extract_provider_known_services() {
         ("adobe@adobe.com", "Adobe"),
         ("salesforce@salesforce.com", "Salesforce"),
         ("atlassian@atlassian.com", "Atlassian"),
-        // jira pattern matches after atlassian - atlassian wins due to order
         ("jira@atlassian.com", "Atlassian"),
         ("dropbox@dropbox.com", "Dropbox"),
         ("auth0@auth0.com", "Auth0"),
@@ -0,0 +1,227 @@
+// Integration tests for the Outlook / Microsoft Graph mail source against a local mock
+
+use http_body_util::{BodyExt, Full};
+use hyper::{server::conn::http1, service::service_fn, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use otpbar::mail_source::{MailSource, RATE_LIMIT_ERROR};
+use otpbar::otp;
+use otpbar::outlook::OutlookClient;
+use otpbar::types::SenderAuth;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+
+#[derive(Default)]
+struct MockGraph {
+    throttled: AtomicBool,
+    token_requests: Mutex<Vec<String>>,
+    requested_paths: Mutex<Vec<String>>,
+}
+
+fn json(status: StatusCode, body: &str) -> Response<Full<hyper::body::Bytes>> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Full::new(body.to_string().into()))
+        .unwrap()
+}
+
+async fn handle(
+    req: Request<hyper::body::Incoming>,
+    mock: Arc<MockGraph>,
+) -> Result<Response<Full<hyper::body::Bytes>>, hyper::Error> {
+    let path = req.uri().path().to_string();
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|p| p.to_string())
+        .unwrap_or_default();
+    mock.requested_paths
+        .lock()
+        .unwrap()
+        .push(path_and_query.clone());
+
+    if path == "/common/oauth2/v2.0/token" {
+        let body = req.into_body().collect().await?.to_bytes();
+        mock.token_requests
+            .lock()
+            .unwrap()
+            .push(String::from_utf8_lossy(&body).to_string());
+        return Ok(json(
+            StatusCode::OK,
+            r#"{"access_token":"mock-access","refresh_token":"mock-refresh","expires_in":3600}"#,
+        ));
+    }
+
+    let authorized = req
+        .headers()
+        .get("authorization")
+        .map(|v| v == "Bearer mock-access")
+        .unwrap_or(false);
+    if !authorized {
+        return Ok(json(
+            StatusCode::UNAUTHORIZED,
+            r#"{"error":"unauthorized"}"#,
+        ));
+    }
+
+    if mock.throttled.load(Ordering::SeqCst) {
+        return Ok(json(
+            StatusCode::TOO_MANY_REQUESTS,
+            r#"{"error":"throttled"}"#,
+        ));
+    }
+
+    let response = match path.as_str() {
+        "/v1.0/me" => json(
+            StatusCode::OK,
+            r#"{"mail":"someone@contoso.com","userPrincipalName":"someone@contoso.com"}"#,
+        ),
+        "/v1.0/me/messages" => json(
+            StatusCode::OK,
+            r#"{"value":[{"id":"AAMkADQ1"},{"id":"AAMkADQ2"}]}"#,
+        ),
+        "/v1.0/me/messages/AAMkADQ1" => json(
+            StatusCode::OK,
+            r#"{
+                "subject": "Your Microsoft account security code",
+                "bodyPreview": "Security code: 739204",
+                "body": {"contentType": "text", "content": "Please use the following security code: 739204"},
+                "from": {"emailAddress": {"name": "Microsoft account team", "address": "account-security-noreply@accountprotection.microsoft.com"}},
+                "receivedDateTime": "2025-10-14T07:30:00Z",
+                "internetMessageHeaders": [
+                    {"name": "Authentication-Results", "value": "spf=pass (sender IP is 40.92.0.1) smtp.mailfrom=accountprotection.microsoft.com; dkim=pass (signature was verified) header.d=accountprotection.microsoft.com; dmarc=pass action=none header.from=accountprotection.microsoft.com;compauth=pass reason=100"}
+                ]
+            }"#,
+        ),
+        _ => json(StatusCode::NOT_FOUND, r#"{"error":"not found"}"#),
+    };
+    Ok(response)
+}
+
+async fn start_mock() -> (String, Arc<MockGraph>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let mock = Arc::new(MockGraph::default());
+    let shared = Arc::clone(&mock);
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mock = Arc::clone(&shared);
+            tokio::spawn(async move {
+                let service = service_fn(move |req| handle(req, Arc::clone(&mock)));
+                let _ = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+
+    (base, mock)
+}
+
+async fn signed_in_client(base: &str) -> OutlookClient {
+    let mut client =
+        OutlookClient::with_endpoints("test-client", &format!("{}/v1.0", base), base, "common");
+    client
+        .get_auth_url("http://127.0.0.1:53682/callback", "test-state")
+        .unwrap();
+    client
+        .exchange_code("auth-code")
+        .await
+        .expect("Exchange should succeed");
+    client
+}
+
+#[tokio::test]
+async fn outlook_auth_url_uses_pkce() {
+    let client = OutlookClient::with_endpoints(
+        "test-client",
+        "http://127.0.0.1:1/v1.0",
+        "http://127.0.0.1:1",
+        "common",
+    );
+    let url = client
+        .get_auth_url("http://127.0.0.1:53682/callback", "test-state")
+        .unwrap();
+
+    assert!(url.starts_with("http://127.0.0.1:1/common/oauth2/v2.0/authorize?"));
+    assert!(url.contains("client_id=test-client"));
+    assert!(url.contains("state=test-state"));
+    assert!(url.contains("redirect_uri=http%3A%2F%2F127.0.0.1%3A53682%2Fcallback"));
+    assert!(url.contains("code_challenge_method=S256"));
+    assert!(url.contains("code_challenge="));
+    assert!(url.contains("Mail.Read"));
+}
+
+#[tokio::test]
+async fn outlook_exchange_sends_code_verifier() {
+    let (base, mock) = start_mock().await;
+    let client = signed_in_client(&base).await;
+    assert!(client.is_authenticated());
+    assert_eq!(client.account(), Some("someone@contoso.com".to_string()));
+
+    let requests = mock.token_requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].contains("grant_type=authorization_code"));
+    assert!(requests[0].contains("code=auth-code"));
+    assert!(requests[0].contains("code_verifier="));
+    assert!(!requests[0].contains("client_secret"));
+}
+
+#[tokio::test]
+async fn outlook_exchange_requires_started_sign_in() {
+    let (base, _mock) = start_mock().await;
+    let mut client =
+        OutlookClient::with_endpoints("test-client", &format!("{}/v1.0", base), &base, "common");
+
+    assert!(client.exchange_code("auth-code").await.is_err());
+    assert!(!client.is_authenticated());
+}
+
+#[tokio::test]
+async fn outlook_lists_unread_messages() {
+    let (base, mock) = start_mock().await;
+    let client = signed_in_client(&base).await;
+
+    let ids = client.list_new_messages().await.unwrap();
+    assert_eq!(ids, vec!["AAMkADQ1".to_string(), "AAMkADQ2".to_string()]);
+
+    let paths = mock.requested_paths.lock().unwrap().clone();
+    let list = paths
+        .iter()
+        .find(|p| p.starts_with("/v1.0/me/messages?"))
+        .expect("List request should be made");
+    assert!(list.contains("isRead%20eq%20false"));
+}
+
+#[tokio::test]
+async fn outlook_maps_message_to_email_message() {
+    let (base, _mock) = start_mock().await;
+    let client = signed_in_client(&base).await;
+
+    let message = client.fetch_message_detail("AAMkADQ1").await.unwrap();
+    assert_eq!(
+        message.from,
+        "Microsoft account team <account-security-noreply@accountprotection.microsoft.com>"
+    );
+    assert_eq!(message.subject, "Your Microsoft account security code");
+    assert_eq!(message.snippet, "Security code: 739204");
+
+    let text = format!("{} {} {}", message.subject, message.snippet, message.body);
+    assert_eq!(otp::extract_otp(&text), Some("739204".to_string()));
+    assert_eq!(otp::extract_provider(&message.from), "Microsoft");
+    assert_eq!(message.sent_at, Some(1_760_427_000_000));
+    assert_eq!(message.sender_auth, SenderAuth::Verified);
+}
+
+#[tokio::test]
+async fn outlook_reports_throttling_as_rate_limit() {
+    let (base, mock) = start_mock().await;
+    let client = signed_in_client(&base).await;
+    mock.throttled.store(true, Ordering::SeqCst);
+
+    let err = client.list_new_messages().await.unwrap_err();
+    assert_eq!(err, RATE_LIMIT_ERROR);
+}
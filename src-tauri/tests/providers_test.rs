@@ -0,0 +1,146 @@
+// Tests for the provider catalog
+
+use otpbar::otp;
+use otpbar::providers::{registrable_domain, Provider, ProviderCatalog};
+
+fn provider(name: &str, domains: &[&str], aliases: &[&str]) -> Provider {
+    Provider {
+        name: name.to_string(),
+        domains: domains.iter().map(|d| d.to_string()).collect(),
+        aliases: aliases.iter().map(|a| a.to_string()).collect(),
+        icon: None,
+        color: None,
+    }
+}
+
+fn name_for(catalog: &ProviderCatalog, sender: &str) -> Option<String> {
+    catalog.provider_for(sender).map(|p| p.name.clone())
+}
+
+#[test]
+fn lookalike_domains_dont_match() {
+    // "meta" and "square" used to match as substrings
+    let catalog = ProviderCatalog::built_in();
+    assert_eq!(name_for(&catalog, "noreply@metabase.com"), None);
+    assert_eq!(name_for(&catalog, "hello@squarespace.com"), None);
+    assert_eq!(name_for(&catalog, "alerts@notgithub.com"), None);
+    assert_eq!(
+        name_for(&catalog, "security@meta.com").as_deref(),
+        Some("Meta")
+    );
+}
+
+#[test]
+fn most_specific_domain_wins() {
+    let catalog = ProviderCatalog::built_in();
+    assert_eq!(
+        name_for(&catalog, "no-reply@signin.aws.amazon.com").as_deref(),
+        Some("AWS")
+    );
+    assert_eq!(
+        name_for(&catalog, "shipment@amazon.co.uk").as_deref(),
+        Some("Amazon")
+    );
+}
+
+#[test]
+fn display_names_never_label_a_sender() {
+    let catalog = ProviderCatalog::built_in();
+    assert_eq!(
+        name_for(&catalog, "GitHub <noreply@mailer.example-esp.com>"),
+        None
+    );
+    assert_eq!(
+        catalog
+            .claimed_provider("\"Uber Eats\" <receipts@relay.example.net>")
+            .map(|p| p.name.as_str()),
+        Some("Uber")
+    );
+    assert!(catalog
+        .claimed_provider("Metabase <noreply@mailer.example-esp.com>")
+        .is_none());
+    // The bare local part isn't a display name
+    assert!(catalog.claimed_provider("github@example.com").is_none());
+}
+
+#[test]
+fn brand_display_names_on_lookalike_domains_are_flagged() {
+    let catalog = ProviderCatalog::built_in();
+    let sender = "\"PayPal\" <security@paypal-security.xyz>";
+    assert_eq!(name_for(&catalog, sender), None);
+    assert!(catalog.is_lookalike(sender));
+    // Labelled by its own domain, not the name it signs with
+    assert_eq!(otp::extract_provider(sender), "Paypal-security");
+
+    assert!(catalog.is_lookalike("PayPal Security Team <alerts@pp-verify.com>"));
+    assert!(!catalog.is_lookalike("PayPal <service@paypal.com>"));
+    assert!(!catalog.is_lookalike("Acme Support <no-reply@acme-mail.com>"));
+}
+
+#[test]
+fn ordinary_names_containing_a_brand_are_not_lookalikes() {
+    let catalog = ProviderCatalog::built_in();
+    for sender in [
+        "Chase Smith <chase@startup.io>",
+        "Square Enix <info@account.square-enix.com>",
+        "Google Workspace Team via Acme <it@acme.com>",
+        "Meta <meta@example.org>",
+    ] {
+        assert!(!catalog.is_lookalike(sender), "{}", sender);
+    }
+}
+
+#[test]
+fn registrable_domains_follow_the_suffix_list() {
+    assert_eq!(
+        registrable_domain("mail.example.co.uk").as_deref(),
+        Some("example.co.uk")
+    );
+    assert_eq!(
+        registrable_domain("Accounts.Example.COM.").as_deref(),
+        Some("example.com")
+    );
+    assert_eq!(registrable_domain("co.uk"), None);
+    assert_eq!(otp::extract_provider("<no-reply@mail.acme.co.uk>"), "Acme");
+}
+
+#[test]
+fn overrides_replace_and_extend_the_catalog() {
+    let catalog = ProviderCatalog::built_in().with_overrides(vec![
+        provider("github", &["github.com", "githubapp.com"], &[]),
+        provider("Acme Bank", &[".Acme-Bank.com"], &["acme"]),
+    ]);
+
+    assert_eq!(
+        name_for(&catalog, "x@githubapp.com").as_deref(),
+        Some("github")
+    );
+    assert_eq!(
+        catalog
+            .providers()
+            .iter()
+            .filter(|p| p.name.eq_ignore_ascii_case("github"))
+            .count(),
+        1
+    );
+    assert_eq!(
+        name_for(&catalog, "alerts@secure.acme-bank.com").as_deref(),
+        Some("Acme Bank")
+    );
+    assert_eq!(
+        name_for(&catalog, "service@paypal.com").as_deref(),
+        Some("PayPal")
+    );
+}
+
+#[test]
+fn built_in_catalog_entries_are_usable() {
+    for p in ProviderCatalog::built_in().providers() {
+        assert!(!p.domains.is_empty(), "{} has no domains", p.name);
+        assert!(
+            p.color.as_deref().is_none_or(|c| c.starts_with('#')),
+            "{}",
+            p.name
+        );
+    }
+}
@@ -0,0 +1,117 @@
+// Tests for user-defined extraction rules
+
+use otpbar::rules::{self, ExtractionRule, RulePattern};
+
+fn rule(sender: &str, pattern: RulePattern) -> ExtractionRule {
+    ExtractionRule {
+        id: "test".to_string(),
+        sender: sender.to_string(),
+        pattern,
+    }
+}
+
+fn regex(pattern: &str) -> RulePattern {
+    RulePattern::Regex {
+        pattern: pattern.to_string(),
+    }
+}
+
+fn after(phrase: &str) -> RulePattern {
+    RulePattern::AfterPhrase {
+        phrase: phrase.to_string(),
+    }
+}
+
+#[test]
+fn sender_matches_addresses_and_domains() {
+    let from = "Acme Bank <alerts@secure.acme-bank.com>";
+    assert!(rules::sender_matches("acme-bank.com", from));
+    assert!(rules::sender_matches("secure.acme-bank.com", from));
+    assert!(rules::sender_matches("ALERTS@secure.acme-bank.com", from));
+    assert!(rules::sender_matches(
+        "acme-bank.com",
+        "alerts@acme-bank.com"
+    ));
+
+    assert!(!rules::sender_matches("bank.com", from));
+    assert!(!rules::sender_matches("other@secure.acme-bank.com", from));
+    assert!(!rules::sender_matches("", from));
+}
+
+#[test]
+fn regex_rule_wins_over_built_in_extractor() {
+    // The built-in extractor would pick the number next to "code"
+    let body = "Reference code 4455 66. Token: zq-77-ab";
+    let rules = [rule("acme.com", regex(r"Token: (?P<code>[a-z0-9-]+)"))];
+
+    let found = rules::apply_rules(&rules, "noreply@acme.com", "Sign in", body).unwrap();
+    assert_eq!(found.code, "zq-77-ab");
+    assert_eq!(found.confidence, 1.0);
+
+    assert!(rules::apply_rules(&rules, "noreply@other.com", "Sign in", body).is_none());
+}
+
+#[test]
+fn phrase_rule_takes_the_next_word() {
+    let rules = [rule("acme.com", after("Your PIN is"))];
+    let found = rules::apply_rules(
+        &rules,
+        "Acme <pin@acme.com>",
+        "Hello",
+        "Hi there,\nyour pin is: 9K2-LM4.\nThanks",
+    )
+    .unwrap();
+    assert_eq!(found.code, "9K2-LM4");
+}
+
+#[test]
+fn first_matching_rule_is_used() {
+    let rules = [
+        rule("acme.com", after("Passcode")),
+        rule("acme.com", regex(r"(?P<code>\d{6})")),
+    ];
+    let found = rules::apply_rules(&rules, "a@acme.com", "", "Use 123456 to continue").unwrap();
+    assert_eq!(found.code, "123456");
+}
+
+#[test]
+fn test_rule_checks_patterns() {
+    assert_eq!(
+        rules::test_rule(&regex(r"ID (?P<code>\w+)"), "Login ID ab12"),
+        Ok(Some("ab12".to_string()))
+    );
+    assert_eq!(
+        rules::test_rule(&regex(r"ID (?P<code>\w+)"), "nothing here"),
+        Ok(None)
+    );
+
+    let err = rules::test_rule(&regex(r"\d{6}"), "123456").unwrap_err();
+    assert!(err.contains("named group"), "{}", err);
+    assert!(rules::test_rule(&regex(r"(?P<code>[0-9"), "1").is_err());
+    assert!(rules::test_rule(&after("  "), "1").is_err());
+}
+
+#[test]
+fn phrase_is_matched_literally() {
+    assert_eq!(
+        rules::test_rule(&after("code (SMS):"), "Your code (SMS): 4821"),
+        Ok(Some("4821".to_string()))
+    );
+}
+
+#[test]
+fn rules_round_trip_as_tagged_json() {
+    let stored = rule("acme.com", after("PIN"));
+    let json = serde_json::to_value(&stored).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "id": "test",
+            "sender": "acme.com",
+            "type": "after_phrase",
+            "phrase": "PIN"
+        })
+    );
+    let parsed: ExtractionRule = serde_json::from_value(json).unwrap();
+    assert_eq!(parsed, stored);
+}
@@ -0,0 +1,145 @@
+// Tests for SPF/DKIM/DMARC sender verification
+
+use otpbar::sender_auth::{parse_authentication_results, verify_sender};
+use otpbar::types::SenderAuth;
+
+const PAYPAL_FROM: &str = "PayPal <service@paypal.com>";
+
+const GMAIL_PASS: &str = "mx.google.com;\r\n       dkim=pass header.i=@paypal.com header.s=pp-dkim1 header.b=abc123;\r\n       spf=pass (google.com: domain of service@paypal.com designates 173.0.84.226 as permitted sender) smtp.mailfrom=service@paypal.com;\r\n       dmarc=pass (p=REJECT sp=REJECT dis=NONE) header.from=paypal.com";
+
+fn verify(headers: &[(&str, &str)], from: &str) -> SenderAuth {
+    verify_sender(headers.iter().copied(), from)
+}
+
+#[test]
+fn parses_clauses_and_skips_comments() {
+    let results = parse_authentication_results(GMAIL_PASS);
+    let methods: Vec<(&str, &str)> = results
+        .iter()
+        .map(|r| (r.method.as_str(), r.result.as_str()))
+        .collect();
+    assert_eq!(
+        methods,
+        [("dkim", "pass"), ("spf", "pass"), ("dmarc", "pass")]
+    );
+    assert!(results[1].properties.contains(&(
+        "smtp.mailfrom".to_string(),
+        "service@paypal.com".to_string()
+    )));
+}
+
+#[test]
+fn dmarc_pass_is_verified() {
+    assert_eq!(
+        verify(&[("Authentication-Results", GMAIL_PASS)], PAYPAL_FROM),
+        SenderAuth::Verified
+    );
+}
+
+#[test]
+fn dmarc_fail_is_unverified() {
+    let header = "mx.google.com; dkim=none; spf=softfail smtp.mailfrom=x@paypal-security.xyz; dmarc=fail (p=REJECT) header.from=paypal.com";
+    assert_eq!(
+        verify(&[("Authentication-Results", header)], PAYPAL_FROM),
+        SenderAuth::Unverified
+    );
+}
+
+#[test]
+fn passing_checks_for_another_domain_are_unverified() {
+    // A phishing server can pass SPF and DKIM for its own domain
+    let header = "mx.example.net; spf=pass smtp.mailfrom=bounce@paypal-security.xyz; dkim=pass header.d=paypal-security.xyz";
+    assert_eq!(
+        verify(&[("Authentication-Results", header)], PAYPAL_FROM),
+        SenderAuth::Unverified
+    );
+}
+
+#[test]
+fn aligned_dkim_without_dmarc_is_verified() {
+    let header = "mx.example.net; dkim=pass header.d=mail.example.com; dmarc=bestguesspass";
+    assert_eq!(
+        verify(
+            &[("authentication-results", header)],
+            "Example <noreply@example.com>"
+        ),
+        SenderAuth::Verified
+    );
+}
+
+#[test]
+fn only_the_topmost_header_counts() {
+    // The lower header was written by the sender and isn't trusted
+    let headers = [
+        (
+            "Authentication-Results",
+            "mx.google.com; spf=fail smtp.mailfrom=paypal.com; dmarc=fail header.from=paypal.com",
+        ),
+        ("Authentication-Results", GMAIL_PASS),
+    ];
+    assert_eq!(verify(&headers, PAYPAL_FROM), SenderAuth::Unverified);
+}
+
+#[test]
+fn arc_results_alone_are_not_trusted() {
+    // Without a validated ARC chain the sender could have written this
+    let arc = format!("i=1; {}", GMAIL_PASS);
+    assert_eq!(
+        verify(&[("ARC-Authentication-Results", &arc)], PAYPAL_FROM),
+        SenderAuth::Unknown
+    );
+}
+
+#[test]
+fn dmarc_pass_must_be_for_the_from_domain() {
+    let header = "mx.google.com; dmarc=pass header.from=paypal-security.xyz";
+    assert_eq!(
+        verify(&[("Authentication-Results", header)], PAYPAL_FROM),
+        SenderAuth::Unverified
+    );
+}
+
+#[test]
+fn alignment_uses_the_organizational_domain() {
+    let from = "Example <noreply@example.co.uk>";
+    let aligned = "mx.example.net; spf=pass smtp.mailfrom=bounce@mail.example.co.uk";
+    assert_eq!(
+        verify(&[("Authentication-Results", aligned)], from),
+        SenderAuth::Verified
+    );
+
+    // Sharing a public suffix isn't alignment
+    let other = "mx.example.net; dkim=pass header.d=co.uk";
+    assert_eq!(
+        verify(&[("Authentication-Results", other)], from),
+        SenderAuth::Unverified
+    );
+}
+
+#[test]
+fn missing_results_are_unknown() {
+    assert_eq!(
+        verify(&[("Subject", "Your code")], PAYPAL_FROM),
+        SenderAuth::Unknown
+    );
+    assert_eq!(
+        verify(
+            &[("Authentication-Results", "mx.example.net; none")],
+            PAYPAL_FROM
+        ),
+        SenderAuth::Unknown
+    );
+}
+
+#[test]
+fn imap_messages_are_checked() {
+    // Folded continuation lines start with a tab
+    let raw = b"Authentication-Results: mx.fastmail.com;\r\n\
+\tdkim=pass header.d=github.com;\r\n\
+\tdmarc=pass header.from=github.com\r\n\
+From: GitHub <noreply@github.com>\r\n\
+Subject: [GitHub] Please verify your device\r\n\r\n\
+Verification code: 482913\r\n";
+    let message = otpbar::imap::parse_raw_message("1", raw);
+    assert_eq!(message.sender_auth, SenderAuth::Verified);
+}
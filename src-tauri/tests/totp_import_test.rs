@@ -0,0 +1,189 @@
+// Golden-file tests for importing authenticator exports
+
+use otpbar::totp::{self, ImportedAccount, OtpKind};
+use otpbar::totp_import::{self, ParsedExport};
+use serde::Deserialize;
+use std::path::PathBuf;
+
+const PASSWORD: &str = "correct horse";
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct ExpectedAccount {
+    kind: String,
+    issuer: String,
+    label: String,
+    algorithm: String,
+    digits: u32,
+    period: u64,
+    counter: u64,
+    secret: String,
+}
+
+impl From<&ImportedAccount> for ExpectedAccount {
+    fn from(imported: &ImportedAccount) -> Self {
+        let account = &imported.account;
+        ExpectedAccount {
+            kind: match account.kind {
+                OtpKind::Totp => "totp".to_string(),
+                OtpKind::Hotp => "hotp".to_string(),
+            },
+            issuer: account.issuer.clone(),
+            label: account.label.clone(),
+            algorithm: serde_json::to_value(account.algorithm)
+                .unwrap()
+                .as_str()
+                .unwrap()
+                .to_string(),
+            digits: account.digits,
+            period: account.period,
+            counter: account.counter,
+            secret: totp::encode_secret(&imported.secret),
+        }
+    }
+}
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path: PathBuf = [
+        env!("CARGO_MANIFEST_DIR"),
+        "tests",
+        "fixtures",
+        "totp_import",
+        name,
+    ]
+    .iter()
+    .collect();
+    std::fs::read(&path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e))
+}
+
+fn assert_matches_golden(imported: &ParsedExport) {
+    let expected: Vec<ExpectedAccount> = serde_json::from_slice(&fixture("expected.json")).unwrap();
+    let actual: Vec<ExpectedAccount> = imported
+        .accounts
+        .iter()
+        .map(ExpectedAccount::from)
+        .collect();
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn imports_google_authenticator_migration_uri() {
+    let uri = String::from_utf8(fixture("google_migration.txt")).unwrap();
+    assert_matches_golden(&totp_import::parse_migration_uri(&uri).unwrap());
+}
+
+#[test]
+fn imports_plain_aegis_export() {
+    let content = String::from_utf8(fixture("aegis_plain.json")).unwrap();
+    let imported = totp_import::parse_aegis_export(&content, None).unwrap();
+    assert_matches_golden(&imported);
+    // The Steam entry in the fixture is skipped
+    assert_eq!(imported.skipped, ["Steam: Unsupported type steam"]);
+}
+
+#[test]
+fn imports_encrypted_aegis_export() {
+    let content = String::from_utf8(fixture("aegis_encrypted.json")).unwrap();
+    assert_matches_golden(&totp_import::parse_aegis_export(&content, Some(PASSWORD)).unwrap());
+
+    assert!(totp_import::parse_aegis_export(&content, None).is_err());
+    let err = totp_import::parse_aegis_export(&content, Some("wrong"))
+        .err()
+        .unwrap();
+    assert!(err.contains("Incorrect password"), "{}", err);
+}
+
+#[test]
+fn imports_plain_andotp_backup() {
+    assert_matches_golden(
+        &totp_import::parse_andotp_export(&fixture("andotp_plain.json"), None).unwrap(),
+    );
+}
+
+#[test]
+fn imports_encrypted_andotp_backup() {
+    let data = fixture("andotp_encrypted.json.aes");
+    assert_matches_golden(&totp_import::parse_andotp_export(&data, Some(PASSWORD)).unwrap());
+
+    assert!(totp_import::parse_andotp_export(&data, None).is_err());
+    let err = totp_import::parse_andotp_export(&data, Some("wrong"))
+        .err()
+        .unwrap();
+    assert!(err.contains("Incorrect password"), "{}", err);
+}
+
+#[test]
+fn detects_export_format() {
+    for (name, password) in [
+        ("google_migration.txt", None),
+        ("aegis_plain.json", None),
+        ("aegis_encrypted.json", Some(PASSWORD)),
+        ("andotp_plain.json", None),
+        ("andotp_encrypted.json.aes", Some(PASSWORD)),
+    ] {
+        let imported = totp_import::parse_export(&fixture(name), password)
+            .unwrap_or_else(|e| panic!("{}: {}", name, e));
+        assert_matches_golden(&imported);
+    }
+
+    let imported = totp_import::parse_export(
+        b"otpauth://totp/GitHub:octocat?secret=JBSWY3DPEHPK3PXP\n",
+        None,
+    )
+    .unwrap();
+    assert_eq!(imported.accounts.len(), 1);
+}
+
+#[test]
+fn rejects_malformed_migration_uri() {
+    assert!(totp_import::parse_migration_uri("otpauth-migration://offline").is_err());
+    assert!(totp_import::parse_migration_uri("otpauth-migration://offline?data=%%%").is_err());
+    assert!(totp_import::parse_migration_uri("otpauth://totp/x?secret=JBSWY3DPEHPK3PXP").is_err());
+}
+
+#[test]
+fn entries_that_cannot_be_imported_are_skipped() {
+    let backup = br#"[
+        {"secret": "JBSWY3DPEHPK3PXP", "issuer": "GitHub", "label": "octocat", "algorithm": "MD5"},
+        {"secret": "JBSWY3DPEHPK3PXP", "issuer": "GitLab", "label": "tanuki"}
+    ]"#;
+
+    let imported = totp_import::parse_andotp_export(backup, None).unwrap();
+    assert_eq!(imported.accounts.len(), 1);
+    assert_eq!(imported.accounts[0].account.issuer, "GitLab");
+    assert_eq!(imported.skipped.len(), 1);
+    assert!(imported.skipped[0].starts_with("GitHub: "));
+}
+
+fn with_scrypt_params(n: u64, r: u32, p: u32) -> String {
+    let mut vault: serde_json::Value =
+        serde_json::from_slice(&fixture("aegis_encrypted.json")).unwrap();
+    for slot in vault["header"]["slots"].as_array_mut().unwrap() {
+        if slot["type"] == 1 {
+            slot["n"] = n.into();
+            slot["r"] = r.into();
+            slot["p"] = p.into();
+        }
+    }
+    vault.to_string()
+}
+
+#[test]
+fn rejects_unreasonable_key_derivation_parameters() {
+    for (n, r, p) in [(1000, 8, 1), (1 << 30, 8, 1), (1 << 15, 8, 1000)] {
+        let content = with_scrypt_params(n, r, p);
+        assert!(
+            totp_import::parse_aegis_export(&content, Some(PASSWORD)).is_err(),
+            "n={} r={} p={}",
+            n,
+            r,
+            p
+        );
+    }
+
+    let mut data = fixture("andotp_encrypted.json.aes");
+    data[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+    let err = totp_import::parse_andotp_export(&data, Some(PASSWORD))
+        .err()
+        .unwrap();
+    assert!(err.contains("iteration count"), "{}", err);
+}
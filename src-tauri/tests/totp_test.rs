@@ -0,0 +1,145 @@
+// Tests for TOTP/HOTP generation against the RFC test vectors and otpauth:// parsing
+
+use otpbar::totp::{self, OtpKind, TotpAlgorithm};
+
+const SHA1_KEY: &[u8] = b"12345678901234567890";
+const SHA256_KEY: &[u8] = b"12345678901234567890123456789012";
+const SHA512_KEY: &[u8] = b"1234567890123456789012345678901234567890123456789012345678901234";
+
+#[test]
+fn hotp_matches_rfc4226_vectors() {
+    let expected = [
+        "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583", "399871",
+        "520489",
+    ];
+    for (counter, code) in expected.iter().enumerate() {
+        assert_eq!(
+            totp::hotp(SHA1_KEY, counter as u64, 6, TotpAlgorithm::Sha1),
+            *code,
+            "counter {}",
+            counter
+        );
+    }
+}
+
+#[test]
+fn totp_matches_rfc6238_vectors() {
+    let vectors: &[(u64, &str, &str, &str)] = &[
+        (59, "94287082", "46119246", "90693936"),
+        (1111111109, "07081804", "68084774", "25091201"),
+        (1111111111, "14050471", "67062674", "99943326"),
+        (1234567890, "89005924", "91819424", "93441116"),
+        (2000000000, "69279037", "90698825", "38618901"),
+        (20000000000, "65353130", "77737706", "47863826"),
+    ];
+
+    for (time, sha1, sha256, sha512) in vectors {
+        assert_eq!(
+            totp::totp(SHA1_KEY, *time, 30, 8, TotpAlgorithm::Sha1),
+            *sha1
+        );
+        assert_eq!(
+            totp::totp(SHA256_KEY, *time, 30, 8, TotpAlgorithm::Sha256),
+            *sha256
+        );
+        assert_eq!(
+            totp::totp(SHA512_KEY, *time, 30, 8, TotpAlgorithm::Sha512),
+            *sha512
+        );
+    }
+}
+
+#[test]
+fn remaining_seconds_counts_down_within_period() {
+    assert_eq!(totp::remaining_seconds(60, 30), 30);
+    assert_eq!(totp::remaining_seconds(59, 30), 1);
+    assert_eq!(totp::remaining_seconds(75, 60), 45);
+}
+
+#[test]
+fn decode_secret_is_lenient_about_formatting() {
+    let expected = totp::decode_secret("JBSWY3DPEHPK3PXP").unwrap();
+    assert_eq!(
+        totp::decode_secret("jbsw y3dp ehpk 3pxp").unwrap(),
+        expected
+    );
+    assert_eq!(totp::encode_secret(&expected), "JBSWY3DPEHPK3PXP");
+    assert!(totp::decode_secret("not base32!").is_err());
+}
+
+#[test]
+fn parse_otpauth_totp_uri() {
+    let imported = totp::parse_otpauth_uri(
+        "otpauth://totp/ACME%20Co:john.doe@email.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60",
+    )
+    .unwrap();
+
+    let account = imported.account;
+    assert_eq!(account.kind, OtpKind::Totp);
+    assert_eq!(account.issuer, "ACME Co");
+    assert_eq!(account.label, "john.doe@email.com");
+    assert_eq!(account.algorithm, TotpAlgorithm::Sha256);
+    assert_eq!(account.digits, 8);
+    assert_eq!(account.period, 60);
+    assert_eq!(
+        imported.secret,
+        totp::decode_secret("HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ").unwrap()
+    );
+}
+
+#[test]
+fn parse_otpauth_uri_defaults_and_label_issuer() {
+    let imported =
+        totp::parse_otpauth_uri("otpauth://totp/GitHub:octocat?secret=JBSWY3DPEHPK3PXP").unwrap();
+
+    let account = imported.account;
+    assert_eq!(account.issuer, "GitHub");
+    assert_eq!(account.label, "octocat");
+    assert_eq!(account.algorithm, TotpAlgorithm::Sha1);
+    assert_eq!(account.digits, 6);
+    assert_eq!(account.period, 30);
+}
+
+#[test]
+fn parse_otpauth_hotp_requires_counter() {
+    let imported =
+        totp::parse_otpauth_uri("otpauth://hotp/Example:alice?secret=JBSWY3DPEHPK3PXP&counter=7")
+            .unwrap();
+    assert_eq!(imported.account.kind, OtpKind::Hotp);
+    assert_eq!(imported.account.counter, 7);
+
+    assert!(
+        totp::parse_otpauth_uri("otpauth://hotp/Example:alice?secret=JBSWY3DPEHPK3PXP").is_err()
+    );
+}
+
+#[test]
+fn parse_otpauth_rejects_invalid_uris() {
+    assert!(totp::parse_otpauth_uri("https://example.com").is_err());
+    assert!(totp::parse_otpauth_uri("otpauth://totp/Example:alice").is_err());
+    assert!(totp::parse_otpauth_uri("otpauth://steam/Example?secret=JBSWY3DPEHPK3PXP").is_err());
+    assert!(totp::parse_otpauth_uri(
+        "otpauth://totp/Example?secret=JBSWY3DPEHPK3PXP&algorithm=MD5"
+    )
+    .is_err());
+    assert!(
+        totp::parse_otpauth_uri("otpauth://totp/Example?secret=JBSWY3DPEHPK3PXP&digits=12")
+            .is_err()
+    );
+}
+
+#[test]
+fn code_entry_includes_countdown_for_totp() {
+    let mut imported = totp::parse_otpauth_uri(
+        "otpauth://totp/Example:alice?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&digits=8",
+    )
+    .unwrap();
+    imported.account.id = "abc".to_string();
+
+    let entry = totp::code_entry(&imported.account, &imported.secret, 59, 59_000);
+    assert_eq!(entry.code, "94287082");
+    assert_eq!(entry.provider, "Example");
+    assert_eq!(entry.sender, "alice");
+    assert_eq!(entry.message_id, "totp:abc");
+    assert_eq!(entry.remaining_seconds, Some(1));
+}
@@ -26,15 +26,43 @@ function App() {
     const unlisten = listen<CodeEntry[]>('codes-updated', (event) => {
       setCodes(event.payload);
     });
+    const unlistenUsed = listen<string[]>('code-used', () => {
+      loadCodes();
+    });
 
     // Disable right click for app-like feel
     document.addEventListener('contextmenu', event => event.preventDefault());
 
     return () => {
       unlisten.then(f => f());
+      unlistenUsed.then(f => f());
     };
   }, []);
 
+  // Authenticator codes roll over on their own, so refresh while any are shown
+  const hasAuthenticatorCodes = codes.some(entry => entry.remaining_seconds != null);
+
+  useEffect(() => {
+    if (!hasAuthenticatorCodes) return;
+
+    const interval = setInterval(loadCodes, 1000);
+    return () => clearInterval(interval);
+  }, [hasAuthenticatorCodes]);
+
+  // Refresh when the next email code reaches its stated expiry, so it is
+  // shown as expired
+  const nextExpiry = codes.reduce<number | null>((next, entry) => {
+    if (entry.expired || entry.expires_at == null) return next;
+    return next === null ? entry.expires_at : Math.min(next, entry.expires_at);
+  }, null);
+
+  useEffect(() => {
+    if (nextExpiry === null) return;
+
+    const timeout = setTimeout(loadCodes, Math.max(nextExpiry - Date.now(), 0) + 100);
+    return () => clearTimeout(timeout);
+  }, [nextExpiry]);
+
   const checkAuth = async () => {
     try {
       const status = await tauriApi.getAuthStatus();
@@ -1,5 +1,5 @@
 import React, { useState, useEffect, useRef } from 'react';
-import { Copy, Check, Clock } from 'lucide-react';
+import { Copy, Check, Clock, Timer, ExternalLink, ShieldAlert } from 'lucide-react';
 import { CodeEntry } from '../types/tauri';
 import { tauriApi } from '../lib/tauri';
 import { cn } from '../lib/utils';
@@ -27,6 +27,19 @@ export const CodeCard: React.FC<CodeCardProps> = ({ entry }) => {
     };
   }, []);
 
+  const isLink = entry.kind === 'link';
+  // History kept as metadata only comes back without the code
+  const isRedacted = entry.code === '';
+  const isUsed = entry.used_at != null;
+
+  const handleOpen = async () => {
+    try {
+      await tauriApi.openLink(entry.message_id);
+    } catch (error) {
+      console.error('Failed to open link:', error);
+    }
+  };
+
   const handleCopy = async () => {
     if (isStartingRef.current) {
       return;
@@ -34,7 +47,7 @@ export const CodeCard: React.FC<CodeCardProps> = ({ entry }) => {
 
     try {
       isStartingRef.current = true;
-      await tauriApi.copyCodeWithExpiry(entry.code);
+      await tauriApi.copyCodeWithExpiry(entry.code, entry.message_id);
       if (!isMountedRef.current) return;
       setCopied(true);
 
@@ -74,6 +87,8 @@ export const CodeCard: React.FC<CodeCardProps> = ({ entry }) => {
     }
   };
 
+  const handleActivate = isRedacted ? () => {} : isLink ? handleOpen : handleCopy;
+
   const timeDisplay = React.useMemo(() => {
     try {
       const date = new Date(entry.timestamp);
@@ -99,14 +114,16 @@ export const CodeCard: React.FC<CodeCardProps> = ({ entry }) => {
 
   return (
     <div
-      onClick={handleCopy}
+      onClick={handleActivate}
       role="button"
       tabIndex={0}
-      aria-label={`Copy OTP code from ${entry.provider || entry.sender}`}
+      aria-label={isLink
+        ? `Open sign-in link from ${entry.provider || entry.sender}`
+        : `Copy OTP code from ${entry.provider || entry.sender}`}
       onKeyDown={(e) => {
         if (e.key === 'Enter' || e.key === ' ') {
           e.preventDefault();
-          handleCopy();
+          handleActivate();
         }
       }}
       className={cn(
@@ -116,17 +133,27 @@ export const CodeCard: React.FC<CodeCardProps> = ({ entry }) => {
         "hover:bg-card hover:border-border/50",
         "shadow-inner-glow",
         "focus-visible:outline-none focus-visible:ring-2 focus-visible:ring-ring focus-visible:ring-offset-2 focus-visible:ring-offset-background",
-        copied && "bg-status-active/10 border-status-active/30"
+        copied && "bg-status-active/10 border-status-active/30",
+        (entry.expired || isRedacted || isUsed) && !copied && "opacity-50"
       )}
     >
       <div className="flex flex-col gap-1.5 min-w-0">
         <div className="flex items-center gap-2">
-          {isRecent && (
+          {isRecent && !entry.expired && (
             <span className="w-1.5 h-1.5 rounded-full bg-status-active glow-active shrink-0" />
           )}
           <h3 className="text-sm font-medium text-foreground/90 truncate max-w-[160px]">
             {entry.provider || entry.sender}
           </h3>
+          {entry.sender_auth === 'unverified' && (
+            <span
+              className="flex items-center text-destructive/80 shrink-0"
+              title="This sender failed email authentication checks (SPF/DKIM/DMARC). It may not be who it claims to be."
+              aria-label="Unverified sender"
+            >
+              <ShieldAlert size={12} />
+            </span>
+          )}
         </div>
 
         <div className="flex items-center gap-1.5 text-[11px] text-muted-foreground">
@@ -134,22 +161,55 @@ export const CodeCard: React.FC<CodeCardProps> = ({ entry }) => {
           <span>{timeDisplay}</span>
           <span className="text-muted-foreground/40 mx-1">|</span>
           <span className="truncate max-w-[100px] opacity-70">{entry.sender}</span>
+          {entry.expired && (
+            <>
+              <span className="text-muted-foreground/40 mx-1">|</span>
+              <span className="text-destructive/80 font-medium">Expired</span>
+            </>
+          )}
+          {isUsed && !entry.expired && (
+            <>
+              <span className="text-muted-foreground/40 mx-1">|</span>
+              <span className="font-medium">Used</span>
+            </>
+          )}
+          {entry.account && (
+            <>
+              <span className="text-muted-foreground/40 mx-1">|</span>
+              <span className="truncate max-w-[100px] opacity-50" title={entry.account}>
+                {entry.account}
+              </span>
+            </>
+          )}
         </div>
       </div>
 
       <div className="flex items-center gap-2.5 pl-3 shrink-0">
         <div className="flex flex-col items-end gap-0.5">
-          <span className={cn(
-            "font-mono text-base font-semibold tracking-widest tabular-nums transition-colors",
-            copied ? "text-status-active" : "text-foreground"
-          )}>
-            {entry.code}
-          </span>
-          {countdown !== null && (
+          {isRedacted ? (
+            <span className="text-xs text-muted-foreground" title="Codes aren't saved with the current retention setting">
+              Not saved
+            </span>
+          ) : isLink ? (
+            <span className="text-sm font-medium text-foreground">Sign-in link</span>
+          ) : (
+            <span className={cn(
+              "font-mono text-base font-semibold tracking-widest tabular-nums transition-colors",
+              copied ? "text-status-active" : "text-foreground"
+            )}>
+              {entry.display_code ?? entry.code}
+            </span>
+          )}
+          {countdown !== null ? (
             <div className="flex items-center gap-1 text-[10px] text-status-active font-medium tabular-nums">
               <Clock size={8} className="opacity-70" />
               <span>{countdown}s</span>
             </div>
+          ) : entry.remaining_seconds != null && (
+            <div className="flex items-center gap-1 text-[10px] text-muted-foreground font-medium tabular-nums">
+              <Timer size={8} className="opacity-70" />
+              <span>{entry.remaining_seconds}s</span>
+            </div>
           )}
         </div>
 
@@ -159,7 +219,9 @@ export const CodeCard: React.FC<CodeCardProps> = ({ entry }) => {
             ? "bg-status-active text-primary-foreground"
             : "bg-secondary/80 text-muted-foreground opacity-0 group-hover:opacity-100"
         )}>
-          {copied ? <Check size={12} strokeWidth={3} /> : <Copy size={12} />}
+          {isLink
+            ? <ExternalLink size={12} />
+            : copied ? <Check size={12} strokeWidth={3} /> : <Copy size={12} />}
         </div>
       </div>
     </div>
@@ -11,7 +11,10 @@ import {
 } from 'lucide-react';
 import { cn } from '../lib/utils';
 import { tauriApi } from '../lib/tauri';
-import type { PrivacyData } from '../types/tauri';
+import type { PrivacyData, RetentionPolicy } from '../types/tauri';
+
+const RETENTION_DAYS = [0, 1, 7, 30, 90];
+const HISTORY_SIZES = [10, 25, 50, 100, 200];
 
 export const PrivacyDashboard: React.FC<{
   onBack: () => void;
@@ -72,6 +75,19 @@ export const PrivacyDashboard: React.FC<{
     }
   };
 
+  const updateRetention = async (changes: Partial<RetentionPolicy>) => {
+    if (!privacyData) return;
+    try {
+      setError(null);
+      const { retention } = await tauriApi.getPreferences();
+      const policy: RetentionPolicy = { ...retention, ...changes };
+      await tauriApi.setRetentionPolicy(policy);
+      setPrivacyData(await tauriApi.getPrivacyData());
+    } catch (err) {
+      setError(err instanceof Error ? err.message : 'Failed to update retention policy');
+    }
+  };
+
   const formatTimestamp = (timestamp: number | null): string => {
     if (!timestamp) return 'Never';
     const date = new Date(timestamp);
@@ -288,6 +304,41 @@ export const PrivacyDashboard: React.FC<{
                 />
               </div>
             </div>
+            <div className="space-y-2 pt-2 border-t border-border/20">
+              <label className="flex items-center justify-between">
+                <span className="text-[11px] text-muted-foreground">Keep codes for</span>
+                <select
+                  value={privacyData.activity.historyRetention}
+                  onChange={(e) => updateRetention({ max_age_days: Number(e.target.value) || null })}
+                  className="text-xs bg-secondary/50 border border-border/40 rounded-md px-1.5 py-0.5 text-foreground"
+                >
+                  {RETENTION_DAYS.map((days) => (
+                    <option key={days} value={days}>{formatRetention(days)}</option>
+                  ))}
+                </select>
+              </label>
+              <label className="flex items-center justify-between">
+                <span className="text-[11px] text-muted-foreground">Keep at most</span>
+                <select
+                  value={privacyData.retention.maxHistorySize}
+                  onChange={(e) => updateRetention({ max_count: Number(e.target.value) })}
+                  className="text-xs bg-secondary/50 border border-border/40 rounded-md px-1.5 py-0.5 text-foreground"
+                >
+                  {HISTORY_SIZES.map((size) => (
+                    <option key={size} value={size}>{size} codes</option>
+                  ))}
+                </select>
+              </label>
+              <label className="flex items-center justify-between">
+                <span className="text-[11px] text-muted-foreground">Save codes to disk</span>
+                <input
+                  type="checkbox"
+                  checked={!privacyData.retention.metadataOnly}
+                  onChange={(e) => updateRetention({ metadata_only: !e.target.checked })}
+                  className="accent-status-accent"
+                />
+              </label>
+            </div>
             <div className="pt-2 border-t border-border/20">
               {successMessage && (
                 <div className="mb-2 px-2 py-1.5 bg-status-active/10 border border-status-active/30 rounded-lg flex items-center gap-1.5">
@@ -6,6 +6,8 @@ import { cn } from '../lib/utils';
 interface PrivacyPreferences {
   auto_copy_enabled: boolean;
   provider_auto_copy: Record<string, boolean>;
+  auto_copy_unverified: boolean;
+  used_codes: 'dim' | 'hide';
 }
 
 export const Settings: React.FC<{
@@ -47,6 +49,34 @@ export const Settings: React.FC<{
     }
   };
 
+  const handleToggleAutoCopyUnverified = async () => {
+    if (!preferences) return;
+
+    const newEnabled = !preferences.auto_copy_unverified;
+    try {
+      await tauriApi.setAutoCopyUnverified(newEnabled);
+      setPreferences({ ...preferences, auto_copy_unverified: newEnabled });
+      setActionError(null);
+    } catch (error) {
+      console.error('Failed to update unverified sender preference:', error);
+      setActionError('Failed to update setting. Please try again.');
+    }
+  };
+
+  const handleToggleHideUsed = async () => {
+    if (!preferences) return;
+
+    const display = preferences.used_codes === 'hide' ? 'dim' : 'hide';
+    try {
+      await tauriApi.setUsedCodeDisplay(display);
+      setPreferences({ ...preferences, used_codes: display });
+      setActionError(null);
+    } catch (error) {
+      console.error('Failed to update used code preference:', error);
+      setActionError('Failed to update setting. Please try again.');
+    }
+  };
+
   const handleRetry = () => {
     setError(null);
     setLoading(true);
@@ -205,6 +235,74 @@ export const Settings: React.FC<{
               </button>
             </div>
           </div>
+
+          <div className="bg-card/60 border border-border/30 rounded-lg p-4 shadow-inner-glow">
+            <div className="flex items-center justify-between">
+              <div className="flex flex-col gap-1">
+                <h3 className="text-sm font-medium text-foreground/90">Auto-Copy from Unverified Senders</h3>
+                <p className="text-xs text-muted-foreground leading-relaxed">
+                  Also auto-copy codes from emails that failed SPF, DKIM or DMARC checks
+                </p>
+              </div>
+
+              <button
+                onClick={handleToggleAutoCopyUnverified}
+                aria-label={preferences.auto_copy_unverified ? "Disable auto-copy from unverified senders" : "Enable auto-copy from unverified senders"}
+                className={cn(
+                  "flex items-center gap-2 px-3 py-1.5 rounded-md text-xs font-medium transition-all focus-visible:outline-none focus-visible:ring-2 focus-visible:ring-ring focus-visible:ring-offset-2 focus-visible:ring-offset-background",
+                  preferences.auto_copy_unverified
+                    ? "bg-status-active/20 text-status-active border border-status-active/30"
+                    : "bg-secondary/80 text-muted-foreground border border-border/30"
+                )}
+              >
+                {preferences.auto_copy_unverified ? (
+                  <>
+                    <ToggleRight size={16} />
+                    <span>On</span>
+                  </>
+                ) : (
+                  <>
+                    <ToggleLeft size={16} />
+                    <span>Off</span>
+                  </>
+                )}
+              </button>
+            </div>
+          </div>
+
+          <div className="bg-card/60 border border-border/30 rounded-lg p-4 shadow-inner-glow">
+            <div className="flex items-center justify-between">
+              <div className="flex flex-col gap-1">
+                <h3 className="text-sm font-medium text-foreground/90">Hide Used Codes</h3>
+                <p className="text-xs text-muted-foreground leading-relaxed">
+                  Remove codes from the list once copied, instead of dimming them
+                </p>
+              </div>
+
+              <button
+                onClick={handleToggleHideUsed}
+                aria-label={preferences.used_codes === 'hide' ? "Show used codes dimmed" : "Hide used codes"}
+                className={cn(
+                  "flex items-center gap-2 px-3 py-1.5 rounded-md text-xs font-medium transition-all focus-visible:outline-none focus-visible:ring-2 focus-visible:ring-ring focus-visible:ring-offset-2 focus-visible:ring-offset-background",
+                  preferences.used_codes === 'hide'
+                    ? "bg-status-active/20 text-status-active border border-status-active/30"
+                    : "bg-secondary/80 text-muted-foreground border border-border/30"
+                )}
+              >
+                {preferences.used_codes === 'hide' ? (
+                  <>
+                    <ToggleRight size={16} />
+                    <span>On</span>
+                  </>
+                ) : (
+                  <>
+                    <ToggleLeft size={16} />
+                    <span>Off</span>
+                  </>
+                )}
+              </button>
+            </div>
+          </div>
         </section>
       </main>
     </div>
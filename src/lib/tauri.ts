@@ -1,5 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
-import { CodeEntry, AuthResult, PrivacyData, ClipboardConfig, PrivacyPreferences } from "../types/tauri";
+import { CodeEntry, AuthResult, PrivacyData, ClipboardConfig, PrivacyPreferences, ImapConfig, AccountInfo, TotpAccount, ExtractionRule, RulePattern, Provider, RetentionPolicy, HistoryPage, HistoryQuery, UsedCodeDisplay, ImportSummary } from "../types/tauri";
 
 export const tauriApi = {
   getCodes: async (): Promise<CodeEntry[]> => {
@@ -10,12 +10,64 @@ export const tauriApi = {
     return invoke("get_auth_status");
   },
 
-  startAuth: async (): Promise<AuthResult> => {
-    return invoke("start_auth");
+  startAuth: async (provider?: "gmail" | "outlook"): Promise<AuthResult> => {
+    return invoke("start_auth", { provider });
   },
 
-  copyCode: async (code: string): Promise<boolean> => {
-    return invoke("copy_code", { code });
+  configureImap: async (config: ImapConfig, secret: string): Promise<AuthResult> => {
+    return invoke("configure_imap", { config, secret });
+  },
+
+  listAccounts: async (): Promise<AccountInfo[]> => {
+    return invoke("list_accounts");
+  },
+
+  removeAccount: async (provider: string, account: string | null): Promise<boolean> => {
+    return invoke("remove_account", { provider, account });
+  },
+
+  addTotpAccount: async (uri: string): Promise<TotpAccount> => {
+    return invoke("add_totp_account", { uri });
+  },
+
+  importTotpAccounts: async (data: Uint8Array, password?: string): Promise<ImportSummary> => {
+    return invoke("import_totp_accounts", { data: Array.from(data), password });
+  },
+
+  listTotpAccounts: async (): Promise<TotpAccount[]> => {
+    return invoke("list_totp_accounts");
+  },
+
+  removeTotpAccount: async (id: string): Promise<void> => {
+    return invoke("remove_totp_account", { id });
+  },
+
+  advanceHotpCounter: async (id: string): Promise<void> => {
+    return invoke("advance_hotp_counter", { id });
+  },
+
+  addExtractionRule: async (sender: string, pattern: RulePattern): Promise<ExtractionRule> => {
+    return invoke("add_extraction_rule", { sender, pattern });
+  },
+
+  listExtractionRules: async (): Promise<ExtractionRule[]> => {
+    return invoke("list_extraction_rules");
+  },
+
+  testExtractionRule: async (pattern: RulePattern, sample: string): Promise<string | null> => {
+    return invoke("test_extraction_rule", { pattern, sample });
+  },
+
+  removeExtractionRule: async (id: string): Promise<void> => {
+    return invoke("remove_extraction_rule", { id });
+  },
+
+  listProviders: async (): Promise<Provider[]> => {
+    return invoke("list_providers");
+  },
+
+  copyCode: async (code: string, messageId?: string): Promise<boolean> => {
+    return invoke("copy_code", { code, messageId });
   },
 
   logout: async (): Promise<boolean> => {
@@ -38,8 +90,15 @@ export const tauriApi = {
     return invoke("clear_history");
   },
 
-  copyCodeWithExpiry: async (code: string): Promise<boolean> => {
-    return invoke("copy_code_with_expiry", { code });
+  copyCodeWithExpiry: async (
+    code: string,
+    messageId?: string
+  ): Promise<boolean> => {
+    return invoke("copy_code_with_expiry", { code, messageId });
+  },
+
+  openLink: async (messageId: string): Promise<void> => {
+    return invoke("open_link", { messageId });
   },
 
   getClipboardConfig: async (): Promise<ClipboardConfig> => {
@@ -50,6 +109,18 @@ export const tauriApi = {
     return invoke("set_clipboard_timeout", { timeoutSeconds });
   },
 
+  getHistory: async (offset: number, limit: number): Promise<HistoryPage> => {
+    return invoke("get_history", { offset, limit });
+  },
+
+  searchHistory: async (query: string, offset: number, limit: number): Promise<HistoryPage> => {
+    return invoke("search_history", { query, offset, limit });
+  },
+
+  queryHistory: async (query: HistoryQuery): Promise<HistoryPage> => {
+    return invoke("query_history", { query });
+  },
+
   getPreferences: async (): Promise<PrivacyPreferences> => {
     return invoke("get_preferences");
   },
@@ -58,6 +129,18 @@ export const tauriApi = {
     return invoke("set_auto_copy_enabled", { enabled });
   },
 
+  setAutoCopyUnverified: async (enabled: boolean): Promise<void> => {
+    return invoke("set_auto_copy_unverified", { enabled });
+  },
+
+  setRetentionPolicy: async (policy: RetentionPolicy): Promise<void> => {
+    return invoke("set_retention_policy", { policy });
+  },
+
+  setUsedCodeDisplay: async (display: UsedCodeDisplay): Promise<void> => {
+    return invoke("set_used_code_display", { display });
+  },
+
   setProviderAutoCopy: async (provider: string, enabled: boolean): Promise<void> => {
     return invoke("set_provider_auto_copy", { provider, enabled });
   }
@@ -4,6 +4,64 @@ export interface CodeEntry {
   provider: string;
   timestamp: number;
   message_id: string;
+  account?: string | null;
+  remaining_seconds?: number | null;
+  display_code?: string | null;
+  // 'link' entries hold a sign-in URL in `code`
+  kind?: EntryKind;
+  // When the code stops working (ms), if the email said
+  expires_at?: number | null;
+  expired?: boolean;
+  sender_auth?: SenderAuth;
+  // When the code was copied from the list (ms)
+  used_at?: number | null;
+}
+
+// Result of the SPF/DKIM/DMARC checks on the email's sender
+export type SenderAuth = 'unknown' | 'verified' | 'unverified';
+
+export type EntryKind = 'code' | 'link';
+
+export type OtpKind = 'totp' | 'hotp';
+
+export interface TotpAccount {
+  id: string;
+  kind: OtpKind;
+  issuer: string;
+  label: string;
+  algorithm: 'SHA1' | 'SHA256' | 'SHA512';
+  digits: number;
+  period: number;
+  counter: number;
+}
+
+// Accounts an import added, and "issuer: reason" for each entry it skipped
+export interface ImportSummary {
+  accounts: TotpAccount[];
+  skipped: string[];
+}
+
+export type RulePattern =
+  | { type: 'regex'; pattern: string }
+  | { type: 'after_phrase'; phrase: string };
+
+export type ExtractionRule = RulePattern & {
+  id: string;
+  sender: string;
+};
+
+export interface Provider {
+  name: string;
+  domains: string[];
+  aliases: string[];
+  icon?: string;
+  color?: string;
+}
+
+export interface AccountInfo {
+  provider: string;
+  account: string | null;
+  authenticated: boolean;
 }
 
 export interface AuthResult {
@@ -11,15 +69,57 @@ export interface AuthResult {
   error?: string;
 }
 
+export type ImapAuthMethod = 'login' | 'xoauth2';
+
+export interface ImapConfig {
+  host: string;
+  port: number;
+  username: string;
+  use_tls: boolean;
+  auth_method: ImapAuthMethod;
+  mailbox: string;
+}
+
 export interface ClipboardConfig {
   timeout_seconds: number;
 }
 
+export interface RetentionPolicy {
+  max_age_days: number | null;
+  max_count: number;
+  recent_count: number;
+  metadata_only: boolean;
+}
+
+export interface HistoryQuery {
+  provider?: string;
+  sender?: string;
+  account?: string;
+  since?: number;
+  until?: number;
+  kind?: EntryKind;
+  text?: string;
+  sort?: 'newest_first' | 'oldest_first' | 'provider';
+  offset?: number;
+  limit?: number;
+}
+
+export interface HistoryPage {
+  entries: CodeEntry[];
+  total: number;
+}
+
 export interface PrivacyPreferences {
   auto_copy_enabled: boolean;
   provider_auto_copy: Record<string, boolean>;
+  auto_copy_unverified: boolean;
+  retention: RetentionPolicy;
+  used_codes: UsedCodeDisplay;
 }
 
+// How codes already copied from the list are shown
+export type UsedCodeDisplay = 'dim' | 'hide';
+
 export type Codes = CodeEntry[];
 
 export interface PrivacyData {
@@ -41,5 +141,6 @@ export interface PrivacyData {
   retention: {
     maxHistorySize: number;
     currentSize: number;
+    metadataOnly: boolean;
   };
 }